@@ -1,10 +1,22 @@
 use std::collections::HashSet;
 
-use yumboard_shared::{Color, Point, Stroke, StrokeId, TransformOp};
+use yumboard_shared::image::{image_points, AssetInfo};
+use yumboard_shared::layer::{layer_index, layer_visible};
+use yumboard_shared::shape::{outline_points, shape_points};
+use yumboard_shared::text::{scale_text, DEFAULT_WRAP_EMS};
+use yumboard_shared::{
+    Blend, ClientMessage, Color, ImageRef, LayerId, Page, PageId, Point, Shape, ShapeKind, Stroke,
+    StrokeId, TextBox, TransformOp,
+};
 
-use crate::geometry::{home_zoom_pan, normalize_point, stroke_hit};
+use crate::geometry::{cut_stroke, home_zoom_pan, normalize_point, stroke_hit};
+use crate::layers::layer_editable;
+use crate::pages::replay_page_edits;
+use crate::recognize::recognize_shape;
 use crate::render::{draw_dot, draw_segment, redraw};
-use crate::state::{EraseMode, Mode, SelectMode, State};
+use crate::smoothing::simplify_stroke_points;
+use crate::state::{EraseMode, Mode, SelectMode, SplitEdit, State};
+use crate::util::make_id;
 
 pub fn parse_color(input: &str) -> Color {
     Color::from_hex(input).unwrap_or(Color::DEFAULT)
@@ -15,27 +27,129 @@ pub fn sanitize_size(size: f32) -> f32 {
     size.max(1.0).min(60.0)
 }
 
-pub fn start_stroke(
-    state: &mut State,
-    ctx: &web_sys::CanvasRenderingContext2d,
+/// Shapes are filled with a translucent tint of their outline color.
+pub fn shape_fill(color: Color) -> Color {
+    Color { a: 0x40, ..color }
+}
+
+/// A freehand stroke of a single point, to be continued with `move_stroke`.
+pub fn new_freehand(
     id: StrokeId,
     color: Color,
     size: f32,
     point: Point,
-) {
-    let point = match normalize_point(point) {
-        Some(point) => point,
-        None => return,
-    };
-    let size = sanitize_size(size);
-    let stroke = Stroke {
-        id: id.clone(),
-        color: color.clone(),
+    blend: Blend,
+    layer: LayerId,
+) -> Stroke {
+    Stroke {
+        id,
+        color,
         size,
         points: vec![point],
+        shape: None,
+        text: None,
+        image: None,
+        blend,
+        layer,
+        author: None,
+    }
+}
+
+/// A zero-sized shape of `kind` at `point`, to be dragged out with `shape_points`.
+pub fn new_shape(
+    kind: ShapeKind,
+    color: Color,
+    size: f32,
+    fill: bool,
+    point: Point,
+    layer: LayerId,
+) -> Stroke {
+    let point = Point {
+        pressure: None,
+        ..point
     };
-    state.strokes.push(stroke);
-    state.active_ids.insert(id);
+    Stroke {
+        id: make_id(),
+        color,
+        size: sanitize_size(size),
+        points: shape_points(kind, point, point),
+        shape: Some(Shape {
+            kind,
+            fill: (fill && kind.is_closed()).then(|| shape_fill(color)),
+        }),
+        text: None,
+        image: None,
+        blend: Blend::Normal,
+        layer,
+        author: None,
+    }
+}
+
+/// Font size of new text boxes for the pen size slider value.
+pub fn text_font_size(size: f32) -> f32 {
+    12.0 + sanitize_size(size) * 4.0
+}
+
+/// An empty text box with its top-left corner at `point`.
+pub fn new_text(color: Color, size: f32, point: Point, layer: LayerId) -> Stroke {
+    let font_size = text_font_size(size);
+    Stroke {
+        id: make_id(),
+        color,
+        size: sanitize_size(size),
+        points: vec![Point {
+            pressure: None,
+            ..point
+        }],
+        shape: None,
+        text: Some(TextBox {
+            content: String::new(),
+            font_size,
+            wrap_width: font_size * DEFAULT_WRAP_EMS,
+        }),
+        image: None,
+        blend: Blend::Normal,
+        layer,
+        author: None,
+    }
+}
+
+/// An image of the uploaded `asset`, `width` board units wide and centered on `center`.
+pub fn new_image(asset: AssetInfo, center: Point, width: f32, layer: LayerId) -> Stroke {
+    let height = width * asset.height as f32 / asset.width.max(1) as f32;
+    let origin = Point {
+        x: center.x - width / 2.0,
+        y: center.y - height / 2.0,
+        pressure: None,
+    };
+    Stroke {
+        id: make_id(),
+        color: Color::DEFAULT,
+        size: 1.0,
+        points: image_points(origin, width, height),
+        shape: None,
+        text: None,
+        image: Some(ImageRef {
+            hash: asset.hash,
+            width: asset.width,
+            height: asset.height,
+        }),
+        blend: Blend::Normal,
+        layer,
+        author: None,
+    }
+}
+
+/// Puts a `new_freehand` stroke on the board and draws its first point.
+pub fn start_stroke(state: &mut State, ctx: &web_sys::CanvasRenderingContext2d, stroke: Stroke) {
+    let (id, color, size) = (stroke.id, stroke.color, sanitize_size(stroke.size));
+    let Some(point) = start_stroke_local(state, stroke) else {
+        return;
+    };
+    if needs_repaint(state, &id) {
+        redraw(ctx, state);
+        return;
+    }
     draw_dot(
         ctx,
         state.zoom,
@@ -47,38 +161,39 @@ pub fn start_stroke(
     );
 }
 
+/// Like `start_stroke` without drawing, for strokes on a page in the background. Returns the
+/// stroke's first point, or `None` if it has no usable one.
+pub fn start_stroke_local(state: &mut State, stroke: Stroke) -> Option<Point> {
+    let point = stroke.points.first().copied().and_then(normalize_point)?;
+    let stroke = Stroke {
+        size: sanitize_size(stroke.size),
+        points: vec![point],
+        ..stroke
+    };
+    state.active_ids.insert(stroke.id);
+    state.strokes.push(stroke);
+    Some(point)
+}
+
 pub fn move_stroke(
     state: &mut State,
     ctx: &web_sys::CanvasRenderingContext2d,
     id: &StrokeId,
     point: Point,
 ) -> bool {
-    let point = match normalize_point(point) {
-        Some(point) => point,
-        None => return false,
-    };
-    if !state.active_ids.contains(id) {
+    let Some((from, to)) = move_stroke_local(state, id, point) else {
         return false;
+    };
+    if needs_repaint(state, id) {
+        return true;
     }
-    let mut draw_action = None;
-    if let Some(stroke) = state
+    let draw_action = state
         .strokes
-        .iter_mut()
+        .iter()
         .rev()
         .find(|stroke| &stroke.id == id)
-    {
-        if let Some(last) = stroke.points.last().copied() {
-            if last == point {
-                return false;
-            }
-            stroke.points.push(point);
-            draw_action = Some((last, point, stroke.color.clone(), stroke.size));
-        } else {
-            stroke.points.push(point);
-            draw_action = Some((point, point, stroke.color.clone(), stroke.size));
-        }
-    }
-    if let Some((from, to, color, size)) = draw_action {
+        .map(|stroke| (stroke.color, stroke.size));
+    if let Some((color, size)) = draw_action {
         if from == to {
             draw_dot(ctx, state.zoom, state.pan_x, state.pan_y, to, color, size);
         } else {
@@ -98,24 +213,125 @@ pub fn move_stroke(
     false
 }
 
+/// Like `move_stroke` without drawing, for strokes on a page in the background. Returns the
+/// segment the point adds (from the previous last point), or `None` if nothing was added.
+pub fn move_stroke_local(state: &mut State, id: &StrokeId, point: Point) -> Option<(Point, Point)> {
+    let point = normalize_point(point)?;
+    if !state.active_ids.contains(id) {
+        return None;
+    }
+    let stroke = state
+        .strokes
+        .iter_mut()
+        .rev()
+        .find(|stroke| &stroke.id == id)?;
+    let from = match stroke.points.last().copied() {
+        Some(last) if last == point => return None,
+        Some(last) => last,
+        None => point,
+    };
+    stroke.points.push(point);
+    Some((from, point))
+}
+
+/// Whether new points of `id` need a full redraw rather than being drawn on top: highlighter
+/// ink stays one path under normal ink, ink on a lower layer stays under the layers above, and
+/// ink on a hidden layer is not shown at all.
+pub fn needs_repaint(state: &State, id: &StrokeId) -> bool {
+    let Some(stroke) = state.strokes.iter().rev().find(|stroke| &stroke.id == id) else {
+        return false;
+    };
+    if stroke.blend == Blend::Highlight || !layer_visible(&state.layers, stroke.layer) {
+        return true;
+    }
+    let index = layer_index(&state.layers, stroke.layer);
+    state
+        .strokes
+        .iter()
+        .any(|other| layer_index(&state.layers, other.layer) > index)
+}
+
 pub fn end_stroke(state: &mut State, id: &StrokeId) {
     state.active_ids.remove(id);
 }
 
-pub fn clear_board(state: &mut State, ctx: &web_sys::CanvasRenderingContext2d) {
-    state.strokes.clear();
-    state.active_ids.clear();
-    if let Mode::Select(select) = &mut state.mode {
-        select.selected_ids.clear();
-        select.mode = SelectMode::Idle;
+/// Replaces a finished local stroke with its simplified control points and returns them for
+/// `stroke:end`, or `None` when smoothing is off or changes nothing.
+pub fn fit_stroke(
+    state: &mut State,
+    ctx: &web_sys::CanvasRenderingContext2d,
+    id: &StrokeId,
+    last_raw: Option<Point>,
+) -> Option<Vec<Point>> {
+    let stroke = state
+        .strokes
+        .iter_mut()
+        .rev()
+        .find(|stroke| &stroke.id == id)?;
+    let mut points = stroke.points.clone();
+    if let Some(raw) = last_raw.and_then(normalize_point) {
+        if points
+            .last()
+            .is_some_and(|last| last.x != raw.x || last.y != raw.y)
+        {
+            points.push(raw);
+        }
     }
+    let fitted = simplify_stroke_points(&points, state.smoothing, state.zoom)?;
+    stroke.points = fitted.clone();
     redraw(ctx, state);
+    Some(fitted)
+}
+
+/// Replaces a just-finished stroke with the shape it was recognized as, if any.
+pub fn snap_stroke(
+    state: &mut State,
+    ctx: &web_sys::CanvasRenderingContext2d,
+    id: &StrokeId,
+) -> Option<Stroke> {
+    let stroke = state
+        .strokes
+        .iter_mut()
+        .rev()
+        .find(|stroke| &stroke.id == id)?;
+    let snapped = recognize_shape(stroke, state.zoom)?;
+    *stroke = snapped.clone();
+    redraw(ctx, state);
+    Some(snapped)
+}
+
+/// Clears the page in `state.strokes`, keeping strokes on locked layers as the server does. The
+/// caller redraws.
+pub fn clear_board(state: &mut State) {
+    let (kept, cleared): (Vec<_>, Vec<_>) = std::mem::take(&mut state.strokes)
+        .into_iter()
+        .partition(|stroke| {
+            state
+                .layers
+                .iter()
+                .any(|layer| layer.id == stroke.layer && layer.locked)
+        });
+    state.strokes = kept;
+    // Only forgets what was on this page, since it may be a page in the background.
+    let cleared = cleared
+        .into_iter()
+        .map(|stroke| stroke.id)
+        .collect::<HashSet<_>>();
+    state.active_ids.retain(|id| !cleared.contains(id));
+    state.unconfirmed_ids.retain(|id| !cleared.contains(id));
+    if let Mode::Select(select) = &mut state.mode {
+        select.selected_ids.retain(|id| !cleared.contains(id));
+        if select.selected_ids.is_empty() {
+            select.mode = SelectMode::Idle;
+        }
+    }
 }
 
 pub fn remove_stroke(state: &mut State, id: &StrokeId) {
     if let Some(index) = state.strokes.iter().position(|stroke| &stroke.id == id) {
         state.strokes.remove(index);
         state.active_ids.remove(id);
+        state.unconfirmed_ids.remove(id);
     }
 }
 
@@ -125,18 +341,35 @@ pub fn replace_stroke_local(state: &mut State, stroke: Stroke) {
     }
 }
 
-pub fn restore_stroke(
-    state: &mut State,
-    ctx: &web_sys::CanvasRenderingContext2d,
-    mut stroke: Stroke,
-) {
+pub fn update_shape_local(state: &mut State, id: &StrokeId, color: Color, size: f32, shape: Shape) {
+    if let Some(stroke) = state
+        .strokes
+        .iter_mut()
+        .find(|stroke| &stroke.id == id && stroke.shape.is_some())
+    {
+        stroke.color = color;
+        stroke.size = size;
+        stroke.shape = Some(shape);
+    }
+}
+
+pub fn update_text_local(state: &mut State, id: &StrokeId, text: TextBox) {
+    if let Some(stroke) = state
+        .strokes
+        .iter_mut()
+        .find(|stroke| &stroke.id == id && stroke.text.is_some())
+    {
+        stroke.text = Some(text);
+    }
+}
+
+pub fn restore_stroke(state: &mut State, mut stroke: Stroke) {
     stroke.points = stroke
         .points
         .into_iter()
         .filter_map(normalize_point)
         .collect();
     state.strokes.push(stroke);
-    redraw(ctx, state);
 }
 
 pub fn erase_hits_at_point(
@@ -156,13 +389,14 @@ pub fn erase_hits_at_point(
     while index > 0 {
         index -= 1;
         let stroke = &state.strokes[index];
-        if hits.contains(&stroke.id) {
+        if hits.contains(&stroke.id) || !layer_editable(&state.layers, stroke.layer) {
             continue;
         }
         if stroke_hit(stroke, px, py, state.zoom, state.pan_x, state.pan_y) {
             let id = stroke.id.clone();
             state.strokes.remove(index);
             state.active_ids.remove(&id);
+            state.unconfirmed_ids.remove(&id);
             hits.insert(id.clone());
             removed.push(id);
         }
@@ -175,30 +409,179 @@ pub fn erase_hits_at_point(
     removed
 }
 
-pub fn adopt_strokes(
+/// Radius of the precise eraser in screen pixels.
+const CUT_RADIUS: f64 = 8.0;
+
+/// Cuts freehand ink along the eraser path from the previous sample to `point`. Other
+/// elements hit on the way are removed whole; their ids are returned for `erase`.
+pub fn cut_strokes_at_point(
     state: &mut State,
     ctx: &web_sys::CanvasRenderingContext2d,
-    strokes: Vec<Stroke>,
-    reset_view: bool,
-) {
-    let mut sanitized = Vec::with_capacity(strokes.len());
-    for mut stroke in strokes {
+    point: Point,
+) -> Vec<StrokeId> {
+    let (last, splits) = match &mut state.mode {
+        Mode::Erase(EraseMode::Cutting { last, splits }) => (last, splits),
+        _ => return Vec::new(),
+    };
+    let radius = CUT_RADIUS / state.zoom;
+    let from = std::mem::replace(last, point);
+    let distance = ((point.x - from.x) as f64).hypot((point.y - from.y) as f64);
+    let steps = (distance / (radius / 2.0)).ceil().max(1.0) as usize;
+    let mut removed = Vec::new();
+    let mut changed = false;
+
+    for step in 1..=steps {
+        let t = step as f32 / steps as f32;
+        let center = Point {
+            x: from.x + (point.x - from.x) * t,
+            y: from.y + (point.y - from.y) * t,
+            pressure: None,
+        };
+        let px = center.x as f64 * state.zoom + state.pan_x;
+        let py = center.y as f64 * state.zoom + state.pan_y;
+        let mut index = state.strokes.len();
+        while index > 0 {
+            index -= 1;
+            let stroke = &state.strokes[index];
+            if state.active_ids.contains(&stroke.id)
+                || state.unconfirmed_ids.contains(&stroke.id)
+                || !layer_editable(&state.layers, stroke.layer)
+            {
+                continue;
+            }
+            if stroke.shape.is_some() || stroke.text.is_some() || stroke.image.is_some() {
+                if stroke_hit(stroke, px, py, state.zoom, state.pan_x, state.pan_y) {
+                    removed.push(state.strokes.remove(index).id);
+                    changed = true;
+                }
+                continue;
+            }
+            let Some(pieces) = cut_stroke(stroke, center, radius) else {
+                continue;
+            };
+            let stroke = state.strokes.remove(index);
+            let fragments = pieces
+                .into_iter()
+                .map(|points| Stroke {
+                    id: make_id(),
+                    points,
+                    ..stroke.clone()
+                })
+                .collect::<Vec<_>>();
+            let ids = fragments.iter().map(|fragment| fragment.id);
+            // Pieces of an earlier piece stay in the split of the original stroke.
+            match splits.iter_mut().find_map(|edit| {
+                let position = edit.fragments.iter().position(|id| id == &stroke.id)?;
+                Some((edit, position))
+            }) {
+                Some((edit, position)) => {
+                    edit.fragments.splice(position..=position, ids);
+                }
+                None => splits.push(SplitEdit {
+                    fragments: ids.collect(),
+                    before: stroke,
+                }),
+            }
+            state.strokes.splice(index..index, fragments);
+            changed = true;
+        }
+    }
+
+    if changed {
+        redraw(ctx, state);
+    }
+    removed
+}
+
+/// The `stroke:split` messages for the strokes cut during a precise erase.
+pub fn finish_cuts(state: &State, splits: Vec<SplitEdit>) -> Vec<ClientMessage> {
+    splits
+        .into_iter()
+        .map(|edit| ClientMessage::StrokeSplit {
+            id: edit.before.id,
+            fragments: edit
+                .fragments
+                .iter()
+                .filter_map(|id| state.strokes.iter().find(|stroke| &stroke.id == id))
+                .cloned()
+                .collect(),
+        })
+        .collect()
+}
+
+pub fn splice_strokes_local(state: &mut State, remove: &[StrokeId], insert: Vec<Stroke>) {
+    let mut index = None;
+    for id in remove {
+        if let Some(position) = state.strokes.iter().position(|stroke| &stroke.id == id) {
+            state.strokes.remove(position);
+            state.active_ids.remove(id);
+            state.unconfirmed_ids.remove(id);
+            index = Some(index.map_or(position, |index: usize| index.min(position)));
+        }
+    }
+    let index = index.unwrap_or(state.strokes.len());
+    let insert = insert.into_iter().map(|mut stroke| {
         stroke.points = stroke
             .points
             .into_iter()
             .filter_map(normalize_point)
             .collect();
-        sanitized.push(stroke);
+        stroke
+    });
+    state.strokes.splice(index..index, insert);
+}
+
+fn normalize_strokes(strokes: Vec<Stroke>) -> Vec<Stroke> {
+    strokes
+        .into_iter()
+        .map(|mut stroke| {
+            stroke.points = stroke
+                .points
+                .into_iter()
+                .filter_map(normalize_point)
+                .collect();
+            stroke
+        })
+        .collect()
+}
+
+/// Makes `pages` the whole board, staying on the current page if it still exists.
+fn set_pages(state: &mut State, mut pages: Vec<Page>) {
+    if pages.is_empty() {
+        pages.push(Page::new(PageId::INITIAL));
+    }
+    if !pages.iter().any(|page| page.id == state.page) {
+        state.page = pages[0].id;
+    }
+    for page in &mut pages {
+        let strokes = std::mem::take(&mut page.strokes);
+        page.strokes = normalize_strokes(strokes);
     }
-    state.strokes = sanitized;
+    let shown = pages
+        .iter_mut()
+        .find(|page| page.id == state.page)
+        .map(|page| std::mem::take(&mut page.strokes))
+        .unwrap_or_default();
+    state.strokes = shown;
+    state.pages = pages;
+}
+
+pub fn adopt_pages(
+    state: &mut State,
+    ctx: &web_sys::CanvasRenderingContext2d,
+    pages: Vec<Page>,
+    reset_view: bool,
+) {
+    set_pages(state, pages);
     state.active_ids.clear();
+    state.unconfirmed_ids.clear();
     if let Mode::Select(select) = &mut state.mode {
         select.selected_ids.clear();
         select.mode = SelectMode::Idle;
     }
 
     if reset_view {
-        let (zoom, pan_x, pan_y) = home_zoom_pan(&state);
+        let (zoom, pan_x, pan_y) = home_zoom_pan(state);
         state.zoom = zoom;
         state.pan_x = pan_x;
         state.pan_y = pan_y;
@@ -206,6 +589,58 @@ pub fn adopt_strokes(
     redraw(ctx, state);
 }
 
+/// Adopts a server snapshot after (re)connecting while keeping local strokes the server
+/// never received, and the page edits still queued in `page_edits`. Returns those strokes,
+/// with their page and whether they are finished, so the caller can queue them for resending.
+/// Strokes on pages removed in the meantime are dropped.
+pub fn reconcile_pages(
+    state: &mut State,
+    ctx: &web_sys::CanvasRenderingContext2d,
+    mut pages: Vec<Page>,
+    page_edits: &[ClientMessage],
+) -> Vec<(PageId, Stroke, bool)> {
+    replay_page_edits(&mut pages, page_edits);
+    let server_ids: HashSet<StrokeId> = pages
+        .iter()
+        .flat_map(|page| &page.strokes)
+        .map(|stroke| stroke.id)
+        .collect();
+    state.unconfirmed_ids.retain(|id| !server_ids.contains(id));
+    let mut resend = Vec::new();
+    for local in state.pages_snapshot() {
+        let Some(page) = pages.iter_mut().find(|page| page.id == local.id) else {
+            continue;
+        };
+        for stroke in local.strokes {
+            if !state.unconfirmed_ids.contains(&stroke.id) {
+                continue;
+            }
+            state.pending_points.remove(&stroke.id);
+            resend.push((
+                page.id,
+                stroke.clone(),
+                !state.active_ids.contains(&stroke.id),
+            ));
+            page.strokes.push(stroke);
+        }
+    }
+
+    let merged_ids: HashSet<StrokeId> = pages
+        .iter()
+        .flat_map(|page| &page.strokes)
+        .map(|stroke| stroke.id)
+        .collect();
+    set_pages(state, pages);
+    state.active_ids.retain(|id| merged_ids.contains(id));
+    state.unconfirmed_ids.retain(|id| merged_ids.contains(id));
+    if let Mode::Select(select) = &mut state.mode {
+        select.selected_ids.retain(|id| merged_ids.contains(id));
+        select.mode = SelectMode::Idle;
+    }
+    redraw(ctx, state);
+    resend
+}
+
 pub fn apply_transformed_strokes(
     state: &mut State,
     ctx: &web_sys::CanvasRenderingContext2d,
@@ -217,12 +652,7 @@ pub fn apply_transformed_strokes(
     redraw(ctx, state);
 }
 
-pub fn apply_transform_operation(
-    state: &mut State,
-    ctx: &web_sys::CanvasRenderingContext2d,
-    ids: &[StrokeId],
-    op: &TransformOp,
-) {
+pub fn apply_transform_operation(state: &mut State, ids: &[StrokeId], op: &TransformOp) {
     if ids.is_empty() {
         return;
     }
@@ -258,6 +688,9 @@ pub fn apply_transform_operation(
                     point.x = (cx + dx * sx) as f32;
                     point.y = (cy + dy * sy) as f32;
                 }
+                if let Some(text) = &mut stroke.text {
+                    scale_text(text, sx, sy);
+                }
             }
         }
         TransformOp::Rotate { center, delta } => {
@@ -281,7 +714,6 @@ pub fn apply_transform_operation(
             }
         }
     }
-    redraw(ctx, state);
 }
 
 pub fn finalize_lasso_selection(state: &mut State) {
@@ -300,8 +732,12 @@ pub fn finalize_lasso_selection(state: &mut State) {
     let polygon = points.clone();
     let mut selected = Vec::new();
     for stroke in &state.strokes {
+        if !layer_editable(&state.layers, stroke.layer) {
+            continue;
+        }
         let mut inside = false;
-        for point in &stroke.points {
+        let outline = outline_points(stroke);
+        for point in outline.as_deref().unwrap_or(&stroke.points) {
             if crate::geometry::point_in_polygon(*point, &polygon) {
                 inside = true;
                 break;
@@ -6,33 +6,66 @@ use js_sys::Uint8Array;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{
-    Blob, Event, FileReader, HtmlAnchorElement, KeyboardEvent, PointerEvent, ProgressEvent, Url,
+    Blob, ClipboardEvent, DragEvent, Event, File, FileReader, HtmlAnchorElement, KeyboardEvent,
+    PointerEvent, ProgressEvent, Url,
 };
 
+use yumboard_shared::highlight::{HIGHLIGHT_ALPHA, HIGHLIGHT_SIZE_SCALE};
+use yumboard_shared::order::{arrange, reordered};
+use yumboard_shared::pdf::MAX_PDF_PAGES;
+use yumboard_shared::shape::shape_points;
+use yumboard_shared::text::sanitize_text;
 use yumboard_shared::{
-    encode_session_file, ClientMessage, ServerMessage, SessionFileData, Stroke, TransformOp,
+    encode_session_file, sanitize_display_name, Blend, ClientMessage, Color, Layer, LayerId, Page,
+    PageId, Point, ServerMessage, SessionFileData, ShapeKind, Stroke, TextBox, TransformOp,
+    MAX_LASER_POINTS, MAX_LAYERS, MAX_PAGES, MAX_STROKES,
 };
 
 use crate::actions::{
-    adopt_strokes, apply_transform_operation, apply_transformed_strokes, clear_board, end_stroke,
-    erase_hits_at_point, finalize_lasso_selection, move_stroke, parse_color, remove_stroke,
-    replace_stroke_local, restore_stroke, sanitize_size, start_stroke,
+    adopt_pages, apply_transform_operation, apply_transformed_strokes, clear_board,
+    cut_strokes_at_point, end_stroke, erase_hits_at_point, finalize_lasso_selection, finish_cuts,
+    fit_stroke, move_stroke, move_stroke_local, needs_repaint, new_freehand, new_image, new_shape,
+    new_text, parse_color, reconcile_pages, remove_stroke, replace_stroke_local, restore_stroke,
+    sanitize_size, shape_fill, snap_stroke, splice_strokes_local, start_stroke, start_stroke_local,
+    update_shape_local, update_text_local,
+};
+use crate::clipboard::{
+    fragment_text, insert_copies, parse_fragment, place_copies, stack_pages, DUPLICATE_OFFSET,
+};
+use crate::dom::{
+    coalesced_pointer_events, event_to_point, is_touch_event, resize_canvas, resize_overlay, Ui,
 };
-use crate::dom::{coalesced_pointer_events, event_to_point, is_touch_event, resize_canvas, Ui};
 use crate::geometry;
 use crate::geometry::{
     angle_between, apply_rotation, apply_scale_xy, apply_translation, clamp_scale,
-    selected_strokes, selection_center, selection_hit_test,
+    selected_strokes, selection_center, selection_hit_test, stroke_hit, view_center,
+};
+use crate::identity::{load_client_identity, load_display_name, store_display_name};
+use crate::images::{image_file, load_asset_image, upload_image};
+use crate::laser::{add_laser_points, draw_lasers};
+use crate::layers::{
+    active_index, add_layer_local, layer_action_from_event, layer_editable, move_layer_local,
+    remove_layer_local, render_layers, set_layers, update_layer_local, LayerAction,
+};
+use crate::net::share_url;
+use crate::pages::{
+    add_page_local, current_index, message_page, move_page_local, page_index_from_event,
+    remove_page_local, render_pages, show_page, swap_page_strokes,
 };
 use crate::palette::{palette_action_from_event, render_palette, PaletteAction};
-use crate::persistence::{build_pdf_html, open_print_window, parse_load_payload_bytes};
+use crate::persistence::{
+    build_pdf, build_svg, download_bytes, parse_load_payload_bytes, ExportRegion,
+};
+use crate::recognize::{HOLD_TOLERANCE, HOLD_TO_SNAP_MS};
 use crate::render::redraw;
+use crate::smoothing::{load_smoothing, store_smoothing, streamline};
 use crate::state::{
-    DrawMode, DrawPointerState, DrawState, EraseMode, InputActivity, LoadingState, Mode, PanMode,
-    PinchState, ScaleAxis, SelectMode, SelectState, SelectionHit, State, DEFAULT_PALETTE,
+    DrawMode, DrawPointerState, DrawState, EraseMode, InputActivity, LaserMode, LoadingState, Mode,
+    PanMode, PeerPresence, PinchState, ScaleAxis, SelectMode, SelectState, SelectionHit, ShapeDrag,
+    ShapeState, State, TextState, DEFAULT_PALETTE,
 };
-use crate::util::make_id;
-use crate::ws::{connect_ws, WsEvent};
+use crate::util::{make_id, make_layer_id, make_page_id, peer_label};
+use crate::ws::{connect_ws, WsEvent, WsSender};
 
 fn schedule_flush(
     window: &web_sys::Window,
@@ -62,6 +95,33 @@ fn schedule_flush(
     let _ = window.request_animation_frame(cb.unchecked_ref());
 }
 
+fn schedule_cursor_flush(
+    window: &web_sys::Window,
+    ws_sender: &Rc<crate::ws::WsSender>,
+    state: &Rc<RefCell<State>>,
+) {
+    {
+        let mut state = state.borrow_mut();
+        if state.cursor_flush_scheduled {
+            return;
+        }
+        state.cursor_flush_scheduled = true;
+    }
+    let state = state.clone();
+    let sender = ws_sender.clone();
+    let cb = Closure::once_into_js(move |_: f64| {
+        let pending = {
+            let mut state = state.borrow_mut();
+            state.cursor_flush_scheduled = false;
+            state.pending_cursor.take()
+        };
+        if let Some((point, color)) = pending {
+            sender.send_ephemeral(&ClientMessage::CursorMove { point, color });
+        }
+    });
+    let _ = window.request_animation_frame(cb.unchecked_ref());
+}
+
 fn schedule_redraw(window: &web_sys::Window, ui: &Rc<Ui>, state: &Rc<RefCell<State>>) {
     {
         let mut state = state.borrow_mut();
@@ -70,24 +130,360 @@ fn schedule_redraw(window: &web_sys::Window, ui: &Rc<Ui>, state: &Rc<RefCell<Sta
         }
         state.redraw_scheduled = true;
     }
-    let state = state.clone();
+    let state_rc = state.clone();
     let ui = ui.clone();
+    let window_cb = window.clone();
     let cb = Closure::once_into_js(move |_: f64| {
-        let mut state = state.borrow_mut();
+        let mut state = state_rc.borrow_mut();
         state.redraw_scheduled = false;
+        load_missing_images(&window_cb, &ui, &state_rc, &mut state);
         redraw(&ui.ctx, &mut state);
+        ui.sync_text_editor(&state);
+    });
+    let _ = window.request_animation_frame(cb.unchecked_ref());
+}
+
+/// Keeps repainting the laser overlay every frame until all trails have faded.
+fn schedule_laser_frame(window: &web_sys::Window, ui: &Rc<Ui>, state: &Rc<RefCell<State>>) {
+    {
+        let mut state = state.borrow_mut();
+        if state.laser_frame_scheduled {
+            return;
+        }
+        state.laser_frame_scheduled = true;
+    }
+    let state_rc = state.clone();
+    let ui = ui.clone();
+    let window_cb = window.clone();
+    let cb = Closure::once_into_js(move |_: f64| {
+        let alive = {
+            let mut state = state_rc.borrow_mut();
+            state.laser_frame_scheduled = false;
+            draw_lasers(&ui.overlay_ctx, &mut state, js_sys::Date::now())
+        };
+        if alive {
+            schedule_laser_frame(&window_cb, &ui, &state_rc);
+        }
+    });
+    let _ = window.request_animation_frame(cb.unchecked_ref());
+}
+
+fn schedule_laser_flush(
+    window: &web_sys::Window,
+    ws_sender: &Rc<crate::ws::WsSender>,
+    state: &Rc<RefCell<State>>,
+) {
+    {
+        let mut state = state.borrow_mut();
+        if state.laser_flush_scheduled {
+            return;
+        }
+        state.laser_flush_scheduled = true;
+    }
+    let state = state.clone();
+    let sender = ws_sender.clone();
+    let cb = Closure::once_into_js(move |_: f64| {
+        let pending = {
+            let mut state = state.borrow_mut();
+            state.laser_flush_scheduled = false;
+            state.pending_laser.take()
+        };
+        let Some((points, mut start)) = pending else {
+            return;
+        };
+        for chunk in points.chunks(MAX_LASER_POINTS) {
+            sender.send_ephemeral(&ClientMessage::LaserMove {
+                points: chunk.to_vec(),
+                start,
+            });
+            start = false;
+        }
     });
     let _ = window.request_animation_frame(cb.unchecked_ref());
 }
 
+/// Adds a local laser sample: shows it at once and queues it for peers.
+fn push_laser_point(state: &mut State, point: Point, start: bool) {
+    add_laser_points(state, None, &[point], start, js_sys::Date::now());
+    match &mut state.pending_laser {
+        Some((points, _)) if !start => points.push(point),
+        pending => *pending = Some((vec![point], start)),
+    }
+}
+
+/// Starts fetching the assets of images on the board that are not cached yet; each one redraws
+/// the board once it arrives.
+fn load_missing_images(
+    window: &web_sys::Window,
+    ui: &Rc<Ui>,
+    state_rc: &Rc<RefCell<State>>,
+    state: &mut State,
+) {
+    let missing = state
+        .strokes
+        .iter()
+        .filter_map(|stroke| stroke.image.as_ref())
+        .filter(|image| !state.images.contains_key(&image.hash))
+        .map(|image| image.hash.clone())
+        .collect::<HashSet<_>>();
+    for hash in missing {
+        let window_cb = window.clone();
+        let ui = ui.clone();
+        let state_cb = state_rc.clone();
+        let on_load = move || schedule_redraw(&window_cb, &ui, &state_cb);
+        if let Some(image) = load_asset_image(window, &hash, on_load) {
+            state.images.insert(hash, image);
+        }
+    }
+}
+
+/// Uploads a pasted or dropped image and adds it centered on `center` (the middle of the view
+/// when `None`), at its natural size or smaller so it fits in view.
+fn insert_image(
+    window: &web_sys::Window,
+    ui: &Rc<Ui>,
+    state: &Rc<RefCell<State>>,
+    sender: &Rc<WsSender>,
+    file: &File,
+    center: Option<Point>,
+) {
+    if state.borrow().view_only || !sender.is_open() {
+        return;
+    }
+    {
+        let state = state.borrow();
+        if !layer_editable(&state.layers, state.layer) {
+            let _ = window.alert_with_message("The active layer is hidden or locked.");
+            return;
+        }
+    }
+    let window_cb = window.clone();
+    let ui = ui.clone();
+    let state = state.clone();
+    let sender = sender.clone();
+    upload_image(window, file, move |result| {
+        let asset = match result {
+            Ok(asset) => asset,
+            Err(message) => {
+                let _ = window_cb.alert_with_message(&message);
+                return;
+            }
+        };
+        let (page, stroke) = {
+            let mut state = state.borrow_mut();
+            if state.view_only || !layer_editable(&state.layers, state.layer) {
+                return;
+            }
+            let center = center.unwrap_or_else(|| view_center(&state));
+            let fit = (state.board_width * 0.8 / state.zoom / asset.width as f64)
+                .min(state.board_height * 0.8 / state.zoom / asset.height as f64)
+                .min(1.0);
+            let stroke = new_image(
+                asset.clone(),
+                center,
+                (asset.width as f64 * fit) as f32,
+                state.layer,
+            );
+            state.unconfirmed_ids.insert(stroke.id);
+            state.strokes.push(stroke.clone());
+            (state.page, stroke)
+        };
+        schedule_redraw(&window_cb, &ui, &state);
+        sender.send(&ClientMessage::ImageCreate { page, stroke });
+    });
+}
+
+/// Adds copies of `strokes` on the active layer of the page on screen as one `strokes:add` and
+/// selects them. They are centered on `center`, or land a little below and right of the
+/// originals when it is `None`.
+fn paste_strokes(
+    window: &web_sys::Window,
+    ui: &Rc<Ui>,
+    state_rc: &Rc<RefCell<State>>,
+    sender: &Rc<WsSender>,
+    strokes: &[Stroke],
+    center: Option<Point>,
+) {
+    let (page, copies, dropped) = {
+        let mut state = state_rc.borrow_mut();
+        if state.view_only || matches!(state.mode, Mode::Loading(_)) {
+            return;
+        }
+        if !layer_editable(&state.layers, state.layer) {
+            drop(state);
+            let _ = window.alert_with_message("The active layer is hidden or locked.");
+            return;
+        }
+        let room = MAX_STROKES.saturating_sub(state.strokes.len());
+        if room == 0 {
+            drop(state);
+            let _ = window.alert_with_message("This page is full.");
+            return;
+        }
+        let dropped = strokes.len().saturating_sub(room);
+        let strokes = &strokes[..strokes.len().min(room)];
+        let copies = place_copies(strokes, state.layer, center, DUPLICATE_OFFSET / state.zoom);
+        finish_text_edit(&mut state, sender);
+        insert_copies(&mut state, &copies);
+        ui.sync_tool_ui(&state, false);
+        render_palette(
+            &ui.document,
+            &ui.palette_el,
+            &state.palette,
+            palette_selected(&state.mode),
+        );
+        ui.hide_color_input();
+        (state.page, copies, dropped)
+    };
+    schedule_redraw(window, ui, state_rc);
+    sender.send(&ClientMessage::AddStrokes {
+        page,
+        strokes: copies,
+    });
+    if dropped > 0 {
+        let _ = window.alert_with_message(&format!(
+            "This page is full, so {dropped} of the strokes were left out."
+        ));
+    }
+}
+
+/// Closes the text editor, dropping the box locally if it was left empty (the server does the
+/// same on `text:end`). Returns whether an edit was open.
+fn finish_text_edit(state: &mut State, sender: &WsSender) -> bool {
+    let Mode::Text(text) = &mut state.mode else {
+        return false;
+    };
+    let Some(id) = text.editing.take() else {
+        return false;
+    };
+    state.active_ids.remove(&id);
+    let empty = state
+        .strokes
+        .iter()
+        .find(|stroke| stroke.id == id)
+        .and_then(|stroke| stroke.text.as_ref())
+        .is_none_or(|text| text.content.trim().is_empty());
+    if empty {
+        remove_stroke(state, &id);
+    }
+    sender.send(&ClientMessage::TextEnd { id });
+    true
+}
+
+/// Whether `event` is aimed at a text input or textarea, which keep their own clipboard.
+fn in_text_field(event: &Event) -> bool {
+    event
+        .target()
+        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+        .is_some_and(|element| matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA"))
+}
+
 fn palette_selected(mode: &Mode) -> Option<usize> {
     match mode {
         Mode::Draw(draw) => Some(draw.palette_selected),
+        Mode::Shape(shape) => Some(shape.palette_selected),
+        Mode::Text(text) => Some(text.palette_selected),
         Mode::Loading(loading) => palette_selected(loading.previous.as_ref()),
         _ => None,
     }
 }
 
+fn render_presence(ui: &Ui, state: &State) {
+    if state.peers.is_empty() {
+        ui.render_peer_list(&[]);
+        return;
+    }
+    let mut peers = state
+        .peers
+        .iter()
+        .map(|(peer, presence)| {
+            (
+                peer_label(*peer, presence.name.as_deref()),
+                presence.color.to_rgba_css(),
+            )
+        })
+        .collect::<Vec<_>>();
+    peers.sort();
+    let own_label = if state.display_name.is_empty() {
+        "You".to_string()
+    } else {
+        format!("{} (you)", state.display_name)
+    };
+    peers.insert(0, (own_label, Color::DEFAULT.to_rgba_css()));
+    ui.render_peer_list(&peers);
+}
+
+fn render_page_bar(ui: &Ui, state: &State) {
+    let count = state.pages.len();
+    let current = current_index(state);
+    render_pages(&ui.document, &ui.page_list, count, current);
+    ui.page_left_button.set_disabled(current == 0);
+    ui.page_right_button.set_disabled(current + 1 >= count);
+    ui.page_add_button.set_disabled(count >= MAX_PAGES);
+    ui.page_remove_button.set_disabled(count <= 1);
+}
+
+fn render_layer_menu(ui: &Ui, state: &State) {
+    render_layers(&ui.document, &ui.layer_list, &state.layers, state.layer);
+    let index = active_index(state);
+    ui.layer_up_button
+        .set_disabled(index + 1 >= state.layers.len());
+    ui.layer_down_button.set_disabled(index == 0);
+    ui.layer_add_button
+        .set_disabled(state.layers.len() >= MAX_LAYERS);
+    ui.layer_remove_button.set_disabled(state.layers.len() <= 1);
+}
+
+/// Closes the text editor if the box being edited is on a layer that was just hidden or locked.
+fn finish_locked_text_edit(state: &mut State, sender: &WsSender) {
+    let locked = match &state.mode {
+        Mode::Text(TextState {
+            editing: Some(id), ..
+        }) => state
+            .strokes
+            .iter()
+            .find(|stroke| &stroke.id == id)
+            .is_some_and(|stroke| !layer_editable(&state.layers, stroke.layer)),
+        _ => false,
+    };
+    if locked {
+        finish_text_edit(state, sender);
+    }
+}
+
+/// Puts `page` on screen, closing any open text edit first, and tells peers.
+fn switch_page(
+    window: &web_sys::Window,
+    ui: &Rc<Ui>,
+    state_rc: &Rc<RefCell<State>>,
+    sender: &WsSender,
+    page: PageId,
+) {
+    {
+        let mut state = state_rc.borrow_mut();
+        finish_text_edit(&mut state, sender);
+        if !show_page(&mut state, page) {
+            return;
+        }
+        render_page_bar(ui, &state);
+    }
+    sender.send_ephemeral(&ClientMessage::PageSwitch { page });
+    schedule_redraw(window, ui, state_rc);
+}
+
+fn copy_link(window: &web_sys::Window, link: &str) {
+    let navigator = window.navigator();
+    let has_clipboard = js_sys::Reflect::get(navigator.as_ref(), &JsValue::from_str("clipboard"))
+        .map(|value| !value.is_undefined())
+        .unwrap_or(false);
+    if has_clipboard {
+        let _ = navigator.clipboard().write_text(link);
+    } else {
+        // No async clipboard outside secure contexts; let the user copy it by hand.
+        let _ = window.prompt_with_message_and_default("Copy this link", link);
+    }
+}
+
 fn handle_load_error_banner(window: &web_sys::Window, ui: &Ui) {
     let Ok(search) = window.location().search() else {
         return;
@@ -136,7 +532,7 @@ fn pinch_distance(points: &[(f64, f64)]) -> f64 {
     (dx * dx + dy * dy).sqrt()
 }
 
-fn read_load_payload(event: &ProgressEvent) -> Option<Vec<Stroke>> {
+fn read_load_payload(event: &ProgressEvent) -> Option<SessionFileData> {
     let reader: FileReader = event.target()?.dyn_into().ok()?;
     let buffer = reader
         .result()
@@ -163,6 +559,10 @@ fn start_app() -> Result<(), JsValue> {
 
     let state = Rc::new(RefCell::new(State {
         strokes: Vec::new(),
+        page: PageId::INITIAL,
+        pages: vec![Page::new(PageId::INITIAL)],
+        layers: vec![Layer::base()],
+        layer: LayerId::BASE,
         active_ids: HashSet::new(),
         board_width: 0.0,
         board_height: 0.0,
@@ -178,18 +578,35 @@ fn start_app() -> Result<(), JsValue> {
             palette_selected: 0,
         }),
         pending_points: HashMap::new(),
+        unconfirmed_ids: HashSet::new(),
         flush_scheduled: false,
         redraw_scheduled: false,
         ws_offline_prompted: false,
         input_activity: InputActivity::None,
         touch_points: HashMap::new(),
         palette_remove_mode: false,
+        display_name: load_display_name(&window),
+        smoothing: load_smoothing(&window),
+        view_only: false,
+        edit_link: None,
+        view_link: None,
+        peers: HashMap::new(),
+        pending_cursor: None,
+        pointer: None,
+        load_insert: false,
+        cursor_flush_scheduled: false,
+        images: HashMap::new(),
+        lasers: Vec::new(),
+        laser_frame_scheduled: false,
+        pending_laser: None,
+        laser_flush_scheduled: false,
     }));
 
     ui.update_size_label();
     ui.set_status("connecting", "Connecting...");
     ui.set_tool_button(&ui.lasso_button, false);
     ui.set_tool_button(&ui.eraser_button, false);
+    ui.set_tool_button(&ui.laser_button, false);
     ui.set_tool_button(&ui.pan_button, false);
     ui.set_canvas_mode(&state.borrow().mode, false);
     {
@@ -202,6 +619,8 @@ fn start_app() -> Result<(), JsValue> {
         }
         render_palette(&ui.document, &ui.palette_el, &state.palette, selected);
         ui.show_color_input(selected);
+        render_page_bar(&ui, &state);
+        render_layer_menu(&ui, &state);
     }
     handle_load_error_banner(&window, &ui);
 
@@ -209,7 +628,7 @@ fn start_app() -> Result<(), JsValue> {
         let ui = ui.clone();
         let message_state = state.clone();
         let window = window.clone();
-        move |event: WsEvent| match event {
+        move |sender: &WsSender, event: WsEvent| match event {
             WsEvent::Open => {
                 ui.set_status("open", "Live connection");
                 ui.hide_reload_banner();
@@ -217,73 +636,408 @@ fn start_app() -> Result<(), JsValue> {
                 state.ws_offline_prompted = false;
             }
             WsEvent::Close => {
-                ui.set_status("closed", "Offline");
+                ui.set_status("closed", "Reconnecting...");
                 let mut state = message_state.borrow_mut();
+                if !state.peers.is_empty() {
+                    state.peers.clear();
+                    render_presence(&ui, &state);
+                    redraw(&ui.ctx, &mut state);
+                }
                 if !state.ws_offline_prompted {
                     state.ws_offline_prompted = true;
-                    ui.show_reload_banner("Connection lost. Please reload the page.");
+                    ui.show_reload_banner("Connection lost. Reconnecting...");
                 }
             }
             WsEvent::Error => {
-                ui.set_status("closed", "Connection error");
+                ui.set_status("closed", "Reconnecting...");
                 let mut state = message_state.borrow_mut();
+                if !state.peers.is_empty() {
+                    state.peers.clear();
+                    render_presence(&ui, &state);
+                    redraw(&ui.ctx, &mut state);
+                }
                 if !state.ws_offline_prompted {
                     state.ws_offline_prompted = true;
-                    ui.show_reload_banner("Connection error. Please reload the page.");
+                    ui.show_reload_banner("Connection error. Reconnecting...");
                 }
             }
             WsEvent::Message(message) => {
                 let mut state = message_state.borrow_mut();
                 let mut needs_redraw = false;
+                let mut needs_laser_frame = false;
+                let mut history_skipped = false;
+                let mut strokes_rejected = false;
+                let background = message_page(&state, &message).filter(|page| *page != state.page);
+                if let Some(page) = background {
+                    if !swap_page_strokes(&mut state, page) {
+                        // The page was removed here while the message was on its way.
+                        return;
+                    }
+                    needs_redraw = true;
+                }
                 match message {
-                    ServerMessage::Sync { strokes } => {
-                        adopt_strokes(&mut state, &ui.ctx, strokes, false);
+                    ServerMessage::Sync { pages, layers } => {
+                        // Picks up images that are not cached yet.
+                        needs_redraw = true;
+                        set_layers(&mut state, layers);
+                        if sender.is_synced() {
+                            adopt_pages(&mut state, &ui.ctx, pages, false);
+                        } else {
+                            let page_edits = sender.queued_page_edits();
+                            for (page, stroke, ended) in
+                                reconcile_pages(&mut state, &ui.ctx, pages, &page_edits)
+                            {
+                                sender.resend_stroke(page, &stroke, ended);
+                            }
+                        }
+                        render_page_bar(&ui, &state);
+                        render_layer_menu(&ui, &state);
+                        sender.send(&ClientMessage::PageSwitch { page: state.page });
                     }
                     ServerMessage::StrokeStart {
+                        page: _,
                         id,
                         color,
                         size,
                         point,
+                        blend,
+                        layer,
+                        author,
                     } => {
-                        start_stroke(&mut state, &ui.ctx, id, color, size, point);
+                        let stroke = Stroke {
+                            author: Some(author),
+                            ..new_freehand(id, color, size, point, blend, layer)
+                        };
+                        if background.is_some() {
+                            let _ = start_stroke_local(&mut state, stroke);
+                        } else {
+                            start_stroke(&mut state, &ui.ctx, stroke);
+                        }
                     }
                     ServerMessage::StrokeMove { id, point } => {
-                        let _ = move_stroke(&mut state, &ui.ctx, &id, point);
+                        if background.is_some() {
+                            let _ = move_stroke_local(&mut state, &id, point);
+                        } else {
+                            let _ = move_stroke(&mut state, &ui.ctx, &id, point);
+                            needs_redraw |= needs_repaint(&state, &id);
+                        }
                     }
                     ServerMessage::StrokePoints { id, points } => {
                         for point in points {
-                            let _ = move_stroke(&mut state, &ui.ctx, &id, point);
+                            if background.is_some() {
+                                let _ = move_stroke_local(&mut state, &id, point);
+                            } else {
+                                let _ = move_stroke(&mut state, &ui.ctx, &id, point);
+                            }
                         }
+                        needs_redraw |= needs_repaint(&state, &id);
                     }
                     ServerMessage::StrokeEnd { id } => {
                         end_stroke(&mut state, &id);
                     }
-                    ServerMessage::Clear => {
-                        clear_board(&mut state, &ui.ctx);
+                    ServerMessage::Clear { .. } => {
+                        clear_board(&mut state);
+                        needs_redraw = true;
                     }
                     ServerMessage::StrokeRemove { id } => {
                         remove_stroke(&mut state, &id);
                         needs_redraw = true;
                     }
-                    ServerMessage::StrokeRestore { stroke } => {
-                        restore_stroke(&mut state, &ui.ctx, stroke);
+                    ServerMessage::StrokeRestore { stroke, .. } => {
+                        restore_stroke(&mut state, stroke);
+                        needs_redraw = true;
                     }
                     ServerMessage::StrokeReplace { stroke } => {
                         replace_stroke_local(&mut state, stroke);
                         needs_redraw = true;
                     }
+                    ServerMessage::StrokeSplice { remove, insert, .. } => {
+                        splice_strokes_local(&mut state, &remove, insert);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::Reorder { order, .. } => {
+                        arrange(&mut state.strokes, &order);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::ShapeCreate { stroke, .. } => {
+                        restore_stroke(&mut state, stroke);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::ShapeUpdate {
+                        id,
+                        color,
+                        size,
+                        shape,
+                    } => {
+                        update_shape_local(&mut state, &id, color, size, shape);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::TextCreate { stroke, .. } => {
+                        restore_stroke(&mut state, stroke);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::TextUpdate { id, text } => {
+                        update_text_local(&mut state, &id, text);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::ImageCreate { stroke, .. } => {
+                        restore_stroke(&mut state, stroke);
+                        needs_redraw = true;
+                    }
                     ServerMessage::TransformUpdate { ids, op } => {
-                        apply_transform_operation(&mut state, &ui.ctx, &ids, &op);
+                        apply_transform_operation(&mut state, &ids, &op);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::Access {
+                        role,
+                        edit_token,
+                        view_token,
+                    } => {
+                        state.view_only = !role.can_edit();
+                        if state.view_only {
+                            sender.discard_outbox();
+                            state.unconfirmed_ids.clear();
+                            state.pending_points.clear();
+                            state.mode = Mode::Pan(PanMode::Idle);
+                            ui.sync_tool_ui(&state, false);
+                            ui.hide_color_input();
+                        }
+                        state.edit_link = edit_token.and_then(|token| share_url(&window, &token));
+                        state.view_link = view_token.and_then(|token| share_url(&window, &token));
+                        ui.set_access(role, state.edit_link.is_some(), state.view_link.is_some());
+                    }
+                    ServerMessage::Peers { peers } => {
+                        state.peers = peers
+                            .into_iter()
+                            .map(|info| {
+                                (
+                                    info.peer,
+                                    PeerPresence {
+                                        name: info.name,
+                                        cursor: None,
+                                        color: Color::DEFAULT,
+                                        page: info.page,
+                                    },
+                                )
+                            })
+                            .collect();
+                        render_presence(&ui, &state);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::PeerJoin { peer } => {
+                        state.peers.insert(
+                            peer,
+                            PeerPresence {
+                                name: None,
+                                cursor: None,
+                                color: Color::DEFAULT,
+                                page: None,
+                            },
+                        );
+                        render_presence(&ui, &state);
+                    }
+                    ServerMessage::PeerName { peer, name } => {
+                        let presence = state.peers.entry(peer).or_insert(PeerPresence {
+                            name: None,
+                            cursor: None,
+                            color: Color::DEFAULT,
+                            page: None,
+                        });
+                        presence.name = Some(name);
+                        render_presence(&ui, &state);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::PeerLeave { peer } => {
+                        if state.peers.remove(&peer).is_some() {
+                            render_presence(&ui, &state);
+                            needs_redraw = true;
+                        }
                     }
+                    ServerMessage::PeerLaser {
+                        peer,
+                        points,
+                        start,
+                    } => {
+                        let on_page = state
+                            .peers
+                            .get(&peer)
+                            .is_some_and(|presence| presence.page == Some(state.page));
+                        if on_page {
+                            add_laser_points(
+                                &mut state,
+                                Some(peer),
+                                &points,
+                                start,
+                                js_sys::Date::now(),
+                            );
+                            needs_laser_frame = true;
+                        }
+                    }
+                    ServerMessage::PeerCursor { peer, point, color } => {
+                        let presence = state.peers.entry(peer).or_insert(PeerPresence {
+                            name: None,
+                            cursor: None,
+                            color,
+                            page: None,
+                        });
+                        let color_changed = presence.color != color;
+                        presence.cursor = point;
+                        presence.color = color;
+                        if color_changed {
+                            render_presence(&ui, &state);
+                        }
+                        needs_redraw = true;
+                    }
+                    ServerMessage::PeerPage { peer, page } => {
+                        let presence = state.peers.entry(peer).or_insert(PeerPresence {
+                            name: None,
+                            cursor: None,
+                            color: Color::DEFAULT,
+                            page: None,
+                        });
+                        presence.page = Some(page);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::PageAdd { page, index } => {
+                        add_page_local(&mut state, page, index);
+                        render_page_bar(&ui, &state);
+                    }
+                    ServerMessage::PageRemove { page } => {
+                        let shown = state.page;
+                        if remove_page_local(&mut state, page) {
+                            render_page_bar(&ui, &state);
+                            needs_redraw = true;
+                        }
+                        if state.page != shown {
+                            sender.send_ephemeral(&ClientMessage::PageSwitch { page: state.page });
+                        }
+                    }
+                    ServerMessage::PageMove { page, index } => {
+                        move_page_local(&mut state, page, index);
+                        render_page_bar(&ui, &state);
+                    }
+                    ServerMessage::Layers { layers } => {
+                        set_layers(&mut state, layers);
+                        finish_locked_text_edit(&mut state, &sender);
+                        render_layer_menu(&ui, &state);
+                        needs_redraw = true;
+                    }
+                    ServerMessage::HistorySkipped { .. } => {
+                        history_skipped = true;
+                    }
+                    ServerMessage::StrokesRejected { ids, .. } => {
+                        for id in &ids {
+                            remove_stroke(&mut state, id);
+                        }
+                        needs_redraw = true;
+                        strokes_rejected = true;
+                    }
+                }
+                if let Some(page) = background {
+                    swap_page_strokes(&mut state, page);
+                }
+                // Someone else removed (or undid) the text box being edited here.
+                let lost_edit = match &state.mode {
+                    Mode::Text(TextState {
+                        editing: Some(id), ..
+                    }) => !state.strokes.iter().any(|stroke| &stroke.id == id),
+                    _ => false,
+                };
+                if lost_edit {
+                    if let Mode::Text(text) = &mut state.mode {
+                        text.editing = None;
+                    }
+                    needs_redraw = true;
                 }
                 drop(state);
                 if needs_redraw {
                     schedule_redraw(&window, &ui, &message_state);
                 }
+                if history_skipped {
+                    let _ = window.alert_with_message(
+                        "Some of these strokes were changed by someone else or are on a locked layer, so they were left as they are.",
+                    );
+                }
+                if strokes_rejected {
+                    let _ = window.alert_with_message(
+                        "Some pasted strokes could not be added, such as images copied from another board, so they were removed.",
+                    );
+                }
+                if needs_laser_frame {
+                    schedule_laser_frame(&window, &ui, &message_state);
+                }
             }
         }
     })?;
 
+    let (client_id, secret) = load_client_identity(&window);
+    ws_sender.set_hello(ClientMessage::Hello {
+        client_id,
+        secret,
+        name: state.borrow().display_name.clone(),
+    });
+
+    {
+        let ws_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let name_state = state.clone();
+        let window_cb = window.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            let is_self = event
+                .target()
+                .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+                .and_then(|element| element.closest("[data-self]").ok().flatten())
+                .is_some();
+            if !is_self {
+                return;
+            }
+            let current = name_state.borrow().display_name.clone();
+            let Ok(Some(input)) =
+                window_cb.prompt_with_message_and_default("Display name", &current)
+            else {
+                return;
+            };
+            let name = sanitize_display_name(&input);
+            store_display_name(&window_cb, &name);
+            {
+                let mut state = name_state.borrow_mut();
+                state.display_name = name.clone();
+                render_presence(&ui_callback, &state);
+            }
+            ws_sender.set_hello(ClientMessage::Hello {
+                client_id,
+                secret,
+                name,
+            });
+        });
+        ui.peer_list
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    for (button, is_edit) in [
+        (&ui.copy_edit_link_button, true),
+        (&ui.copy_view_link_button, false),
+    ] {
+        let link_state = state.clone();
+        let window_cb = window.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let link = {
+                let state = link_state.borrow();
+                if is_edit {
+                    state.edit_link.clone()
+                } else {
+                    state.view_link.clone()
+                }
+            };
+            if let Some(link) = link {
+                copy_link(&window_cb, &link);
+            }
+        });
+        button.add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
     {
         let ws_sender = ws_sender.clone();
         let ui_callback = ui.clone();
@@ -292,7 +1046,7 @@ fn start_app() -> Result<(), JsValue> {
             reconnect_state.borrow_mut().ws_offline_prompted = false;
             ui_callback.hide_reload_banner();
             if ws_sender.reconnect().is_err() {
-                ui_callback.show_reload_banner("Connection error. Please reload the page.");
+                ui_callback.show_reload_banner("Connection error. Reconnecting...");
             }
         });
         ui.reload_button
@@ -308,6 +1062,7 @@ fn start_app() -> Result<(), JsValue> {
             {
                 let mut state = resize_state.borrow_mut();
                 resize_canvas(&window_cb, &ui.canvas, &ui.ctx, &mut state);
+                resize_overlay(&window_cb, &ui.overlay, &ui.overlay_ctx);
             }
             schedule_redraw(&window_cb, &ui, &resize_state);
         });
@@ -316,12 +1071,124 @@ fn start_app() -> Result<(), JsValue> {
     }
 
     {
-        let key_sender = ws_sender.clone();
-        let key_state = state.clone();
+        let paste_state = state.clone();
+        let paste_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onpaste = Closure::<dyn FnMut(ClipboardEvent)>::new(move |event: ClipboardEvent| {
+            // Text fields keep their own paste.
+            if in_text_field(&event) {
+                return;
+            }
+            let fragment = event
+                .clipboard_data()
+                .and_then(|data| data.get_data("text/plain").ok())
+                .and_then(|text| parse_fragment(&text));
+            if let Some(strokes) = fragment {
+                event.prevent_default();
+                let pointer = paste_state.borrow().pointer;
+                paste_strokes(
+                    &window_cb,
+                    &ui_callback,
+                    &paste_state,
+                    &paste_sender,
+                    &strokes,
+                    pointer,
+                );
+                return;
+            }
+            let Some(file) = image_file(event.clipboard_data().and_then(|data| data.files()))
+            else {
+                return;
+            };
+            event.prevent_default();
+            insert_image(
+                &window_cb,
+                &ui_callback,
+                &paste_state,
+                &paste_sender,
+                &file,
+                None,
+            );
+        });
+        window.add_event_listener_with_callback("paste", onpaste.as_ref().unchecked_ref())?;
+        onpaste.forget();
+
+        let copy_state = state.clone();
+        let oncopy = Closure::<dyn FnMut(ClipboardEvent)>::new(move |event: ClipboardEvent| {
+            if in_text_field(&event) {
+                return;
+            }
+            let text = {
+                let state = copy_state.borrow();
+                let Mode::Select(select) = &state.mode else {
+                    return;
+                };
+                let strokes = selected_strokes(&state.strokes, select);
+                if strokes.is_empty() {
+                    return;
+                }
+                fragment_text(&strokes)
+            };
+            let Some(data) = event.clipboard_data() else {
+                return;
+            };
+            if data.set_data("text/plain", &text).is_ok() {
+                event.prevent_default();
+            }
+        });
+        window.add_event_listener_with_callback("copy", oncopy.as_ref().unchecked_ref())?;
+        oncopy.forget();
+    }
+
+    {
+        // The canvas has to accept the drag for `drop` to fire.
+        let ondragover = Closure::<dyn FnMut(DragEvent)>::new(move |event: DragEvent| {
+            event.prevent_default();
+        });
+        ui.canvas
+            .add_event_listener_with_callback("dragover", ondragover.as_ref().unchecked_ref())?;
+        ondragover.forget();
+
+        let drop_state = state.clone();
+        let drop_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let ondrop = Closure::<dyn FnMut(DragEvent)>::new(move |event: DragEvent| {
+            event.prevent_default();
+            let Some(file) = image_file(event.data_transfer().and_then(|data| data.files())) else {
+                return;
+            };
+            let center = {
+                let state = drop_state.borrow();
+                let rect = ui_callback.canvas.get_bounding_client_rect();
+                Point {
+                    x: ((event.client_x() as f64 - rect.left() - state.pan_x) / state.zoom) as f32,
+                    y: ((event.client_y() as f64 - rect.top() - state.pan_y) / state.zoom) as f32,
+                    pressure: None,
+                }
+            };
+            insert_image(
+                &window_cb,
+                &ui_callback,
+                &drop_state,
+                &drop_sender,
+                &file,
+                Some(center),
+            );
+        });
+        ui.canvas
+            .add_event_listener_with_callback("drop", ondrop.as_ref().unchecked_ref())?;
+        ondrop.forget();
+    }
+
+    {
+        let key_sender = ws_sender.clone();
+        let key_state = state.clone();
         let ui_callback = ui.clone();
         let window_key = window.clone();
         let onkeydown = Closure::<dyn FnMut(KeyboardEvent)>::new(move |event: KeyboardEvent| {
-            if !key_sender.is_open() {
+            if key_state.borrow().view_only {
                 return;
             }
             let key = event.key();
@@ -352,6 +1219,33 @@ fn start_app() -> Result<(), JsValue> {
                 }
                 return;
             }
+            // Ctrl/Cmd+C and V go on to the `copy` and `paste` events, whose clipboard data
+            // works without the async clipboard API (which needs a secure context).
+            if key.eq_ignore_ascii_case("d") {
+                if in_text_field(&event) {
+                    return;
+                }
+                event.prevent_default();
+                let strokes = {
+                    let state = key_state.borrow();
+                    match &state.mode {
+                        Mode::Select(select) => selected_strokes(&state.strokes, select),
+                        _ => return,
+                    }
+                };
+                if strokes.is_empty() {
+                    return;
+                }
+                paste_strokes(
+                    &window_key,
+                    &ui_callback,
+                    &key_state,
+                    &key_sender,
+                    &strokes,
+                    None,
+                );
+                return;
+            }
             if event.shift_key() && key.eq_ignore_ascii_case("z") {
                 event.prevent_default();
                 key_sender.send(&ClientMessage::Redo);
@@ -374,6 +1268,7 @@ fn start_app() -> Result<(), JsValue> {
     {
         let mut state = state.borrow_mut();
         resize_canvas(&window, &ui.canvas, &ui.ctx, &mut state);
+        resize_overlay(&window, &ui.overlay, &ui.overlay_ctx);
     }
     schedule_redraw(&window, &ui, &state);
 
@@ -390,11 +1285,28 @@ fn start_app() -> Result<(), JsValue> {
     {
         let tool_state = state.clone();
         let ui_callback = ui.clone();
-        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
             let mut state = tool_state.borrow_mut();
             if matches!(state.mode, Mode::Loading(_)) {
                 return;
             }
+            // A second click on the active eraser opens its options instead.
+            if matches!(state.mode, Mode::Erase(_)) {
+                event.stop_propagation();
+                let is_open = !ui_callback.eraser_menu.has_attribute("hidden");
+                if is_open {
+                    let _ = ui_callback.eraser_menu.set_attribute("hidden", "");
+                    let _ = ui_callback
+                        .eraser_button
+                        .set_attribute("aria-expanded", "false");
+                } else {
+                    let _ = ui_callback.eraser_menu.remove_attribute("hidden");
+                    let _ = ui_callback
+                        .eraser_button
+                        .set_attribute("aria-expanded", "true");
+                }
+                return;
+            }
             state.mode = Mode::Erase(EraseMode::Idle);
             ui_callback.sync_tool_ui(&state, false);
             render_palette(
@@ -410,6 +1322,29 @@ fn start_app() -> Result<(), JsValue> {
         onclick.forget();
     }
 
+    {
+        let tool_state = state.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let mut state = tool_state.borrow_mut();
+            if matches!(state.mode, Mode::Loading(_)) {
+                return;
+            }
+            state.mode = Mode::Laser(LaserMode::Idle);
+            ui_callback.sync_tool_ui(&state, false);
+            render_palette(
+                &ui_callback.document,
+                &ui_callback.palette_el,
+                &state.palette,
+                palette_selected(&state.mode),
+            );
+            ui_callback.hide_color_input();
+        });
+        ui.laser_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
     {
         let tool_state = state.clone();
         let ui_callback = ui.clone();
@@ -556,10 +1491,16 @@ fn start_app() -> Result<(), JsValue> {
                         return;
                     }
                     let already_selected = palette_selected(&state.mode) == Some(index);
-                    state.mode = Mode::Draw(DrawState {
-                        mode: DrawMode::Idle,
-                        palette_selected: index,
-                    });
+                    if let Mode::Shape(shape) = &mut state.mode {
+                        shape.palette_selected = index;
+                    } else if let Mode::Text(text) = &mut state.mode {
+                        text.palette_selected = index;
+                    } else {
+                        state.mode = Mode::Draw(DrawState {
+                            mode: DrawMode::Idle,
+                            palette_selected: index,
+                        });
+                    }
                     if let Some(color) = state.palette.get(index).cloned() {
                         ui_callback.color_input.set_value(&color);
                     }
@@ -706,6 +1647,8 @@ fn start_app() -> Result<(), JsValue> {
             let mut state = palette_state.borrow_mut();
             let selected = match &state.mode {
                 Mode::Draw(draw) => draw.palette_selected,
+                Mode::Shape(shape) => shape.palette_selected,
+                Mode::Text(text) => text.palette_selected,
                 _ => return,
             };
             if let Some(entry) = state.palette.get_mut(selected) {
@@ -730,45 +1673,582 @@ fn start_app() -> Result<(), JsValue> {
         let clear_state = state.clone();
         let clear_sender = ws_sender.clone();
         let ui_callback = ui.clone();
+        let clear_window = window.clone();
         let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
-            if !clear_sender.is_open() {
+            if !clear_sender.is_synced() {
+                let _ = clear_window.alert_with_message("Reconnect before clearing the page.");
                 return;
             }
-            {
+            let page = {
                 let mut state = clear_state.borrow_mut();
-                clear_board(&mut state, &ui_callback.ctx);
-            }
-            clear_sender.send(&ClientMessage::Clear);
+                clear_board(&mut state);
+                redraw(&ui_callback.ctx, &mut state);
+                state.page
+            };
+            clear_sender.send(&ClientMessage::Clear { page });
         });
         ui.clear_button
             .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
         onclick.forget();
     }
 
+    {
+        let page_state = state.clone();
+        let page_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            let Some(index) = page_index_from_event(&event) else {
+                return;
+            };
+            let Some(page) = page_state.borrow().pages.get(index).map(|page| page.id) else {
+                return;
+            };
+            switch_page(&window_cb, &ui_callback, &page_state, &page_sender, page);
+        });
+        ui.page_list
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let page_state = state.clone();
+        let page_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let page = make_page_id();
+            let index = {
+                let mut state = page_state.borrow_mut();
+                let index = current_index(&state) + 1;
+                if !add_page_local(&mut state, page, index) {
+                    return;
+                }
+                index
+            };
+            page_sender.send(&ClientMessage::PageAdd { page, index });
+            switch_page(&window_cb, &ui_callback, &page_state, &page_sender, page);
+        });
+        ui.page_add_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let page_state = state.clone();
+        let page_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let (removed, shown) = {
+                let mut state = page_state.borrow_mut();
+                finish_text_edit(&mut state, &page_sender);
+                let removed = state.page;
+                if !remove_page_local(&mut state, removed) {
+                    return;
+                }
+                render_page_bar(&ui_callback, &state);
+                (removed, state.page)
+            };
+            page_sender.send(&ClientMessage::PageRemove { page: removed });
+            page_sender.send_ephemeral(&ClientMessage::PageSwitch { page: shown });
+            schedule_redraw(&window_cb, &ui_callback, &page_state);
+        });
+        ui.page_remove_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    for (button, forward) in [(&ui.page_left_button, false), (&ui.page_right_button, true)] {
+        let page_state = state.clone();
+        let page_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let (page, index) = {
+                let mut state = page_state.borrow_mut();
+                let current = current_index(&state);
+                let index = if forward {
+                    current + 1
+                } else {
+                    let Some(index) = current.checked_sub(1) else {
+                        return;
+                    };
+                    index
+                };
+                let page = state.page;
+                if index >= state.pages.len() || !move_page_local(&mut state, page, index) {
+                    return;
+                }
+                render_page_bar(&ui_callback, &state);
+                (page, index)
+            };
+            page_sender.send(&ClientMessage::PageMove { page, index });
+        });
+        button.add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            event.stop_propagation();
+            let is_open = !ui_callback.layer_menu.has_attribute("hidden");
+            if is_open {
+                let _ = ui_callback.layer_menu.set_attribute("hidden", "");
+                let _ = ui_callback
+                    .layers_button
+                    .set_attribute("aria-expanded", "false");
+            } else {
+                let _ = ui_callback.layer_menu.remove_attribute("hidden");
+                let _ = ui_callback
+                    .layers_button
+                    .set_attribute("aria-expanded", "true");
+            }
+        });
+        ui.layers_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let layer_state = state.clone();
+        let layer_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            // The list is rebuilt below, which would otherwise make the click look like one
+            // outside the menu.
+            event.stop_propagation();
+            let Some((index, action)) = layer_action_from_event(&event) else {
+                return;
+            };
+            let update = {
+                let mut state = layer_state.borrow_mut();
+                let Some(mut layer) = state.layers.get(index).cloned() else {
+                    return;
+                };
+                let update = match action {
+                    LayerAction::Select => {
+                        state.layer = layer.id;
+                        None
+                    }
+                    LayerAction::ToggleVisible => {
+                        layer.visible = !layer.visible;
+                        Some(layer)
+                    }
+                    LayerAction::ToggleLocked => {
+                        layer.locked = !layer.locked;
+                        Some(layer)
+                    }
+                };
+                if let Some(layer) = &update {
+                    update_layer_local(&mut state, layer.clone());
+                    finish_locked_text_edit(&mut state, &layer_sender);
+                }
+                render_layer_menu(&ui_callback, &state);
+                update
+            };
+            if let Some(layer) = update {
+                layer_sender.send(&ClientMessage::LayerUpdate { layer });
+                schedule_redraw(&window_cb, &ui_callback, &layer_state);
+            }
+        });
+        ui.layer_list
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let layer_state = state.clone();
+        let layer_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let ondblclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            let Some((index, LayerAction::Select)) = layer_action_from_event(&event) else {
+                return;
+            };
+            let Some(mut layer) = layer_state.borrow().layers.get(index).cloned() else {
+                return;
+            };
+            let Ok(Some(name)) =
+                window_cb.prompt_with_message_and_default("Layer name", &layer.name)
+            else {
+                return;
+            };
+            let name = name.trim().to_string();
+            if name.is_empty() || name == layer.name {
+                return;
+            }
+            layer.name = name;
+            {
+                let mut state = layer_state.borrow_mut();
+                update_layer_local(&mut state, layer.clone());
+                render_layer_menu(&ui_callback, &state);
+            }
+            layer_sender.send(&ClientMessage::LayerUpdate { layer });
+        });
+        ui.layer_list
+            .add_event_listener_with_callback("dblclick", ondblclick.as_ref().unchecked_ref())?;
+        ondblclick.forget();
+    }
+
+    {
+        let layer_state = state.clone();
+        let layer_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let (layer, index) = {
+                let mut state = layer_state.borrow_mut();
+                let layer =
+                    Layer::new(make_layer_id(), format!("Layer {}", state.layers.len() + 1));
+                let index = active_index(&state) + 1;
+                if !add_layer_local(&mut state, layer.clone(), index) {
+                    return;
+                }
+                state.layer = layer.id;
+                render_layer_menu(&ui_callback, &state);
+                (layer, index)
+            };
+            layer_sender.send(&ClientMessage::LayerAdd { layer, index });
+        });
+        ui.layer_add_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let layer_state = state.clone();
+        let layer_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let layer = {
+                let mut state = layer_state.borrow_mut();
+                let layer = state.layer;
+                if !remove_layer_local(&mut state, layer) {
+                    drop(state);
+                    let _ = window_cb
+                        .alert_with_message("Only a layer with nothing on it can be deleted.");
+                    return;
+                }
+                render_layer_menu(&ui_callback, &state);
+                layer
+            };
+            layer_sender.send(&ClientMessage::LayerRemove { layer });
+        });
+        ui.layer_remove_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    for (button, up) in [(&ui.layer_up_button, true), (&ui.layer_down_button, false)] {
+        let layer_state = state.clone();
+        let layer_sender = ws_sender.clone();
+        let window_cb = window.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let (layer, index) = {
+                let mut state = layer_state.borrow_mut();
+                let current = active_index(&state);
+                let index = if up {
+                    current + 1
+                } else {
+                    let Some(index) = current.checked_sub(1) else {
+                        return;
+                    };
+                    index
+                };
+                let layer = state.layer;
+                if index >= state.layers.len() || !move_layer_local(&mut state, layer, index) {
+                    return;
+                }
+                render_layer_menu(&ui_callback, &state);
+                (layer, index)
+            };
+            layer_sender.send(&ClientMessage::LayerMove { layer, index });
+            schedule_redraw(&window_cb, &ui_callback, &layer_state);
+        });
+        button.add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
     {
         let undo_sender = ws_sender.clone();
         let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
-            if !undo_sender.is_open() {
+            undo_sender.send(&ClientMessage::Undo);
+        });
+        ui.undo_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let redo_sender = ws_sender.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            redo_sender.send(&ClientMessage::Redo);
+        });
+        ui.redo_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            event.stop_propagation();
+            let is_open = !ui_callback.pen_menu.has_attribute("hidden");
+            if is_open {
+                let _ = ui_callback.pen_menu.set_attribute("hidden", "");
+                let _ = ui_callback
+                    .pen_settings_button
+                    .set_attribute("aria-expanded", "false");
+            } else {
+                let _ = ui_callback.pen_menu.remove_attribute("hidden");
+                let _ = ui_callback
+                    .pen_settings_button
+                    .set_attribute("aria-expanded", "true");
+            }
+        });
+        ui.pen_settings_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let smoothing_state = state.clone();
+        let ui_callback = ui.clone();
+        let window_cb = window.clone();
+        ui.smoothing_input
+            .set_value_as_number((state.borrow().smoothing * 10.0).round());
+        let oninput = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let value = ui_callback.smoothing_input.value_as_number();
+            if !value.is_finite() {
+                return;
+            }
+            let strength = (value / 10.0).clamp(0.0, 1.0);
+            smoothing_state.borrow_mut().smoothing = strength;
+            store_smoothing(&window_cb, strength);
+        });
+        ui.smoothing_input
+            .add_event_listener_with_callback("input", oninput.as_ref().unchecked_ref())?;
+        oninput.forget();
+    }
+
+    {
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            event.stop_propagation();
+            let is_open = !ui_callback.shape_menu.has_attribute("hidden");
+            if is_open {
+                let _ = ui_callback.shape_menu.set_attribute("hidden", "");
+                let _ = ui_callback
+                    .shapes_button
+                    .set_attribute("aria-expanded", "false");
+            } else {
+                let _ = ui_callback.shape_menu.remove_attribute("hidden");
+                let _ = ui_callback
+                    .shapes_button
+                    .set_attribute("aria-expanded", "true");
+            }
+        });
+        ui.shapes_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let tool_state = state.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            let kind = event
+                .target()
+                .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+                .and_then(|element| element.closest("[data-shape]").ok().flatten())
+                .and_then(|element| element.get_attribute("data-shape"))
+                .and_then(|name| ShapeKind::from_name(&name));
+            let Some(kind) = kind else {
+                return;
+            };
+            let mut state = tool_state.borrow_mut();
+            if matches!(state.mode, Mode::Loading(_)) {
+                return;
+            }
+            let selected = palette_selected(&state.mode).unwrap_or(0);
+            state.mode = Mode::Shape(ShapeState {
+                kind,
+                palette_selected: selected,
+                drag: None,
+            });
+            if let Some(color) = state.palette.get(selected).cloned() {
+                ui_callback.color_input.set_value(&color);
+            }
+            ui_callback.sync_tool_ui(&state, false);
+            render_palette(
+                &ui_callback.document,
+                &ui_callback.palette_el,
+                &state.palette,
+                Some(selected),
+            );
+            ui_callback.show_color_input(Some(selected));
+            let _ = ui_callback.shape_menu.set_attribute("hidden", "");
+            let _ = ui_callback
+                .shapes_button
+                .set_attribute("aria-expanded", "false");
+        });
+        ui.shape_menu
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let tool_state = state.clone();
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let mut state = tool_state.borrow_mut();
+            if matches!(state.mode, Mode::Loading(_) | Mode::Text(_)) {
+                return;
+            }
+            let selected = palette_selected(&state.mode).unwrap_or(0);
+            state.mode = Mode::Text(TextState {
+                palette_selected: selected,
+                editing: None,
+            });
+            if let Some(color) = state.palette.get(selected).cloned() {
+                ui_callback.color_input.set_value(&color);
+            }
+            ui_callback.sync_tool_ui(&state, false);
+            render_palette(
+                &ui_callback.document,
+                &ui_callback.palette_el,
+                &state.palette,
+                Some(selected),
+            );
+            ui_callback.show_color_input(Some(selected));
+        });
+        ui.text_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let text_state = state.clone();
+        let text_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let window_cb = window.clone();
+        let oninput = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let update = {
+                let mut state = text_state.borrow_mut();
+                let Mode::Text(TextState {
+                    editing: Some(id), ..
+                }) = &state.mode
+                else {
+                    return;
+                };
+                let id = *id;
+                let Some(stroke) = state.strokes.iter_mut().find(|stroke| stroke.id == id) else {
+                    return;
+                };
+                let Some(text) = stroke.text.clone().and_then(|text| {
+                    sanitize_text(TextBox {
+                        content: ui_callback.text_editor.value(),
+                        ..text
+                    })
+                }) else {
+                    return;
+                };
+                stroke.text = Some(text.clone());
+                ui_callback.sync_text_editor(&state);
+                ClientMessage::TextUpdate { id, text }
+            };
+            schedule_redraw(&window_cb, &ui_callback, &text_state);
+            text_sender.send(&update);
+        });
+        ui.text_editor
+            .add_event_listener_with_callback("input", oninput.as_ref().unchecked_ref())?;
+        oninput.forget();
+    }
+
+    {
+        let text_state = state.clone();
+        let text_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let window_cb = window.clone();
+        let onblur = Closure::<dyn FnMut(Event)>::new(move |_| {
+            // Hiding the focused editor blurs it synchronously, which happens while the state is
+            // already borrowed by whoever is closing the edit.
+            let Ok(mut state) = text_state.try_borrow_mut() else {
+                return;
+            };
+            if !finish_text_edit(&mut state, &text_sender) {
+                return;
+            }
+            drop(state);
+            schedule_redraw(&window_cb, &ui_callback, &text_state);
+        });
+        ui.text_editor
+            .add_event_listener_with_callback("blur", onblur.as_ref().unchecked_ref())?;
+        onblur.forget();
+
+        let ui_callback = ui.clone();
+        let onkeydown = Closure::<dyn FnMut(KeyboardEvent)>::new(move |event: KeyboardEvent| {
+            // Typing must not reach the board shortcuts (undo, delete selection).
+            event.stop_propagation();
+            if event.key() == "Escape" {
+                let _ = ui_callback.text_editor.blur();
+            }
+        });
+        ui.text_editor
+            .add_event_listener_with_callback("keydown", onkeydown.as_ref().unchecked_ref())?;
+        onkeydown.forget();
+    }
+
+    {
+        let fill_state = state.clone();
+        let fill_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let window_cb = window.clone();
+        let onchange = Closure::<dyn FnMut(Event)>::new(move |_| {
+            // With shapes selected, the fill toggle restyles them instead of the next shape.
+            let fill = ui_callback.shape_fill.checked();
+            let updates = {
+                let mut state = fill_state.borrow_mut();
+                let ids = match &state.mode {
+                    Mode::Select(select) => select.selected_ids.clone(),
+                    _ => return,
+                };
+                let mut updates = Vec::new();
+                for stroke in state
+                    .strokes
+                    .iter_mut()
+                    .filter(|stroke| ids.contains(&stroke.id))
+                {
+                    let Some(shape) = &mut stroke.shape else {
+                        continue;
+                    };
+                    if !shape.kind.is_closed() || shape.fill.is_some() == fill {
+                        continue;
+                    }
+                    shape.fill = fill.then(|| shape_fill(stroke.color));
+                    updates.push(ClientMessage::ShapeUpdate {
+                        id: stroke.id,
+                        color: stroke.color,
+                        size: stroke.size,
+                        shape: *shape,
+                    });
+                }
+                updates
+            };
+            if updates.is_empty() {
                 return;
             }
-            undo_sender.send(&ClientMessage::Undo);
-        });
-        ui.undo_button
-            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
-        onclick.forget();
-    }
-
-    {
-        let redo_sender = ws_sender.clone();
-        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
-            if !redo_sender.is_open() {
-                return;
+            schedule_redraw(&window_cb, &ui_callback, &fill_state);
+            for message in updates {
+                fill_sender.send(&message);
             }
-            redo_sender.send(&ClientMessage::Redo);
         });
-        ui.redo_button
-            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
-        onclick.forget();
+        ui.shape_fill
+            .add_event_listener_with_callback("change", onchange.as_ref().unchecked_ref())?;
+        onchange.forget();
     }
 
     {
@@ -797,8 +2277,14 @@ fn start_app() -> Result<(), JsValue> {
         let save_state = state.clone();
         let ui_callback = ui.clone();
         let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
-            let strokes = { save_state.borrow().strokes.clone() };
-            let payload = SessionFileData { strokes };
+            let payload = {
+                let state = save_state.borrow();
+                SessionFileData {
+                    pages: state.pages_snapshot(),
+                    layers: state.layers.clone(),
+                    access: None,
+                }
+            };
             let bytes = encode_session_file(&payload);
             let array = js_sys::Uint8Array::from(bytes.as_slice());
             let parts = js_sys::Array::new();
@@ -829,18 +2315,114 @@ fn start_app() -> Result<(), JsValue> {
         onclick.forget();
     }
 
+    {
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let expanded = ui_callback.pdf_options.has_attribute("hidden");
+            if expanded {
+                let _ = ui_callback.pdf_options.remove_attribute("hidden");
+            } else {
+                let _ = ui_callback.pdf_options.set_attribute("hidden", "");
+            }
+            let _ = ui_callback
+                .save_pdf_button
+                .set_attribute("aria-expanded", if expanded { "true" } else { "false" });
+        });
+        ui.save_pdf_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
     {
         let save_state = state.clone();
         let ui_callback = ui.clone();
+        let window_cb = window.clone();
         let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
-            let html = build_pdf_html(&save_state.borrow(), false);
-            open_print_window(&ui_callback.document, &html);
+            let pdf = build_pdf(
+                &ui_callback.document,
+                &save_state.borrow(),
+                &ui_callback.pdf_layout.value(),
+                &ui_callback.pdf_paper.value(),
+                ui_callback.pdf_landscape.checked(),
+            );
+            let Some(pdf) = pdf else {
+                let _ = window_cb.alert_with_message(&format!(
+                    "Tiling this board would take more than {MAX_PDF_PAGES} pages. Use the single page layout or a larger paper."
+                ));
+                return;
+            };
+            download_bytes(
+                &ui_callback.document,
+                &pdf,
+                "yumboard.pdf",
+                "application/pdf",
+            );
+            let _ = ui_callback.pdf_options.set_attribute("hidden", "");
+            let _ = ui_callback
+                .save_pdf_button
+                .set_attribute("aria-expanded", "false");
             let _ = ui_callback.save_menu.set_attribute("hidden", "");
             let _ = ui_callback
                 .save_button
                 .set_attribute("aria-expanded", "false");
         });
-        ui.save_pdf_button
+        ui.pdf_download_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let ui_callback = ui.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let expanded = ui_callback.svg_options.has_attribute("hidden");
+            if expanded {
+                let _ = ui_callback.svg_options.remove_attribute("hidden");
+            } else {
+                let _ = ui_callback.svg_options.set_attribute("hidden", "");
+            }
+            let _ = ui_callback
+                .save_svg_button
+                .set_attribute("aria-expanded", if expanded { "true" } else { "false" });
+        });
+        ui.save_svg_button
+            .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
+    {
+        let save_state = state.clone();
+        let ui_callback = ui.clone();
+        let window_cb = window.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let region = ExportRegion::from_value(&ui_callback.svg_region.value());
+            let background = ui_callback.svg_background.checked();
+            let padding = ui_callback.svg_padding.value_as_number().max(0.0);
+            let Some(svg) = build_svg(
+                &ui_callback.document,
+                &save_state.borrow(),
+                region,
+                background,
+                padding,
+            ) else {
+                let _ = window_cb.alert_with_message("Select some strokes to export a selection.");
+                return;
+            };
+            download_bytes(
+                &ui_callback.document,
+                svg.as_bytes(),
+                "yumboard.svg",
+                "image/svg+xml",
+            );
+            let _ = ui_callback.svg_options.set_attribute("hidden", "");
+            let _ = ui_callback
+                .save_svg_button
+                .set_attribute("aria-expanded", "false");
+            let _ = ui_callback.save_menu.set_attribute("hidden", "");
+            let _ = ui_callback
+                .save_button
+                .set_attribute("aria-expanded", "false");
+        });
+        ui.svg_download_button
             .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
         onclick.forget();
     }
@@ -855,15 +2437,23 @@ fn start_app() -> Result<(), JsValue> {
             let Some(target) = target.dyn_into::<web_sys::Node>().ok() else {
                 return;
             };
-            let menu_node: web_sys::Node = ui_callback.save_menu.clone().into();
-            let button_node: web_sys::Node = ui_callback.save_button.clone().into();
-            if menu_node.contains(Some(&target)) || button_node.contains(Some(&target)) {
-                return;
+            let menus = [
+                (&ui_callback.save_menu, &ui_callback.save_button),
+                (&ui_callback.load_menu, &ui_callback.load_button),
+                (&ui_callback.pen_menu, &ui_callback.pen_settings_button),
+                (&ui_callback.shape_menu, &ui_callback.shapes_button),
+                (&ui_callback.eraser_menu, &ui_callback.eraser_button),
+                (&ui_callback.layer_menu, &ui_callback.layers_button),
+            ];
+            for (menu, button) in menus {
+                let menu_node: &web_sys::Node = menu.as_ref();
+                let button_node: &web_sys::Node = button.as_ref();
+                if menu_node.contains(Some(&target)) || button_node.contains(Some(&target)) {
+                    continue;
+                }
+                let _ = menu.set_attribute("hidden", "");
+                let _ = button.set_attribute("aria-expanded", "false");
             }
-            let _ = ui_callback.save_menu.set_attribute("hidden", "");
-            let _ = ui_callback
-                .save_button
-                .set_attribute("aria-expanded", "false");
         });
         ui.document
             .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
@@ -873,26 +2463,68 @@ fn start_app() -> Result<(), JsValue> {
     {
         let ui_callback = ui.clone();
         let load_state = state.clone();
-        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            event.stop_propagation();
             if matches!(load_state.borrow().mode, Mode::Loading(_)) {
                 return;
             }
-            ui_callback.load_file.set_value("");
-            ui_callback.load_file.click();
+            let is_open = !ui_callback.load_menu.has_attribute("hidden");
+            if is_open {
+                let _ = ui_callback.load_menu.set_attribute("hidden", "");
+                let _ = ui_callback
+                    .load_button
+                    .set_attribute("aria-expanded", "false");
+            } else {
+                let _ = ui_callback.load_menu.remove_attribute("hidden");
+                let _ = ui_callback
+                    .load_button
+                    .set_attribute("aria-expanded", "true");
+            }
         });
         ui.load_button
             .add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
         onclick.forget();
     }
 
+    for (button, insert) in [
+        (ui.load_replace_button.clone(), false),
+        (ui.load_insert_button.clone(), true),
+    ] {
+        let ui_callback = ui.clone();
+        let load_state = state.clone();
+        let onclick = Closure::<dyn FnMut(Event)>::new(move |_| {
+            let _ = ui_callback.load_menu.set_attribute("hidden", "");
+            let _ = ui_callback
+                .load_button
+                .set_attribute("aria-expanded", "false");
+            {
+                let mut state = load_state.borrow_mut();
+                if matches!(state.mode, Mode::Loading(_)) {
+                    return;
+                }
+                state.load_insert = insert;
+            }
+            ui_callback.load_file.set_value("");
+            ui_callback.load_file.click();
+        });
+        button.add_event_listener_with_callback("click", onclick.as_ref().unchecked_ref())?;
+        onclick.forget();
+    }
+
     {
         let ui_callback = ui.clone();
         let load_state_onchange = state.clone();
         let load_sender_onchange = ws_sender.clone();
+        let window_load = window.clone();
         let onchange = Closure::<dyn FnMut(Event)>::new(move |_| {
             if !load_sender_onchange.is_open() {
                 return;
             }
+            let insert = load_state_onchange.borrow().load_insert;
+            if !insert && !load_sender_onchange.is_synced() {
+                let _ = window_load.alert_with_message("Reconnect before opening a session.");
+                return;
+            }
             let files = ui_callback.load_file.files();
             let file = files.and_then(|list| list.get(0));
             let Some(file) = file else {
@@ -911,26 +2543,58 @@ fn start_app() -> Result<(), JsValue> {
             let load_state_onload = load_state_onchange.clone();
             let load_sender_onload = load_sender_onchange.clone();
             let ui_onload = ui_callback.clone();
+            let window_onload = window_load.clone();
             let onload = Closure::<dyn FnMut(ProgressEvent)>::new(move |event: ProgressEvent| {
                 if !load_sender_onload.is_open() {
                     ui_onload.set_load_busy(false);
                     return;
                 }
-                let strokes = read_load_payload(&event);
-                {
+                let data = read_load_payload(&event);
+                let insert = {
                     let mut state = load_state_onload.borrow_mut();
                     let Some(previous) = take_loading_previous(&mut state) else {
                         ui_onload.set_load_busy(false);
                         return;
                     };
                     state.mode = previous;
-                    if let Some(strokes) = strokes.as_ref() {
-                        adopt_strokes(&mut state, &ui_onload.ctx, strokes.clone(), true);
+                    if let Some(data) = data.as_ref().filter(|_| !state.load_insert) {
+                        set_layers(&mut state, data.layers.clone());
+                        adopt_pages(&mut state, &ui_onload.ctx, data.pages.clone(), true);
+                        render_page_bar(&ui_onload, &state);
+                        render_layer_menu(&ui_onload, &state);
                     }
-                }
+                    state.load_insert
+                };
                 ui_onload.set_load_busy(false);
-                if let Some(strokes) = strokes {
-                    load_sender_onload.send(&ClientMessage::Load { strokes });
+                let Some(data) = data else {
+                    return;
+                };
+                if insert {
+                    // Every page of the file lands on the page on screen, one below the other.
+                    let strokes = stack_pages(&data.pages);
+                    if strokes.is_empty() {
+                        let _ = window_onload.alert_with_message("This file has no strokes.");
+                        return;
+                    }
+                    let center = view_center(&load_state_onload.borrow());
+                    paste_strokes(
+                        &window_onload,
+                        &ui_onload,
+                        &load_state_onload,
+                        &load_sender_onload,
+                        &strokes,
+                        Some(center),
+                    );
+                    return;
+                }
+                let sent = load_sender_onload.send(&ClientMessage::Load {
+                    pages: data.pages,
+                    layers: data.layers,
+                });
+                if !sent {
+                    let _ = window_onload.alert_with_message(
+                        "The connection was lost, so the session was not opened.",
+                    );
                 }
             });
             reader.set_onload(Some(onload.as_ref().unchecked_ref()));
@@ -994,7 +2658,8 @@ fn start_app() -> Result<(), JsValue> {
                             let id = id.clone();
                             draw.mode = DrawMode::Idle;
                             end_stroke(&mut state, &id);
-                            down_sender.send(&ClientMessage::StrokeEnd { id });
+                            let points = fit_stroke(&mut state, &ui_callback.ctx, &id, None);
+                            down_sender.send(&ClientMessage::StrokeEnd { id, points });
                         }
                     }
                     let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
@@ -1046,10 +2711,6 @@ fn start_app() -> Result<(), JsValue> {
                     state.mode = Mode::Loading(loading);
                 }
                 Mode::Select(mut select) => {
-                    if !down_sender.is_open() {
-                        state.mode = Mode::Select(select);
-                        return;
-                    }
                     let world_point =
                         match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
                             Some(point) => point,
@@ -1078,6 +2739,18 @@ fn start_app() -> Result<(), JsValue> {
                                 let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
                                 return;
                             }
+                            SelectionHit::Order(to) => {
+                                let order = reordered(&state.strokes, &selection_ids, to);
+                                arrange(&mut state.strokes, &order);
+                                state.mode = Mode::Select(select);
+                                drop(state);
+                                schedule_redraw(&window, &ui_callback, &down_state);
+                                down_sender.send(&ClientMessage::Reorder {
+                                    ids: selection_ids,
+                                    to,
+                                });
+                                return;
+                            }
                             SelectionHit::Rotate => {
                                 if let Some(center) = center {
                                     select.mode = SelectMode::Rotate {
@@ -1147,10 +2820,6 @@ fn start_app() -> Result<(), JsValue> {
                     let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
                 }
                 Mode::Erase(_) => {
-                    if !down_sender.is_open() {
-                        state.mode = Mode::Erase(EraseMode::Idle);
-                        return;
-                    }
                     let point =
                         match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
                             Some(point) => point,
@@ -1159,53 +2828,171 @@ fn start_app() -> Result<(), JsValue> {
                                 return;
                             }
                         };
-                    state.mode = Mode::Erase(EraseMode::Active {
-                        hits: HashSet::new(),
-                    });
-                    let removed_ids = erase_hits_at_point(&mut state, &ui_callback.ctx, point);
+                    // Everything one drag erases undoes as one step.
+                    down_sender.send(&ClientMessage::BatchStart);
+                    let removed_ids = if ui_callback.precise_erase_input.checked() {
+                        state.mode = Mode::Erase(EraseMode::Cutting {
+                            last: point,
+                            splits: Vec::new(),
+                        });
+                        cut_strokes_at_point(&mut state, &ui_callback.ctx, point)
+                    } else {
+                        state.mode = Mode::Erase(EraseMode::Active {
+                            hits: HashSet::new(),
+                        });
+                        erase_hits_at_point(&mut state, &ui_callback.ctx, point)
+                    };
                     for id in removed_ids {
                         down_sender.send(&ClientMessage::Erase { id });
                     }
                     let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
                 }
-                Mode::Draw(mut draw) => {
-                    if !down_sender.is_open() {
-                        state.mode = Mode::Draw(draw);
-                        return;
-                    }
+                Mode::Laser(_) => {
+                    let point =
+                        match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
+                            Some(point) => point,
+                            None => {
+                                state.mode = Mode::Laser(LaserMode::Idle);
+                                return;
+                            }
+                        };
+                    state.mode = Mode::Laser(LaserMode::Active {
+                        pointer_id: event.pointer_id(),
+                    });
+                    push_laser_point(&mut state, point, true);
+                    drop(state);
+                    let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
+                    schedule_laser_frame(&window, &ui_callback, &down_state);
+                    schedule_laser_flush(&window, &down_sender, &down_state);
+                }
+                Mode::Shape(mut shape) => {
+                    let point =
+                        match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
+                            Some(point) if layer_editable(&state.layers, state.layer) => point,
+                            _ => {
+                                state.mode = Mode::Shape(shape);
+                                return;
+                            }
+                        };
+                    let stroke = new_shape(
+                        shape.kind,
+                        parse_color(&ui_callback.color_input.value()),
+                        ui_callback.size_input.value_as_number() as f32,
+                        ui_callback.shape_fill.checked(),
+                        point,
+                        state.layer,
+                    );
+                    shape.drag = Some(ShapeDrag {
+                        start: stroke.points[0],
+                        stroke,
+                    });
+                    state.mode = Mode::Shape(shape);
+                    let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
+                }
+                Mode::Text(text) => {
                     let point =
                         match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
                             Some(point) => point,
                             None => {
+                                state.mode = Mode::Text(text);
+                                return;
+                            }
+                        };
+                    state.mode = Mode::Text(text);
+                    let finished = finish_text_edit(&mut state, &down_sender);
+                    let hit = state
+                        .strokes
+                        .iter()
+                        .rev()
+                        .find(|stroke| {
+                            stroke.text.is_some()
+                                && layer_editable(&state.layers, stroke.layer)
+                                && stroke_hit(stroke, screen_x, screen_y, zoom, pan_x, pan_y)
+                        })
+                        .map(|stroke| stroke.id);
+                    // Clicking empty board only closes an open editor; the next click adds a box.
+                    let editing = match hit {
+                        Some(id) => Some(id),
+                        None if finished || !layer_editable(&state.layers, state.layer) => None,
+                        None => {
+                            let stroke = new_text(
+                                parse_color(&ui_callback.color_input.value()),
+                                ui_callback.size_input.value_as_number() as f32,
+                                point,
+                                state.layer,
+                            );
+                            let id = stroke.id;
+                            state.unconfirmed_ids.insert(id);
+                            state.strokes.push(stroke.clone());
+                            down_sender.send(&ClientMessage::TextCreate {
+                                page: state.page,
+                                stroke,
+                            });
+                            Some(id)
+                        }
+                    };
+                    if let Some(id) = editing {
+                        state.active_ids.insert(id);
+                        let content = state
+                            .strokes
+                            .iter()
+                            .find(|stroke| stroke.id == id)
+                            .and_then(|stroke| stroke.text.as_ref())
+                            .map(|text| text.content.clone())
+                            .unwrap_or_default();
+                        ui_callback.text_editor.set_value(&content);
+                    }
+                    if let Mode::Text(text) = &mut state.mode {
+                        text.editing = editing;
+                    }
+                    ui_callback.sync_text_editor(&state);
+                    drop(state);
+                    schedule_redraw(&window, &ui_callback, &down_state);
+                }
+                Mode::Draw(mut draw) => {
+                    let point =
+                        match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
+                            Some(point) if layer_editable(&state.layers, state.layer) => point,
+                            _ => {
                                 state.mode = Mode::Draw(draw);
                                 return;
                             }
                         };
                     let id = make_id();
-                    let color = parse_color(&ui_callback.color_input.value());
-                    let size = sanitize_size(ui_callback.size_input.value_as_number() as f32);
+                    let mut color = parse_color(&ui_callback.color_input.value());
+                    let mut size = ui_callback.size_input.value_as_number() as f32;
+                    let blend = if ui_callback.highlighter_input.checked() {
+                        color.a = HIGHLIGHT_ALPHA;
+                        size *= HIGHLIGHT_SIZE_SCALE;
+                        Blend::Highlight
+                    } else {
+                        Blend::Normal
+                    };
+                    let size = sanitize_size(size);
 
                     state.input_activity = InputActivity::Draw(DrawPointerState {
                         pointer_id: event.pointer_id(),
                         last_timestamp: event.time_stamp(),
+                        last_raw: point,
+                        still_point: point,
+                        still_since: event.time_stamp(),
                     });
 
                     draw.mode = DrawMode::Drawing { id: id.clone() };
                     state.mode = Mode::Draw(draw);
-                    start_stroke(
-                        &mut state,
-                        &ui_callback.ctx,
-                        id.clone(),
-                        color.clone(),
-                        size,
-                        point,
-                    );
+                    state.unconfirmed_ids.insert(id);
+                    let layer = state.layer;
+                    let stroke = new_freehand(id, color, size, point, blend, layer);
+                    start_stroke(&mut state, &ui_callback.ctx, stroke);
 
                     down_sender.send(&ClientMessage::StrokeStart {
+                        page: state.page,
                         id,
                         color,
                         size,
                         point,
+                        blend,
+                        layer,
                     });
                     let _ = ui_callback.canvas.set_pointer_capture(event.pointer_id());
                 }
@@ -1302,9 +3089,6 @@ fn start_app() -> Result<(), JsValue> {
                 let mut state = move_state.borrow_mut();
                 match &mut state.mode {
                     Mode::Select(select) => {
-                        if !move_sender.is_open() {
-                            continue;
-                        }
                         let world_point =
                             match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
                                 Some(point) => point,
@@ -1454,20 +3238,36 @@ fn start_app() -> Result<(), JsValue> {
                             schedule_redraw(&window, &ui_callback, &move_state);
                         }
                     }
-                    Mode::Erase(EraseMode::Active { .. }) => {
-                        if !move_sender.is_open() {
-                            continue;
-                        }
+                    Mode::Erase(erase @ (EraseMode::Active { .. } | EraseMode::Cutting { .. })) => {
+                        let precise = matches!(erase, EraseMode::Cutting { .. });
                         let point =
                             match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
                                 Some(point) => point,
                                 None => continue,
                             };
-                        let removed_ids = erase_hits_at_point(&mut state, &ui_callback.ctx, point);
+                        let removed_ids = if precise {
+                            cut_strokes_at_point(&mut state, &ui_callback.ctx, point)
+                        } else {
+                            erase_hits_at_point(&mut state, &ui_callback.ctx, point)
+                        };
                         for id in removed_ids {
                             move_sender.send(&ClientMessage::Erase { id });
                         }
                     }
+                    Mode::Laser(LaserMode::Active { pointer_id }) => {
+                        if *pointer_id != event.pointer_id() {
+                            continue;
+                        }
+                        let point =
+                            match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
+                                Some(point) => point,
+                                None => continue,
+                            };
+                        push_laser_point(&mut state, point, false);
+                        drop(state);
+                        schedule_laser_frame(&window, &ui_callback, &move_state);
+                        schedule_laser_flush(&window, &move_sender, &move_state);
+                    }
                     Mode::Pan(PanMode::Active {
                         start_x,
                         start_y,
@@ -1481,10 +3281,20 @@ fn start_app() -> Result<(), JsValue> {
                         drop(state);
                         schedule_redraw(&window, &ui_callback, &move_state);
                     }
-                    Mode::Draw(draw) => {
-                        if !move_sender.is_open() {
+                    Mode::Shape(shape) => {
+                        let Some(drag) = &mut shape.drag else {
                             continue;
-                        }
+                        };
+                        let point =
+                            match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
+                                Some(point) => point,
+                                None => continue,
+                            };
+                        drag.stroke.points = shape_points(shape.kind, drag.start, point);
+                        drop(state);
+                        schedule_redraw(&window, &ui_callback, &move_state);
+                    }
+                    Mode::Draw(draw) => {
                         let id = match &draw.mode {
                             DrawMode::Drawing { id } => id.clone(),
                             _ => continue,
@@ -1500,12 +3310,29 @@ fn start_app() -> Result<(), JsValue> {
                             continue;
                         }
                         active.last_timestamp = timestamp;
-                        let point =
+                        let raw =
                             match event_to_point(&ui_callback.canvas, &event, pan_x, pan_y, zoom) {
                                 Some(point) => point,
                                 None => continue,
                             };
+                        active.last_raw = raw;
+                        let moved = (raw.x - active.still_point.x)
+                            .hypot(raw.y - active.still_point.y)
+                            as f64
+                            * zoom;
+                        if moved > HOLD_TOLERANCE {
+                            active.still_point = raw;
+                            active.still_since = timestamp;
+                        }
+                        let previous = state
+                            .strokes
+                            .iter()
+                            .rev()
+                            .find(|stroke| stroke.id == id)
+                            .and_then(|stroke| stroke.points.last().copied());
+                        let point = streamline(previous, raw, state.smoothing);
                         if move_stroke(&mut state, &ui_callback.ctx, &id, point) {
+                            let repaint = needs_repaint(&state, &id);
                             state.pending_points.entry(id).or_default().push(point);
                             let should_schedule = if state.flush_scheduled {
                                 false
@@ -1513,10 +3340,13 @@ fn start_app() -> Result<(), JsValue> {
                                 state.flush_scheduled = true;
                                 true
                             };
+                            drop(state);
                             if should_schedule {
-                                drop(state);
                                 schedule_flush(&move_window, &move_sender, &move_state);
                             }
+                            if repaint {
+                                schedule_redraw(&move_window, &ui_callback, &move_state);
+                            }
                         }
                     }
                     _ => {}
@@ -1559,11 +3389,26 @@ fn start_app() -> Result<(), JsValue> {
                 }
                 return;
             }
+            if matches!(
+                &state.mode,
+                Mode::Text(TextState {
+                    editing: Some(_),
+                    ..
+                })
+            ) {
+                // Focus only once the press is over so the canvas does not take it back.
+                drop(state);
+                event.prevent_default();
+                let _ = ui_callback.text_editor.focus();
+                return;
+            }
             let active = matches!(
                 &state.mode,
                 Mode::Select(_)
-                    | Mode::Erase(EraseMode::Active { .. })
+                    | Mode::Erase(EraseMode::Active { .. } | EraseMode::Cutting { .. })
+                    | Mode::Laser(LaserMode::Active { .. })
                     | Mode::Pan(PanMode::Active { .. })
+                    | Mode::Shape(ShapeState { drag: Some(_), .. })
                     | Mode::Draw(DrawState {
                         mode: DrawMode::Drawing { .. },
                         ..
@@ -1610,11 +3455,46 @@ fn start_app() -> Result<(), JsValue> {
                 }
                 Mode::Erase(EraseMode::Active { .. }) => {
                     state.mode = Mode::Erase(EraseMode::Idle);
+                    stop_sender.send(&ClientMessage::BatchEnd);
+                }
+                Mode::Erase(EraseMode::Cutting { splits, .. }) => {
+                    state.mode = Mode::Erase(EraseMode::Idle);
+                    for message in finish_cuts(&state, splits) {
+                        stop_sender.send(&message);
+                    }
+                    stop_sender.send(&ClientMessage::BatchEnd);
+                }
+                Mode::Laser(LaserMode::Active { .. }) => {
+                    state.mode = Mode::Laser(LaserMode::Idle);
                 }
                 Mode::Pan(PanMode::Active { .. }) => {
                     state.mode = Mode::Pan(PanMode::Idle);
                     ui_callback.set_canvas_mode(&state.mode, false);
                 }
+                Mode::Shape(mut shape) => {
+                    let drag = shape.drag.take();
+                    state.mode = Mode::Shape(shape);
+                    let Some(ShapeDrag { stroke, .. }) = drag else {
+                        return;
+                    };
+                    // A click without a drag would leave an invisible shape behind.
+                    let visible =
+                        geometry::bounds(std::iter::once(&stroke)).is_some_and(|bounds| {
+                            (bounds.max_x - bounds.min_x).max(bounds.max_y - bounds.min_y)
+                                * state.zoom
+                                >= 3.0
+                        });
+                    if visible {
+                        state.unconfirmed_ids.insert(stroke.id);
+                        state.strokes.push(stroke.clone());
+                    }
+                    let page = state.page;
+                    drop(state);
+                    schedule_redraw(&window, &ui_callback, &stop_state);
+                    if visible {
+                        stop_sender.send(&ClientMessage::ShapeCreate { page, stroke });
+                    }
+                }
                 Mode::Draw(mut draw) => {
                     let InputActivity::Draw(active) = &state.input_activity else {
                         state.mode = Mode::Draw(draw);
@@ -1624,6 +3504,9 @@ fn start_app() -> Result<(), JsValue> {
                         state.mode = Mode::Draw(draw);
                         return;
                     }
+                    let last_raw = active.last_raw;
+                    let held = event.type_() == "pointerup"
+                        && event.time_stamp() - active.still_since >= HOLD_TO_SNAP_MS;
                     state.input_activity = InputActivity::None;
                     let id = match &draw.mode {
                         DrawMode::Drawing { id } => id.clone(),
@@ -1635,6 +3518,18 @@ fn start_app() -> Result<(), JsValue> {
                     draw.mode = DrawMode::Idle;
                     state.mode = Mode::Draw(draw);
                     end_stroke(&mut state, &id);
+                    // A recognized shape replaces the stroke as its own step, so the first undo
+                    // brings back the freehand line.
+                    let snapped = if held {
+                        snap_stroke(&mut state, &ui_callback.ctx, &id)
+                    } else {
+                        None
+                    };
+                    let fitted = if snapped.is_none() {
+                        fit_stroke(&mut state, &ui_callback.ctx, &id, Some(last_raw))
+                    } else {
+                        None
+                    };
                     if let Some(mut points) = state.pending_points.remove(&id) {
                         drop(state);
                         const MAX_POINTS_PER_MESSAGE: usize = 128;
@@ -1649,7 +3544,10 @@ fn start_app() -> Result<(), JsValue> {
                     } else {
                         drop(state);
                     }
-                    stop_sender.send(&ClientMessage::StrokeEnd { id });
+                    stop_sender.send(&ClientMessage::StrokeEnd { id, points: fitted });
+                    if let Some(stroke) = snapped {
+                        stop_sender.send(&ClientMessage::StrokeReplace { stroke });
+                    }
                 }
                 other => {
                     state.mode = other;
@@ -1669,6 +3567,59 @@ fn start_app() -> Result<(), JsValue> {
         onstop.forget();
     }
 
+    {
+        let cursor_state = state.clone();
+        let cursor_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let window_cursor = window.clone();
+        let oncursor = Closure::<dyn FnMut(PointerEvent)>::new(move |event: PointerEvent| {
+            if is_touch_event(&event) {
+                return;
+            }
+            let point = {
+                let state = cursor_state.borrow();
+                event_to_point(
+                    &ui_callback.canvas,
+                    &event,
+                    state.pan_x,
+                    state.pan_y,
+                    state.zoom,
+                )
+            };
+            let Some(point) = point else {
+                return;
+            };
+            let color = parse_color(&ui_callback.color_input.value());
+            let mut state = cursor_state.borrow_mut();
+            state.pointer = Some(point);
+            state.pending_cursor = Some((Some(point), color));
+            drop(state);
+            schedule_cursor_flush(&window_cursor, &cursor_sender, &cursor_state);
+        });
+        ui.canvas
+            .add_event_listener_with_callback("pointermove", oncursor.as_ref().unchecked_ref())?;
+        oncursor.forget();
+
+        let leave_state = state.clone();
+        let leave_sender = ws_sender.clone();
+        let ui_callback = ui.clone();
+        let window_leave = window.clone();
+        let onleave = Closure::<dyn FnMut(PointerEvent)>::new(move |event: PointerEvent| {
+            if is_touch_event(&event) {
+                return;
+            }
+            let color = parse_color(&ui_callback.color_input.value());
+            let mut state = leave_state.borrow_mut();
+            state.pointer = None;
+            state.pending_cursor = Some((None, color));
+            drop(state);
+            schedule_cursor_flush(&window_leave, &leave_sender, &leave_state);
+        });
+        ui.canvas
+            .add_event_listener_with_callback("pointerleave", onleave.as_ref().unchecked_ref())?;
+        onleave.forget();
+    }
+
     {
         let zoom_state = state.clone();
         let ui_callback = ui.clone();
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+use yumboard_shared::{LayerId, Page, Point, Stroke};
+
+use crate::geometry::{apply_translation, bounds};
+use crate::state::{Mode, SelectMode, SelectState, State};
+use crate::util::make_id;
+
+/// How far a duplicate lands from its original, in screen pixels.
+pub const DUPLICATE_OFFSET: f64 = 24.0;
+
+/// Space between the pages of an inserted file, in world units.
+const INSERT_PAGE_GAP: f64 = 40.0;
+
+/// Copied strokes as they travel through the system clipboard, as `text/plain` JSON so they
+/// can be pasted into another board.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Fragment {
+    #[serde(rename = "yumboard:strokes")]
+    Strokes { strokes: Vec<Stroke> },
+}
+
+pub fn fragment_text(strokes: &[Stroke]) -> String {
+    serde_json::to_string(&Fragment::Strokes {
+        strokes: strokes.to_vec(),
+    })
+    .unwrap_or_default()
+}
+
+/// The strokes of a copied fragment; `None` for any other clipboard text.
+pub fn parse_fragment(text: &str) -> Option<Vec<Stroke>> {
+    let Fragment::Strokes { strokes } = serde_json::from_str(text).ok()?;
+    (!strokes.is_empty()).then_some(strokes)
+}
+
+/// Copies of `strokes` with fresh ids on `layer`, centered on `center`, or moved by `offset`
+/// along both axes when there is none. The server makes whoever adds them their author.
+pub fn place_copies(
+    strokes: &[Stroke],
+    layer: LayerId,
+    center: Option<Point>,
+    offset: f64,
+) -> Vec<Stroke> {
+    let (dx, dy) = match (center, bounds(strokes.iter())) {
+        (Some(center), Some(bounds)) => (
+            center.x as f64 - (bounds.min_x + bounds.max_x) / 2.0,
+            center.y as f64 - (bounds.min_y + bounds.max_y) / 2.0,
+        ),
+        _ => (offset, offset),
+    };
+    apply_translation(strokes, dx as f32, dy as f32)
+        .into_iter()
+        .map(|mut stroke| {
+            stroke.id = make_id();
+            stroke.layer = layer;
+            stroke.author = None;
+            stroke
+        })
+        .collect()
+}
+
+/// The strokes of every page of an inserted file, each page's drawing placed below the previous
+/// one with their left edges lined up so they do not overlap. Empty pages are skipped.
+pub fn stack_pages(pages: &[Page]) -> Vec<Stroke> {
+    let mut stacked = Vec::new();
+    // Left edge of the stack and top of the next page.
+    let mut next: Option<(f64, f64)> = None;
+    for page in pages {
+        let Some(page_bounds) = bounds(page.strokes.iter()) else {
+            continue;
+        };
+        let (left, top) = *next.get_or_insert((page_bounds.min_x, page_bounds.min_y));
+        stacked.extend(apply_translation(
+            &page.strokes,
+            (left - page_bounds.min_x) as f32,
+            (top - page_bounds.min_y) as f32,
+        ));
+        next = Some((
+            left,
+            top + page_bounds.max_y - page_bounds.min_y + INSERT_PAGE_GAP,
+        ));
+    }
+    stacked
+}
+
+/// Puts pasted copies on top of the page on screen and selects them with the lasso tool, so
+/// they can be moved right away.
+pub fn insert_copies(state: &mut State, strokes: &[Stroke]) {
+    for stroke in strokes {
+        state.unconfirmed_ids.insert(stroke.id);
+    }
+    state.strokes.extend(strokes.iter().cloned());
+    state.mode = Mode::Select(SelectState {
+        selected_ids: strokes.iter().map(|stroke| stroke.id).collect(),
+        mode: SelectMode::Idle,
+    });
+}
@@ -3,38 +3,86 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{
     CanvasRenderingContext2d, Document, Element, HtmlButtonElement, HtmlCanvasElement, HtmlElement,
-    HtmlInputElement, HtmlSpanElement, PointerEvent, Window,
+    HtmlInputElement, HtmlSelectElement, HtmlSpanElement, HtmlTextAreaElement, PointerEvent,
+    Window,
 };
 
-use yumboard_shared::Point;
+use yumboard_shared::text::{text_size, TEXT_FONT_FAMILY, TEXT_LINE_HEIGHT};
+use yumboard_shared::{Point, Role};
 
 use crate::geometry;
 use crate::geometry::normalize_point;
-use crate::state::{Mode, State};
+use crate::state::{Mode, State, TextState};
 
 pub struct Ui {
     pub document: Document,
     pub canvas: HtmlCanvasElement,
     pub ctx: CanvasRenderingContext2d,
+    /// Transparent canvas over the board for laser trails, animated without redrawing strokes.
+    pub overlay: HtmlCanvasElement,
+    pub overlay_ctx: CanvasRenderingContext2d,
     pub color_input: HtmlInputElement,
     pub palette_el: HtmlElement,
     pub size_input: HtmlInputElement,
     pub size_value: HtmlSpanElement,
     pub clear_button: HtmlButtonElement,
+    pub pen_settings_button: HtmlButtonElement,
+    pub pen_menu: HtmlElement,
+    pub smoothing_input: HtmlInputElement,
+    pub highlighter_input: HtmlInputElement,
+    pub shapes_button: HtmlButtonElement,
+    pub shape_menu: HtmlElement,
+    pub shape_fill: HtmlInputElement,
+    pub text_button: HtmlButtonElement,
+    pub text_editor: HtmlTextAreaElement,
     pub save_button: HtmlButtonElement,
     pub save_menu: HtmlElement,
     pub save_session_button: HtmlButtonElement,
     pub save_pdf_button: HtmlButtonElement,
+    pub pdf_options: HtmlElement,
+    pub pdf_layout: HtmlSelectElement,
+    pub pdf_paper: HtmlSelectElement,
+    pub pdf_landscape: HtmlInputElement,
+    pub pdf_download_button: HtmlButtonElement,
+    pub save_svg_button: HtmlButtonElement,
+    pub svg_options: HtmlElement,
+    pub svg_region: HtmlSelectElement,
+    pub svg_background: HtmlInputElement,
+    pub svg_padding: HtmlInputElement,
+    pub svg_download_button: HtmlButtonElement,
     pub load_button: HtmlButtonElement,
+    pub load_menu: HtmlElement,
+    pub load_replace_button: HtmlButtonElement,
+    pub load_insert_button: HtmlButtonElement,
     pub load_file: HtmlInputElement,
     pub lasso_button: HtmlButtonElement,
+    pub laser_button: HtmlButtonElement,
     pub eraser_button: HtmlButtonElement,
+    pub eraser_menu: HtmlElement,
+    pub precise_erase_input: HtmlInputElement,
+    pub layers_button: HtmlButtonElement,
+    pub layer_menu: HtmlElement,
+    pub layer_list: HtmlElement,
+    pub layer_add_button: HtmlButtonElement,
+    pub layer_up_button: HtmlButtonElement,
+    pub layer_down_button: HtmlButtonElement,
+    pub layer_remove_button: HtmlButtonElement,
     pub pan_button: HtmlButtonElement,
     pub home_button: HtmlButtonElement,
     pub undo_button: HtmlButtonElement,
     pub redo_button: HtmlButtonElement,
     pub status_el: Element,
     pub status_text: Element,
+    pub peer_list: HtmlElement,
+    pub hint: HtmlElement,
+    pub share_links: HtmlElement,
+    pub copy_edit_link_button: HtmlButtonElement,
+    pub copy_view_link_button: HtmlButtonElement,
+    pub page_list: HtmlElement,
+    pub page_left_button: HtmlButtonElement,
+    pub page_right_button: HtmlButtonElement,
+    pub page_add_button: HtmlButtonElement,
+    pub page_remove_button: HtmlButtonElement,
     pub reload_banner: HtmlElement,
     pub reload_message: HtmlSpanElement,
     pub reload_button: HtmlButtonElement,
@@ -49,20 +97,58 @@ impl Ui {
             .dyn_into::<CanvasRenderingContext2d>()?;
         ctx.set_line_cap("round");
         ctx.set_line_join("round");
+        let overlay: HtmlCanvasElement = get_element(&document, "overlay")?;
+        let overlay_ctx = overlay
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("Missing overlay context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
         Ok(Self {
             color_input: get_element(&document, "color")?,
             palette_el: get_element(&document, "palette")?,
             size_input: get_element(&document, "size")?,
             size_value: get_element(&document, "sizeValue")?,
             clear_button: get_element(&document, "clear")?,
+            pen_settings_button: get_element(&document, "penSettings")?,
+            pen_menu: get_element(&document, "penMenu")?,
+            smoothing_input: get_element(&document, "smoothing")?,
+            highlighter_input: get_element(&document, "highlighter")?,
+            shapes_button: get_element(&document, "shapes")?,
+            shape_menu: get_element(&document, "shapeMenu")?,
+            shape_fill: get_element(&document, "shapeFill")?,
+            text_button: get_element(&document, "textTool")?,
+            text_editor: get_element(&document, "textEditor")?,
             save_button: get_element(&document, "save")?,
             save_menu: get_element(&document, "saveMenu")?,
             save_session_button: get_element(&document, "saveSession")?,
             save_pdf_button: get_element(&document, "savePdf")?,
+            pdf_options: get_element(&document, "pdfOptions")?,
+            pdf_layout: get_element(&document, "pdfLayout")?,
+            pdf_paper: get_element(&document, "pdfPaper")?,
+            pdf_landscape: get_element(&document, "pdfLandscape")?,
+            pdf_download_button: get_element(&document, "pdfDownload")?,
+            save_svg_button: get_element(&document, "saveSvg")?,
+            svg_options: get_element(&document, "svgOptions")?,
+            svg_region: get_element(&document, "svgRegion")?,
+            svg_background: get_element(&document, "svgBackground")?,
+            svg_padding: get_element(&document, "svgPadding")?,
+            svg_download_button: get_element(&document, "svgDownload")?,
             load_button: get_element(&document, "load")?,
+            load_menu: get_element(&document, "loadMenu")?,
+            load_replace_button: get_element(&document, "loadReplace")?,
+            load_insert_button: get_element(&document, "loadInsert")?,
             load_file: get_element(&document, "loadFile")?,
             lasso_button: get_element(&document, "lasso")?,
+            laser_button: get_element(&document, "laser")?,
             eraser_button: get_element(&document, "eraser")?,
+            eraser_menu: get_element(&document, "eraserMenu")?,
+            precise_erase_input: get_element(&document, "preciseErase")?,
+            layers_button: get_element(&document, "layers")?,
+            layer_menu: get_element(&document, "layerMenu")?,
+            layer_list: get_element(&document, "layerList")?,
+            layer_add_button: get_element(&document, "layerAdd")?,
+            layer_up_button: get_element(&document, "layerUp")?,
+            layer_down_button: get_element(&document, "layerDown")?,
+            layer_remove_button: get_element(&document, "layerRemove")?,
             pan_button: get_element(&document, "pan")?,
             home_button: get_element(&document, "home")?,
             undo_button: get_element(&document, "undo")?,
@@ -73,12 +159,24 @@ impl Ui {
             status_text: document
                 .get_element_by_id("statusText")
                 .ok_or_else(|| JsValue::from_str("Missing status text"))?,
+            peer_list: get_element(&document, "peerList")?,
+            hint: get_element(&document, "hint")?,
+            share_links: get_element(&document, "shareLinks")?,
+            copy_edit_link_button: get_element(&document, "copyEditLink")?,
+            copy_view_link_button: get_element(&document, "copyViewLink")?,
+            page_list: get_element(&document, "pageList")?,
+            page_left_button: get_element(&document, "pageLeft")?,
+            page_right_button: get_element(&document, "pageRight")?,
+            page_add_button: get_element(&document, "pageAdd")?,
+            page_remove_button: get_element(&document, "pageRemove")?,
             reload_banner: get_element(&document, "reloadBanner")?,
             reload_message: get_element(&document, "reloadMessage")?,
             reload_button: get_element(&document, "reloadButton")?,
             document,
             canvas,
             ctx,
+            overlay,
+            overlay_ctx,
         })
     }
 
@@ -92,6 +190,55 @@ impl Ui {
         self.status_text.set_text_content(Some(text));
     }
 
+    pub fn render_peer_list(&self, peers: &[(String, String)]) {
+        self.peer_list.set_inner_html("");
+        if peers.is_empty() {
+            let _ = self.peer_list.set_attribute("hidden", "");
+            return;
+        }
+        for (index, (label, color)) in peers.iter().enumerate() {
+            let Ok(item) = self.document.create_element("li") else {
+                continue;
+            };
+            // The first entry is always the local user, who can click it to rename.
+            if index == 0 {
+                let _ = item.set_attribute("data-self", "");
+                let _ = item.set_attribute("title", "Change your display name");
+            }
+            if let Ok(swatch) = self.document.create_element("span") {
+                let _ = swatch.set_attribute("class", "peer-swatch");
+                let _ = swatch.set_attribute("style", &format!("background: {color}"));
+                let _ = item.append_child(&swatch);
+            }
+            if let Ok(text) = self.document.create_element("span") {
+                text.set_text_content(Some(label));
+                let _ = item.append_child(&text);
+            }
+            let _ = self.peer_list.append_child(&item);
+        }
+        let _ = self.peer_list.remove_attribute("hidden");
+    }
+
+    /// Shows the share buttons the server handed tokens for and hides editing tools for viewers.
+    pub fn set_access(&self, role: Role, can_share_edit: bool, can_share_view: bool) {
+        if let Some(body) = self.document.body() {
+            if role.can_edit() {
+                let _ = body.remove_attribute("data-view-only");
+            } else {
+                let _ = body.set_attribute("data-view-only", "");
+            }
+        }
+        let hint = match role {
+            Role::Owner => "You own this board. Share an edit or view link.",
+            Role::Editor => "Share this tab's URL to sketch together.",
+            Role::Viewer => "View only.",
+        };
+        self.hint.set_text_content(Some(hint));
+        set_hidden(&self.copy_edit_link_button, !can_share_edit);
+        set_hidden(&self.copy_view_link_button, !can_share_view);
+        set_hidden(&self.share_links, !(can_share_edit || can_share_view));
+    }
+
     pub fn set_load_busy(&self, busy: bool) {
         let value = if busy { "true" } else { "false" };
         let _ = self.load_button.set_attribute("aria-busy", value);
@@ -101,12 +248,55 @@ impl Ui {
         let is_pan = matches!(state.mode, Mode::Pan(_));
         let is_erase = matches!(state.mode, Mode::Erase(_));
         let is_select = matches!(state.mode, Mode::Select(_));
+        let is_shape = matches!(state.mode, Mode::Shape(_));
+        let is_text = matches!(state.mode, Mode::Text(_));
+        let is_laser = matches!(state.mode, Mode::Laser(_));
         self.set_tool_button(&self.pan_button, is_pan);
+        self.set_tool_button(&self.shapes_button, is_shape);
+        self.set_tool_button(&self.text_button, is_text);
         self.set_tool_button(&self.eraser_button, is_erase);
         self.set_tool_button(&self.lasso_button, is_select);
+        self.set_tool_button(&self.laser_button, is_laser);
         self.set_canvas_mode(&state.mode, dragging);
     }
 
+    /// Lays the text editor over the text box being edited, or hides it when there is none.
+    pub fn sync_text_editor(&self, state: &State) {
+        let stroke = match &state.mode {
+            Mode::Text(TextState {
+                editing: Some(id), ..
+            }) => state.strokes.iter().find(|stroke| &stroke.id == id),
+            _ => None,
+        };
+        let Some((stroke, text, origin)) = stroke
+            .and_then(|stroke| Some((stroke, stroke.text.as_ref()?, *stroke.points.first()?)))
+        else {
+            if !self.text_editor.hidden() {
+                self.text_editor.set_hidden(true);
+            }
+            return;
+        };
+        let rect = self.canvas.get_bounding_client_rect();
+        let (x, y) = geometry::world_to_screen(state, origin);
+        let (width, height) = text_size(text);
+        let style = self.text_editor.style();
+        let _ = style.set_property("left", &format!("{}px", rect.left() + x));
+        let _ = style.set_property("top", &format!("{}px", rect.top() + y));
+        let _ = style.set_property("width", &format!("{}px", width * state.zoom));
+        let _ = style.set_property("height", &format!("{}px", height * state.zoom));
+        let _ = style.set_property(
+            "font",
+            &format!(
+                "{}px/{} {}",
+                text.font_size as f64 * state.zoom,
+                TEXT_LINE_HEIGHT,
+                TEXT_FONT_FAMILY
+            ),
+        );
+        let _ = style.set_property("color", &stroke.color.to_rgba_css());
+        self.text_editor.set_hidden(false);
+    }
+
     pub fn hide_color_input(&self) {
         self.color_input.set_class_name("hidden-color");
     }
@@ -178,7 +368,8 @@ impl Ui {
                 }
             }
             Mode::Erase(_) => "cell",
-            Mode::Draw(_) => "crosshair",
+            Mode::Draw(_) | Mode::Shape(_) | Mode::Laser(_) => "crosshair",
+            Mode::Text(_) => "text",
             Mode::Select(_) => "default",
             Mode::Loading(_) => "progress",
         };
@@ -188,6 +379,14 @@ impl Ui {
     }
 }
 
+fn set_hidden(element: &Element, hidden: bool) {
+    if hidden {
+        let _ = element.set_attribute("hidden", "");
+    } else {
+        let _ = element.remove_attribute("hidden");
+    }
+}
+
 pub fn get_element<T: JsCast>(document: &Document, id: &str) -> Result<T, JsValue> {
     let element = document
         .get_element_by_id(id)
@@ -267,6 +466,19 @@ pub fn resize_canvas(
     }
 }
 
+/// Matches the overlay's backing store to its size and the device pixel ratio, like the board.
+pub fn resize_overlay(
+    window: &Window,
+    overlay: &HtmlCanvasElement,
+    ctx: &CanvasRenderingContext2d,
+) {
+    let rect = overlay.get_bounding_client_rect();
+    let dpr = window.device_pixel_ratio();
+    overlay.set_width((rect.width() * dpr) as u32);
+    overlay.set_height((rect.height() * dpr) as u32);
+    let _ = ctx.set_transform(dpr, 0.0, 0.0, dpr, 0.0, 0.0);
+}
+
 pub fn event_to_point(
     canvas: &HtmlCanvasElement,
     event: &PointerEvent,
@@ -284,5 +496,6 @@ pub fn event_to_point(
     normalize_point(Point {
         x: x as f32,
         y: y as f32,
+        pressure: (event.pointer_type() == "pen").then(|| event.pressure()),
     })
 }
@@ -1,6 +1,9 @@
-use yumboard_shared::{Point, Stroke};
+use yumboard_shared::pressure::{max_stroke_width, normalize_pressure};
+use yumboard_shared::shape::outline_points;
+use yumboard_shared::text::scale_text;
+use yumboard_shared::{Point, Stroke, ZOrder};
 
-use crate::state::{ScaleAxis, ScaleHandle, SelectState, SelectionHit, State, STROKE_UNIT};
+use crate::state::{ScaleAxis, ScaleHandle, SelectState, SelectionHit, State};
 
 pub struct Bounds {
     pub min_x: f64,
@@ -13,7 +16,10 @@ pub fn normalize_point(point: Point) -> Option<Point> {
     if !point.x.is_finite() || !point.y.is_finite() {
         return None;
     }
-    Some(point)
+    Some(Point {
+        pressure: normalize_pressure(point.pressure),
+        ..point
+    })
 }
 
 pub fn world_to_screen(state: &State, point: Point) -> (f64, f64) {
@@ -28,13 +34,23 @@ fn world_to_screen_transform(zoom: f64, pan_x: f64, pan_y: f64, point: Point) ->
     (x, y)
 }
 
+/// The board point in the middle of the view.
+pub fn view_center(state: &State) -> Point {
+    Point {
+        x: ((state.board_width / 2.0 - state.pan_x) / state.zoom) as f32,
+        y: ((state.board_height / 2.0 - state.pan_y) / state.zoom) as f32,
+        pressure: None,
+    }
+}
+
 pub fn bounds<'a>(strokes: impl Iterator<Item = &'a Stroke>) -> Option<Bounds> {
     let mut min_x = f64::MAX;
     let mut min_y = f64::MAX;
     let mut max_x = f64::MIN;
     let mut max_y = f64::MIN;
     for stroke in strokes {
-        for point in &stroke.points {
+        let outline = outline_points(stroke);
+        for point in outline.as_deref().unwrap_or(&stroke.points) {
             min_x = min_x.min(point.x as f64);
             min_y = min_y.min(point.y as f64);
             max_x = max_x.max(point.x as f64);
@@ -66,9 +82,22 @@ pub fn selection_center(strokes: &[Stroke], select: &SelectState) -> Option<Poin
     Some(Point {
         x: ((bounds.min_x + bounds.max_x) / 2.0) as f32,
         y: ((bounds.min_y + bounds.max_y) / 2.0) as f32,
+        pressure: None,
     })
 }
 
+/// The z-order buttons stacked under the trash handle of a selection whose top right corner is
+/// at (`right`, `top`) on screen, as (order, x, y).
+pub fn order_handles(right: f64, top: f64) -> [(ZOrder, f64, f64); 4] {
+    let x = right + 18.0;
+    [
+        (ZOrder::Front, x, top - 2.0),
+        (ZOrder::Forward, x, top + 14.0),
+        (ZOrder::Backward, x, top + 30.0),
+        (ZOrder::Back, x, top + 46.0),
+    ]
+}
+
 pub fn selection_hit_test(
     strokes: &[Stroke],
     select: &SelectState,
@@ -86,6 +115,7 @@ pub fn selection_hit_test(
         Point {
             x: bounds.min_x as f32,
             y: bounds.min_y as f32,
+            pressure: None,
         },
     );
     let (right, bottom) = world_to_screen_transform(
@@ -95,6 +125,7 @@ pub fn selection_hit_test(
         Point {
             x: bounds.max_x as f32,
             y: bounds.max_y as f32,
+            pressure: None,
         },
     );
     let handle = 10.0;
@@ -103,6 +134,11 @@ pub fn selection_hit_test(
     if hit_rect(screen_x, screen_y, right + 18.0, top - 18.0, handle) {
         return Some(SelectionHit::Trash);
     }
+    for (to, x, y) in order_handles(right, top) {
+        if hit_rect(screen_x, screen_y, x, y, handle) {
+            return Some(SelectionHit::Order(to));
+        }
+    }
     if hit_circle(screen_x, screen_y, center_x, rotate_y, 7.0) {
         return Some(SelectionHit::Rotate);
     }
@@ -112,6 +148,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: bounds.max_x as f32,
                 y: bounds.max_y as f32,
+                pressure: None,
             },
         }));
     }
@@ -121,6 +158,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: bounds.min_x as f32,
                 y: bounds.max_y as f32,
+                pressure: None,
             },
         }));
     }
@@ -130,6 +168,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: bounds.max_x as f32,
                 y: bounds.min_y as f32,
+                pressure: None,
             },
         }));
     }
@@ -139,6 +178,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: bounds.min_x as f32,
                 y: bounds.min_y as f32,
+                pressure: None,
             },
         }));
     }
@@ -150,6 +190,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: ((bounds.min_x + bounds.max_x) / 2.0) as f32,
                 y: bounds.max_y as f32,
+                pressure: None,
             },
         }));
     }
@@ -159,6 +200,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: ((bounds.min_x + bounds.max_x) / 2.0) as f32,
                 y: bounds.min_y as f32,
+                pressure: None,
             },
         }));
     }
@@ -168,6 +210,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: bounds.max_x as f32,
                 y: ((bounds.min_y + bounds.max_y) / 2.0) as f32,
+                pressure: None,
             },
         }));
     }
@@ -177,6 +220,7 @@ pub fn selection_hit_test(
             anchor: Point {
                 x: bounds.min_x as f32,
                 y: ((bounds.min_y + bounds.max_y) / 2.0) as f32,
+                pressure: None,
             },
         }));
     }
@@ -218,12 +262,19 @@ pub fn apply_translation(strokes: &[Stroke], dx: f32, dy: f32) -> Vec<Stroke> {
             id: stroke.id.clone(),
             color: stroke.color.clone(),
             size: stroke.size,
+            shape: stroke.shape,
+            text: stroke.text.clone(),
+            image: stroke.image.clone(),
+            blend: stroke.blend,
+            layer: stroke.layer,
+            author: stroke.author,
             points: stroke
                 .points
                 .iter()
                 .map(|point| Point {
                     x: point.x + dx,
                     y: point.y + dy,
+                    ..*point
                 })
                 .collect(),
         })
@@ -239,12 +290,22 @@ pub fn apply_scale_xy(strokes: &[Stroke], center: Point, sx: f64, sy: f64) -> Ve
             id: stroke.id.clone(),
             color: stroke.color.clone(),
             size: stroke.size,
+            shape: stroke.shape,
+            text: stroke.text.clone().map(|mut text| {
+                scale_text(&mut text, sx, sy);
+                text
+            }),
+            image: stroke.image.clone(),
+            blend: stroke.blend,
+            layer: stroke.layer,
+            author: stroke.author,
             points: stroke
                 .points
                 .iter()
                 .map(|point| Point {
                     x: (cx + (point.x as f64 - cx) * sx) as f32,
                     y: (cy + (point.y as f64 - cy) * sy) as f32,
+                    ..*point
                 })
                 .collect(),
         })
@@ -274,6 +335,12 @@ pub fn apply_rotation(strokes: &[Stroke], center: Point, angle: f64) -> Vec<Stro
             id: stroke.id.clone(),
             color: stroke.color.clone(),
             size: stroke.size,
+            shape: stroke.shape,
+            text: stroke.text.clone(),
+            image: stroke.image.clone(),
+            blend: stroke.blend,
+            layer: stroke.layer,
+            author: stroke.author,
             points: stroke
                 .points
                 .iter()
@@ -283,6 +350,7 @@ pub fn apply_rotation(strokes: &[Stroke], center: Point, angle: f64) -> Vec<Stro
                     Point {
                         x: (cx + dx * cos - dy * sin) as f32,
                         y: (cy + dx * sin + dy * cos) as f32,
+                        ..*point
                     }
                 })
                 .collect(),
@@ -329,14 +397,32 @@ pub fn stroke_hit(stroke: &Stroke, px: f64, py: f64, zoom: f64, pan_x: f64, pan_
     if stroke.points.is_empty() {
         return false;
     }
-    let threshold = (stroke.size as f64 * zoom * STROKE_UNIT / 2.0).max(6.0);
-    if stroke.points.len() == 1 {
-        let point = stroke.points[0];
+    let threshold = (max_stroke_width(stroke) * zoom / 2.0).max(6.0);
+    let outline = outline_points(stroke);
+    let points = outline.as_deref().unwrap_or(&stroke.points);
+    // Filled shapes, text boxes and images are hit anywhere inside, not only on their outline.
+    let filled = stroke.text.is_some()
+        || stroke.image.is_some()
+        || stroke
+            .shape
+            .is_some_and(|shape| shape.fill.is_some() && shape.kind.is_closed());
+    if filled {
+        let inside = Point {
+            x: ((px - pan_x) / zoom) as f32,
+            y: ((py - pan_y) / zoom) as f32,
+            pressure: None,
+        };
+        if point_in_polygon(inside, points) {
+            return true;
+        }
+    }
+    if points.len() == 1 {
+        let point = points[0];
         let dx = point.x as f64 * zoom + pan_x - px;
         let dy = point.y as f64 * zoom + pan_y - py;
         return dx * dx + dy * dy <= threshold * threshold;
     }
-    for window in stroke.points.windows(2) {
+    for window in points.windows(2) {
         let start = window[0];
         let end = window[1];
         let distance = distance_to_segment(
@@ -354,6 +440,84 @@ pub fn stroke_hit(stroke: &Stroke, px: f64, py: f64, zoom: f64, pan_x: f64, pan_
     false
 }
 
+/// Cuts the part of a freehand stroke's ink within `radius` of `center` (all in board units).
+/// Returns the remaining pieces, or `None` when the eraser misses the stroke.
+pub fn cut_stroke(stroke: &Stroke, center: Point, radius: f64) -> Option<Vec<Vec<Point>>> {
+    let reach = radius + max_stroke_width(stroke) / 2.0;
+    let (cx, cy) = (center.x as f64, center.y as f64);
+    let inside = |point: Point| {
+        let (dx, dy) = (point.x as f64 - cx, point.y as f64 - cy);
+        dx * dx + dy * dy <= reach * reach
+    };
+    let first = *stroke.points.first()?;
+    if stroke.points.len() == 1 {
+        return inside(first).then(Vec::new);
+    }
+    let lerp = |a: Point, b: Point, t: f64| Point {
+        x: (a.x as f64 + (b.x - a.x) as f64 * t) as f32,
+        y: (a.y as f64 + (b.y - a.y) as f64 * t) as f32,
+        pressure: match (a.pressure, b.pressure) {
+            (Some(pa), Some(pb)) => Some(pa + (pb - pa) * t as f32),
+            (pressure, _) => pressure,
+        },
+    };
+
+    let mut hit = false;
+    let mut pieces = Vec::new();
+    let mut current = if inside(first) {
+        hit = true;
+        Vec::new()
+    } else {
+        vec![first]
+    };
+    for pair in stroke.points.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        // Solve |a + t (b - a) - center| = reach for the span of the segment under the eraser.
+        let (dx, dy) = ((b.x - a.x) as f64, (b.y - a.y) as f64);
+        let (fx, fy) = (a.x as f64 - cx, a.y as f64 - cy);
+        let qa = dx * dx + dy * dy;
+        let qb = 2.0 * (fx * dx + fy * dy);
+        let qc = fx * fx + fy * fy - reach * reach;
+        let span = if qa <= f64::EPSILON {
+            (qc <= 0.0).then_some((0.0, 1.0))
+        } else {
+            let discriminant = qb * qb - 4.0 * qa * qc;
+            let root = discriminant.max(0.0).sqrt();
+            let t0 = (-qb - root) / (2.0 * qa);
+            let t1 = (-qb + root) / (2.0 * qa);
+            (discriminant > 0.0 && t0 < 1.0 && t1 > 0.0).then(|| (t0.max(0.0), t1.min(1.0)))
+        };
+        let Some((t0, t1)) = span else {
+            current.push(b);
+            continue;
+        };
+        hit = true;
+        if !current.is_empty() {
+            current.push(lerp(a, b, t0));
+            pieces.push(std::mem::take(&mut current));
+        }
+        if t1 < 1.0 {
+            current = vec![lerp(a, b, t1), b];
+        }
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    if !hit {
+        return None;
+    }
+    // Slivers at the cut edges would only render as stray dots.
+    let min_length = radius * 0.1;
+    pieces.retain(|points| {
+        let length: f64 = points
+            .windows(2)
+            .map(|pair| ((pair[1].x - pair[0].x) as f64).hypot((pair[1].y - pair[0].y) as f64))
+            .sum();
+        points.len() >= 2 && length >= min_length
+    });
+    Some(pieces)
+}
+
 pub fn home_zoom_pan(state: &State) -> (f64, f64, f64) {
     let bounds = bounds(state.strokes.iter());
     const STANDARD_DISPLAY_SIZE: f64 = 1000.0;
@@ -0,0 +1,69 @@
+use web_sys::{Storage, Window};
+
+use yumboard_shared::{sanitize_display_name, ClientId, ClientSecret};
+
+use crate::util::{make_client_id, make_client_secret};
+
+const CLIENT_IDENTITY_KEY: &str = "yumboard:client-identity";
+const DISPLAY_NAME_KEY: &str = "yumboard:display-name";
+
+fn local_storage(window: &Window) -> Option<Storage> {
+    window.local_storage().ok().flatten()
+}
+
+/// Returns the id and secret this tab uses across reconnects and reloads, creating them on
+/// first use. Kept in `sessionStorage` so two tabs never share an undo history.
+pub fn load_client_identity(window: &Window) -> (ClientId, ClientSecret) {
+    let storage = window.session_storage().ok().flatten();
+    if let Some(identity) = storage
+        .as_ref()
+        .and_then(|storage| storage.get_item(CLIENT_IDENTITY_KEY).ok().flatten())
+        .and_then(|value| parse_client_identity(&value))
+    {
+        return identity;
+    }
+    let id = make_client_id();
+    let secret = make_client_secret();
+    if let Some(storage) = storage {
+        let (id, secret) = (id.value(), secret.value());
+        let _ = storage.set_item(
+            CLIENT_IDENTITY_KEY,
+            &format!(
+                "{:016x}{:016x}{:016x}{:016x}",
+                id[0], id[1], secret[0], secret[1]
+            ),
+        );
+    }
+    (id, secret)
+}
+
+pub fn load_display_name(window: &Window) -> String {
+    local_storage(window)
+        .and_then(|storage| storage.get_item(DISPLAY_NAME_KEY).ok().flatten())
+        .map(|name| sanitize_display_name(&name))
+        .unwrap_or_default()
+}
+
+pub fn store_display_name(window: &Window, name: &str) {
+    if let Some(storage) = local_storage(window) {
+        if name.is_empty() {
+            let _ = storage.remove_item(DISPLAY_NAME_KEY);
+        } else {
+            let _ = storage.set_item(DISPLAY_NAME_KEY, name);
+        }
+    }
+}
+
+fn parse_client_identity(value: &str) -> Option<(ClientId, ClientSecret)> {
+    if value.len() != 64 || !value.is_ascii() {
+        return None;
+    }
+    let mut words = [0u64; 4];
+    for (index, word) in words.iter_mut().enumerate() {
+        *word = u64::from_str_radix(&value[index * 16..(index + 1) * 16], 16).ok()?;
+    }
+    Some((
+        ClientId::new([words[0], words[1]]),
+        ClientSecret::new([words[2], words[3]]),
+    ))
+}
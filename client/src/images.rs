@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    Blob, CanvasRenderingContext2d, Document, File, FileList, HtmlCanvasElement, HtmlImageElement,
+    Url, Window, XmlHttpRequest,
+};
+
+use yumboard_shared::image::{AssetInfo, MAX_IMAGE_SIDE};
+use yumboard_shared::pdf::RasterImage;
+
+use crate::net::asset_url;
+
+type UploadDone = Rc<RefCell<Option<Box<dyn FnOnce(Result<AssetInfo, String>)>>>>;
+
+/// The first image among dropped or pasted files.
+pub fn image_file(files: Option<FileList>) -> Option<File> {
+    let files = files?;
+    (0..files.length())
+        .filter_map(|index| files.get(index))
+        .find(|file| file.type_().starts_with("image/"))
+}
+
+/// Decodes `file`, re-encodes it as a PNG no larger than `MAX_IMAGE_SIDE` on either side and
+/// uploads it to the board's assets. `done` gets the stored asset or a message for the user.
+pub fn upload_image(
+    window: &Window,
+    file: &File,
+    done: impl FnOnce(Result<AssetInfo, String>) + 'static,
+) {
+    let done: UploadDone = Rc::new(RefCell::new(Some(Box::new(done))));
+    let (Some(url), Some(document)) = (asset_url(window, None), window.document()) else {
+        report(
+            &done,
+            Err("Images can only be added to a shared board.".into()),
+        );
+        return;
+    };
+    let (Ok(image), Ok(src)) = (
+        HtmlImageElement::new(),
+        Url::create_object_url_with_blob(file),
+    ) else {
+        report(&done, Err("Could not read the image.".into()));
+        return;
+    };
+    let onload = {
+        let image = image.clone();
+        let src = src.clone();
+        let done = done.clone();
+        Closure::once_into_js(move || {
+            let _ = Url::revoke_object_url(&src);
+            let Some((canvas, _)) = scaled_canvas(&document, &image) else {
+                report(&done, Err("Could not read the image.".into()));
+                return;
+            };
+            let blob_done = done.clone();
+            let callback = Closure::once_into_js(move |blob: JsValue| match blob.dyn_into() {
+                Ok(blob) => post_asset(&url, &blob, blob_done),
+                Err(_) => report(&blob_done, Err("Could not encode the image.".into())),
+            });
+            if canvas.to_blob(callback.unchecked_ref()).is_err() {
+                report(&done, Err("Could not encode the image.".into()));
+            }
+        })
+    };
+    let onerror = {
+        let src = src.clone();
+        let done = done.clone();
+        Closure::once_into_js(move || {
+            let _ = Url::revoke_object_url(&src);
+            report(
+                &done,
+                Err("This file is not an image the browser can read.".into()),
+            );
+        })
+    };
+    image.set_onload(Some(onload.unchecked_ref()));
+    image.set_onerror(Some(onerror.unchecked_ref()));
+    image.set_src(&src);
+}
+
+/// Starts fetching a stored asset; `on_load` runs once it can be drawn.
+pub fn load_asset_image(
+    window: &Window,
+    hash: &str,
+    on_load: impl FnOnce() + 'static,
+) -> Option<HtmlImageElement> {
+    let url = asset_url(window, Some(hash))?;
+    let image = HtmlImageElement::new().ok()?;
+    let onload = Closure::once_into_js(on_load);
+    image.set_onload(Some(onload.unchecked_ref()));
+    image.set_src(&url);
+    Some(image)
+}
+
+/// Whether `image` finished loading and has pixels to draw.
+pub fn is_ready(image: &HtmlImageElement) -> bool {
+    image.complete() && image.natural_width() > 0
+}
+
+/// A PNG `data:` URL of a loaded image, for standalone SVG exports.
+pub fn image_data_url(document: &Document, image: &HtmlImageElement) -> Option<String> {
+    let (canvas, _) = scaled_canvas(document, image)?;
+    canvas.to_data_url_with_type("image/png").ok()
+}
+
+/// The pixels of a loaded image, for PDF exports.
+pub fn image_pixels(document: &Document, image: &HtmlImageElement) -> Option<RasterImage> {
+    let (canvas, ctx) = scaled_canvas(document, image)?;
+    let (width, height) = (canvas.width(), canvas.height());
+    let data = ctx
+        .get_image_data(0.0, 0.0, width as f64, height as f64)
+        .ok()?;
+    Some(RasterImage {
+        width,
+        height,
+        rgba: data.data().0,
+    })
+}
+
+fn report(done: &UploadDone, result: Result<AssetInfo, String>) {
+    if let Some(done) = done.borrow_mut().take() {
+        done(result);
+    }
+}
+
+fn post_asset(url: &str, blob: &Blob, done: UploadDone) {
+    let Ok(request) = XmlHttpRequest::new() else {
+        report(&done, Err("Upload failed.".into()));
+        return;
+    };
+    if request.open_with_async("POST", url, true).is_err() {
+        report(&done, Err("Upload failed.".into()));
+        return;
+    }
+    let _ = request.set_request_header("Content-Type", "image/png");
+    let onload = {
+        let request = request.clone();
+        let done = done.clone();
+        Closure::once_into_js(move || {
+            let body = request.response_text().ok().flatten();
+            let result = match (request.status().unwrap_or(0), body) {
+                (200, Some(body)) => serde_json::from_str::<AssetInfo>(&body)
+                    .map_err(|_| "Unexpected upload response.".to_string()),
+                (403, _) => Err("This link cannot add images.".into()),
+                (413, _) => Err("The image is too large.".into()),
+                (status, _) => Err(format!("Upload failed ({status}).")),
+            };
+            report(&done, result);
+        })
+    };
+    let onerror = {
+        let done = done.clone();
+        Closure::once_into_js(move || report(&done, Err("Upload failed.".into())))
+    };
+    request.set_onload(Some(onload.unchecked_ref()));
+    request.set_onerror(Some(onerror.unchecked_ref()));
+    if request.send_with_opt_blob(Some(blob)).is_err() {
+        report(&done, Err("Upload failed.".into()));
+    }
+}
+
+/// A canvas holding `image` at its natural size, shrunk to fit `MAX_IMAGE_SIDE`.
+fn scaled_canvas(
+    document: &Document,
+    image: &HtmlImageElement,
+) -> Option<(HtmlCanvasElement, CanvasRenderingContext2d)> {
+    let (width, height) = (image.natural_width(), image.natural_height());
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let scale = (MAX_IMAGE_SIDE as f64 / width.max(height) as f64).min(1.0);
+    let width = ((width as f64 * scale).round() as u32).max(1);
+    let height = ((height as f64 * scale).round() as u32).max(1);
+    let canvas = document
+        .create_element("canvas")
+        .ok()?
+        .dyn_into::<HtmlCanvasElement>()
+        .ok()?;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let ctx = canvas
+        .get_context("2d")
+        .ok()??
+        .dyn_into::<CanvasRenderingContext2d>()
+        .ok()?;
+    ctx.draw_image_with_html_image_element_and_dw_and_dh(
+        image,
+        0.0,
+        0.0,
+        width as f64,
+        height as f64,
+    )
+    .ok()?;
+    Some((canvas, ctx))
+}
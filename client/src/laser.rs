@@ -0,0 +1,92 @@
+use web_sys::CanvasRenderingContext2d;
+use yumboard_shared::{PeerId, Point};
+
+use crate::geometry::world_to_screen;
+use crate::state::State;
+
+/// How long (ms) a laser sample stays visible.
+pub const LASER_FADE_MS: f64 = 1500.0;
+const LASER_RGB: (u8, u8, u8) = (0xff, 0x2d, 0x2d);
+/// Trail width in screen pixels, independent of zoom.
+const LASER_WIDTH: f64 = 4.0;
+
+/// A fading laser pointer trail, drawn on the overlay canvas only.
+pub struct LaserTrail {
+    /// `None` for this client's own pointer.
+    pub peer: Option<PeerId>,
+    /// Samples in board coordinates with the time (ms) they were added.
+    pub points: Vec<(Point, f64)>,
+}
+
+/// Appends samples to the newest trail of `peer`, or to a new one when `start` is set.
+pub fn add_laser_points(
+    state: &mut State,
+    peer: Option<PeerId>,
+    points: &[Point],
+    start: bool,
+    now: f64,
+) {
+    let index = if start {
+        None
+    } else {
+        state.lasers.iter().rposition(|trail| trail.peer == peer)
+    };
+    let index = index.unwrap_or_else(|| {
+        state.lasers.push(LaserTrail {
+            peer,
+            points: Vec::new(),
+        });
+        state.lasers.len() - 1
+    });
+    state.lasers[index]
+        .points
+        .extend(points.iter().map(|point| (*point, now)));
+}
+
+/// Drops expired samples and repaints the overlay; each segment fades with the age of its newer
+/// end. Returns whether any trail is still visible, i.e. whether to keep animating.
+pub fn draw_lasers(ctx: &CanvasRenderingContext2d, state: &mut State, now: f64) -> bool {
+    for trail in &mut state.lasers {
+        trail.points.retain(|(_, time)| now - time < LASER_FADE_MS);
+    }
+    state.lasers.retain(|trail| !trail.points.is_empty());
+    ctx.clear_rect(0.0, 0.0, state.board_width, state.board_height);
+    if state.lasers.is_empty() {
+        return false;
+    }
+    let (r, g, b) = LASER_RGB;
+    let color = |alpha: f64| format!("rgba({r}, {g}, {b}, {alpha:.3})");
+    let fade = |time: f64| (1.0 - (now - time) / LASER_FADE_MS).clamp(0.0, 1.0);
+    ctx.save();
+    ctx.set_line_cap("round");
+    ctx.set_line_join("round");
+    ctx.set_line_width(LASER_WIDTH);
+    ctx.set_shadow_blur(LASER_WIDTH * 2.0);
+    for trail in &state.lasers {
+        for pair in trail.points.windows(2) {
+            let (from, to) = (
+                world_to_screen(state, pair[0].0),
+                world_to_screen(state, pair[1].0),
+            );
+            let alpha = fade(pair[1].1);
+            ctx.set_stroke_style_str(&color(alpha));
+            ctx.set_shadow_color(&color(alpha));
+            ctx.begin_path();
+            ctx.move_to(from.0, from.1);
+            ctx.line_to(to.0, to.1);
+            ctx.stroke();
+        }
+        let Some((head, time)) = trail.points.last() else {
+            continue;
+        };
+        let (x, y) = world_to_screen(state, *head);
+        let alpha = fade(*time);
+        ctx.set_fill_style_str(&color(alpha));
+        ctx.set_shadow_color(&color(alpha));
+        ctx.begin_path();
+        let _ = ctx.arc(x, y, LASER_WIDTH, 0.0, std::f64::consts::PI * 2.0);
+        ctx.fill();
+    }
+    ctx.restore();
+    true
+}
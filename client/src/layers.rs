@@ -0,0 +1,174 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, Event, HtmlElement, HtmlInputElement};
+
+use yumboard_shared::layer::layer_index;
+use yumboard_shared::{Layer, LayerId, MAX_LAYERS};
+
+use crate::state::{Mode, SelectMode, State};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LayerAction {
+    Select,
+    ToggleVisible,
+    ToggleLocked,
+}
+
+/// Whether tools may touch strokes on `layer` here: it is shown and not locked. Strokes on
+/// layers missing from the list are treated as being on the bottom one.
+pub fn layer_editable(layers: &[Layer], layer: LayerId) -> bool {
+    let layer = layers
+        .iter()
+        .find(|item| item.id == layer)
+        .or_else(|| layers.first());
+    layer.is_none_or(|layer| layer.visible && !layer.locked)
+}
+
+/// Lists the layers top first, one row each with a name button and show/lock checkboxes.
+pub fn render_layers(
+    document: &Document,
+    list_el: &HtmlElement,
+    layers: &[Layer],
+    active: LayerId,
+) {
+    list_el.set_inner_html("");
+    for (index, layer) in layers.iter().enumerate().rev() {
+        let Ok(row) = document.create_element("div") else {
+            continue;
+        };
+        let class_name = if layer.id == active {
+            "layer-row active"
+        } else {
+            "layer-row"
+        };
+        let _ = row.set_attribute("class", class_name);
+        let _ = row.set_attribute("data-layer-index", &index.to_string());
+
+        if let Ok(name) = document.create_element("button") {
+            let _ = name.set_attribute("type", "button");
+            let _ = name.set_attribute("class", "button-base layer-name");
+            let _ = name.set_attribute("data-layer-action", "select");
+            let _ = name.set_attribute("title", "Double-click to rename");
+            name.set_text_content(Some(&layer.name));
+            let _ = row.append_child(&name);
+        }
+        for (action, label, checked) in [
+            ("visible", "Show", layer.visible),
+            ("locked", "Lock", layer.locked),
+        ] {
+            let Ok(label_el) = document.create_element("label") else {
+                continue;
+            };
+            let Ok(input) = document.create_element("input") else {
+                continue;
+            };
+            let Ok(input) = input.dyn_into::<HtmlInputElement>() else {
+                continue;
+            };
+            input.set_type("checkbox");
+            input.set_checked(checked);
+            let _ = input.set_attribute("data-layer-action", action);
+            let _ = label_el.append_child(&input);
+            let _ = label_el.append_with_str_1(label);
+            let _ = row.append_child(&label_el);
+        }
+        let _ = list_el.append_child(&row);
+    }
+}
+
+pub fn layer_action_from_event(event: &Event) -> Option<(usize, LayerAction)> {
+    let element = event.target()?.dyn_into::<Element>().ok()?;
+    let control = element.closest("[data-layer-action]").ok()??;
+    let action = match control.get_attribute("data-layer-action")?.as_str() {
+        "select" => LayerAction::Select,
+        "visible" => LayerAction::ToggleVisible,
+        "locked" => LayerAction::ToggleLocked,
+        _ => return None,
+    };
+    let row = control.closest("[data-layer-index]").ok()??;
+    let index = row.get_attribute("data-layer-index")?.parse().ok()?;
+    Some((index, action))
+}
+
+/// Adopts the layer list from the server. A missing active layer falls back to the topmost
+/// one that can be drawn on, and the selection drops strokes that can no longer be edited.
+pub fn set_layers(state: &mut State, mut layers: Vec<Layer>) {
+    if layers.is_empty() {
+        layers.push(Layer::base());
+    }
+    state.layers = layers;
+    if !state.layers.iter().any(|layer| layer.id == state.layer) {
+        state.layer = state
+            .layers
+            .iter()
+            .rev()
+            .find(|layer| layer.visible && !layer.locked)
+            .or_else(|| state.layers.last())
+            .map(|layer| layer.id)
+            .unwrap_or_default();
+    }
+    let layers = &state.layers;
+    let strokes = &state.strokes;
+    if let Mode::Select(select) = &mut state.mode {
+        select.selected_ids.retain(|id| {
+            strokes
+                .iter()
+                .find(|stroke| &stroke.id == id)
+                .is_some_and(|stroke| layer_editable(layers, stroke.layer))
+        });
+        if select.selected_ids.is_empty() {
+            select.mode = SelectMode::Idle;
+        }
+    }
+}
+
+pub fn add_layer_local(state: &mut State, layer: Layer, index: usize) -> bool {
+    if state.layers.len() >= MAX_LAYERS || state.layers.iter().any(|item| item.id == layer.id) {
+        return false;
+    }
+    let index = index.min(state.layers.len());
+    let mut layers = state.layers.clone();
+    layers.insert(index, layer);
+    set_layers(state, layers);
+    true
+}
+
+pub fn update_layer_local(state: &mut State, layer: Layer) -> bool {
+    let mut layers = state.layers.clone();
+    let Some(slot) = layers.iter_mut().find(|item| item.id == layer.id) else {
+        return false;
+    };
+    *slot = layer;
+    set_layers(state, layers);
+    true
+}
+
+pub fn move_layer_local(state: &mut State, layer: LayerId, index: usize) -> bool {
+    let Some(from) = state.layers.iter().position(|item| item.id == layer) else {
+        return false;
+    };
+    let moved = state.layers.remove(from);
+    let index = index.min(state.layers.len());
+    state.layers.insert(index, moved);
+    true
+}
+
+/// Deletes a layer that has no strokes on any page. The last layer is kept.
+pub fn remove_layer_local(state: &mut State, layer: LayerId) -> bool {
+    let used = state
+        .strokes
+        .iter()
+        .chain(state.pages.iter().flat_map(|page| &page.strokes))
+        .any(|stroke| stroke.layer == layer);
+    if used || state.layers.len() <= 1 {
+        return false;
+    }
+    let mut layers = state.layers.clone();
+    layers.retain(|item| item.id != layer);
+    set_layers(state, layers);
+    true
+}
+
+/// Position of the active layer from the bottom.
+pub fn active_index(state: &State) -> usize {
+    layer_index(&state.layers, state.layer)
+}
@@ -1,11 +1,19 @@
 mod actions;
 mod app;
+mod clipboard;
 mod dom;
 mod geometry;
+mod identity;
+mod images;
+mod laser;
+mod layers;
 mod net;
+mod pages;
 mod palette;
 mod persistence;
+mod recognize;
 mod render;
+mod smoothing;
 mod state;
 mod util;
 mod ws;
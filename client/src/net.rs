@@ -13,13 +13,49 @@ pub fn websocket_url(window: &Window) -> Result<String, JsValue> {
         format!("{}:{}", format_host(&hostname), port)
     };
     let session_id = session_id_from_location(&location);
+    let query = match access_token(&location) {
+        Some(token) => format!("?token={token}"),
+        None => String::new(),
+    };
     if let Some(session_id) = session_id {
-        Ok(format!("{scheme}://{host}/ws/{session_id}"))
+        Ok(format!("{scheme}://{host}/ws/{session_id}{query}"))
     } else {
-        Ok(format!("{scheme}://{host}/ws"))
+        Ok(format!("{scheme}://{host}/ws{query}"))
     }
 }
 
+/// The page URL with its access token swapped for `token`.
+pub fn share_url(window: &Window, token: &str) -> Option<String> {
+    let location = window.location();
+    let origin = location.origin().ok()?;
+    let session_id = session_id_from_location(&location)?;
+    let token = js_sys::encode_uri_component(token);
+    Some(format!("{origin}/s/{session_id}?token={token}"))
+}
+
+/// Where images of this board are uploaded (`POST`) and, with the hash appended, fetched.
+pub fn asset_url(window: &Window, hash: Option<&str>) -> Option<String> {
+    let location = window.location();
+    let session_id = session_id_from_location(&location)?;
+    let hash = hash.map(|hash| format!("/{hash}")).unwrap_or_default();
+    let query = match access_token(&location) {
+        Some(token) => format!("?token={token}"),
+        None => String::new(),
+    };
+    Some(format!("/api/sessions/{session_id}/assets{hash}{query}"))
+}
+
+fn access_token(location: &web_sys::Location) -> Option<String> {
+    let search = location.search().ok()?;
+    search.trim_start_matches('?').split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        if parts.next()? != "token" {
+            return None;
+        }
+        Some(parts.next().unwrap_or("").to_string()).filter(|token| !token.is_empty())
+    })
+}
+
 fn format_host(hostname: &str) -> String {
     if hostname.contains(':') && !hostname.starts_with('[') {
         format!("[{hostname}]")
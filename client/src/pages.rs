@@ -0,0 +1,181 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, Event, HtmlButtonElement, HtmlElement};
+
+use yumboard_shared::{ClientMessage, Page, PageId, ServerMessage, StrokeId, MAX_PAGES};
+
+use crate::state::{Mode, SelectMode, State};
+
+pub fn render_pages(document: &Document, list_el: &HtmlElement, count: usize, current: usize) {
+    list_el.set_inner_html("");
+    for index in 0..count {
+        let Ok(element) = document.create_element("button") else {
+            continue;
+        };
+        let Ok(button) = element.dyn_into::<HtmlButtonElement>() else {
+            continue;
+        };
+        let _ = button.set_attribute("type", "button");
+        let _ = button.set_attribute("data-index", &index.to_string());
+        let _ = button.set_attribute("aria-label", &format!("Page {}", index + 1));
+        let class_name = if index == current {
+            "button-base page-tab active"
+        } else {
+            "button-base page-tab"
+        };
+        let _ = button.set_attribute("class", class_name);
+        button.set_text_content(Some(&(index + 1).to_string()));
+        let _ = list_el.append_child(&button);
+    }
+}
+
+pub fn page_index_from_event(event: &Event) -> Option<usize> {
+    let element = event.target()?.dyn_into::<Element>().ok()?;
+    let button = element.closest("[data-index]").ok()??;
+    button.get_attribute("data-index")?.parse().ok()
+}
+
+pub fn current_index(state: &State) -> usize {
+    state
+        .pages
+        .iter()
+        .position(|page| page.id == state.page)
+        .unwrap_or(0)
+}
+
+pub fn page_of(state: &State, id: &StrokeId) -> Option<PageId> {
+    if state.strokes.iter().any(|stroke| &stroke.id == id) {
+        return Some(state.page);
+    }
+    state
+        .pages
+        .iter()
+        .find(|page| page.strokes.iter().any(|stroke| &stroke.id == id))
+        .map(|page| page.id)
+}
+
+/// The page a board message edits. Messages naming a stroke by id are looked up by it.
+pub fn message_page(state: &State, message: &ServerMessage) -> Option<PageId> {
+    match message {
+        ServerMessage::StrokeStart { page, .. }
+        | ServerMessage::Clear { page }
+        | ServerMessage::StrokeRestore { page, .. }
+        | ServerMessage::StrokeSplice { page, .. }
+        | ServerMessage::Reorder { page, .. }
+        | ServerMessage::ShapeCreate { page, .. }
+        | ServerMessage::TextCreate { page, .. }
+        | ServerMessage::ImageCreate { page, .. }
+        | ServerMessage::StrokesRejected { page, .. } => Some(*page),
+        ServerMessage::StrokeMove { id, .. }
+        | ServerMessage::StrokePoints { id, .. }
+        | ServerMessage::StrokeEnd { id }
+        | ServerMessage::StrokeRemove { id }
+        | ServerMessage::ShapeUpdate { id, .. }
+        | ServerMessage::TextUpdate { id, .. } => page_of(state, id),
+        ServerMessage::StrokeReplace { stroke } => page_of(state, &stroke.id),
+        ServerMessage::TransformUpdate { ids, .. } => page_of(state, ids.first()?),
+        _ => None,
+    }
+}
+
+/// Swaps the strokes on screen with those of `page`; swapping again restores them. This lets
+/// edits to a page in the background go through the same handlers as the page on screen, as
+/// long as nothing draws until they are swapped back. Returns false if there is no such page.
+pub fn swap_page_strokes(state: &mut State, page: PageId) -> bool {
+    let Some(page) = state.pages.iter_mut().find(|item| item.id == page) else {
+        return false;
+    };
+    std::mem::swap(&mut state.strokes, &mut page.strokes);
+    true
+}
+
+/// Puts `page` on screen, stashing the strokes of the page shown so far. The selection and
+/// laser trails belong to the old page and are dropped. Returns whether the page changed.
+pub fn show_page(state: &mut State, page: PageId) -> bool {
+    if page == state.page {
+        return false;
+    }
+    let Some(index) = state.pages.iter().position(|item| item.id == page) else {
+        return false;
+    };
+    let strokes = std::mem::take(&mut state.pages[index].strokes);
+    let shown = std::mem::replace(&mut state.strokes, strokes);
+    if let Some(previous) = state.pages.iter_mut().find(|item| item.id == state.page) {
+        previous.strokes = shown;
+    }
+    state.page = page;
+    if let Mode::Select(select) = &mut state.mode {
+        select.selected_ids.clear();
+        select.mode = SelectMode::Idle;
+    }
+    state.lasers.clear();
+    true
+}
+
+pub fn add_page_local(state: &mut State, page: PageId, index: usize) -> bool {
+    if state.pages.len() >= MAX_PAGES || state.pages.iter().any(|item| item.id == page) {
+        return false;
+    }
+    let index = index.min(state.pages.len());
+    state.pages.insert(index, Page::new(page));
+    true
+}
+
+/// Deletes a page, first moving to its neighbor if it is on screen. The last page is kept.
+pub fn remove_page_local(state: &mut State, page: PageId) -> bool {
+    let Some(index) = state.pages.iter().position(|item| item.id == page) else {
+        return false;
+    };
+    if state.pages.len() <= 1 {
+        return false;
+    }
+    if page == state.page {
+        let neighbor = if index + 1 < state.pages.len() {
+            index + 1
+        } else {
+            index - 1
+        };
+        let neighbor = state.pages[neighbor].id;
+        show_page(state, neighbor);
+    }
+    let removed = state.pages.remove(index);
+    for stroke in &removed.strokes {
+        state.active_ids.remove(&stroke.id);
+        state.unconfirmed_ids.remove(&stroke.id);
+        state.pending_points.remove(&stroke.id);
+    }
+    true
+}
+
+pub fn move_page_local(state: &mut State, page: PageId, index: usize) -> bool {
+    let Some(from) = state.pages.iter().position(|item| item.id == page) else {
+        return false;
+    };
+    let moved = state.pages.remove(from);
+    let index = index.min(state.pages.len());
+    state.pages.insert(index, moved);
+    true
+}
+
+/// Applies page edits still waiting in the outbox to a server snapshot, the way the server will
+/// once they arrive.
+pub fn replay_page_edits(pages: &mut Vec<Page>, edits: &[ClientMessage]) {
+    for edit in edits {
+        match edit {
+            ClientMessage::PageAdd { page, index } => {
+                if pages.len() < MAX_PAGES && !pages.iter().any(|item| item.id == *page) {
+                    pages.insert((*index).min(pages.len()), Page::new(*page));
+                }
+            }
+            ClientMessage::PageRemove { page } if pages.len() > 1 => {
+                pages.retain(|item| item.id != *page);
+            }
+            ClientMessage::PageMove { page, index } => {
+                if let Some(from) = pages.iter().position(|item| item.id == *page) {
+                    let moved = pages.remove(from);
+                    pages.insert((*index).min(pages.len()), moved);
+                }
+            }
+            _ => {}
+        }
+    }
+}
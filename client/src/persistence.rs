@@ -1,14 +1,36 @@
-use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+
 use wasm_bindgen::JsCast;
-use web_sys::{Document, Event, HtmlIFrameElement};
+use web_sys::{Blob, BlobPropertyBag, Document, HtmlAnchorElement, HtmlImageElement, Url};
+
+use yumboard_shared::pdf::{render_pdf, PaperSize, PdfLayout, PdfOptions};
+use yumboard_shared::svg::{render_svg_document, SvgOptions, SvgRect};
+use yumboard_shared::{decode_session_file, Page, PageId, SessionFileData, Stroke};
 
-use yumboard_shared::{decode_session_file, SessionFileData, Stroke};
+use crate::geometry::selected_strokes;
+use crate::images::{image_data_url, image_pixels, is_ready};
+use crate::state::{Mode, State};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportRegion {
+    Board,
+    Viewport,
+    Selection,
+}
 
-use crate::state::{State, STROKE_UNIT};
+impl ExportRegion {
+    pub fn from_value(value: &str) -> Self {
+        match value {
+            "viewport" => ExportRegion::Viewport,
+            "selection" => ExportRegion::Selection,
+            _ => ExportRegion::Board,
+        }
+    }
+}
 
-pub fn parse_load_payload_bytes(bytes: &[u8]) -> Option<Vec<Stroke>> {
-    if let Ok(SessionFileData { strokes }) = decode_session_file(bytes) {
-        return Some(strokes);
+pub fn parse_load_payload_bytes(bytes: &[u8]) -> Option<SessionFileData> {
+    if let Ok(data) = decode_session_file(bytes) {
+        return Some(data);
     }
     let Ok(text) = String::from_utf8(bytes.to_vec()) else {
         return None;
@@ -16,33 +38,44 @@ pub fn parse_load_payload_bytes(bytes: &[u8]) -> Option<Vec<Stroke>> {
     parse_load_payload_text(&text)
 }
 
-pub fn parse_load_payload_text(text: &str) -> Option<Vec<Stroke>> {
-    if let Some(strokes) = try_parse_strokes(text) {
-        return Some(strokes);
+pub fn parse_load_payload_text(text: &str) -> Option<SessionFileData> {
+    if let Some(data) = try_parse_session(text) {
+        return Some(data);
     }
     let trimmed = text.trim();
     if let Some(payload) = extract_data_url_payload(trimmed) {
-        if let Some(strokes) = try_parse_strokes(&payload) {
-            return Some(strokes);
+        if let Some(data) = try_parse_session(&payload) {
+            return Some(data);
         }
         if let Some(decoded) = decode_uri_string(&payload) {
-            if let Some(strokes) = try_parse_strokes(&decoded) {
-                return Some(strokes);
+            if let Some(data) = try_parse_session(&decoded) {
+                return Some(data);
             }
         }
     }
     if let Some(decoded) = decode_uri_string(trimmed) {
-        if let Some(strokes) = try_parse_strokes(&decoded) {
-            return Some(strokes);
+        if let Some(data) = try_parse_session(&decoded) {
+            return Some(data);
         }
     }
     None
 }
 
-fn try_parse_strokes(text: &str) -> Option<Vec<Stroke>> {
+fn try_parse_session(text: &str) -> Option<SessionFileData> {
     if let Ok(data) = serde_json::from_str::<SessionFileData>(text) {
-        return Some(data.strokes);
+        return Some(data);
     }
+    try_parse_strokes(text).map(|strokes| SessionFileData {
+        pages: vec![Page {
+            id: PageId::INITIAL,
+            strokes,
+        }],
+        ..SessionFileData::default()
+    })
+}
+
+/// Saves from before pages, holding a single board.
+fn try_parse_strokes(text: &str) -> Option<Vec<Stroke>> {
     #[derive(serde::Deserialize)]
     struct LegacySaveData {
         version: u8,
@@ -70,112 +103,118 @@ fn decode_uri_string(text: &str) -> Option<String> {
         .and_then(|value| value.as_string())
 }
 
-pub fn build_pdf_html(state: &State, include_background: bool) -> String {
-    let (min_x, min_y, width, height) = pdf_bounds(state);
-    let mut paths = String::new();
-    for stroke in &state.strokes {
-        if stroke.points.is_empty() {
-            continue;
-        }
-        let mut data = String::new();
-        for (index, point) in stroke.points.iter().enumerate() {
-            let x = point.x as f64;
-            let y = point.y as f64;
-            if index == 0 {
-                data.push_str(&format!("M {} {}", x, y));
-            } else {
-                data.push_str(&format!(" L {} {}", x, y));
-            }
-        }
-        let color = stroke.color.to_rgba_css();
-        let width = stroke.size as f64 * STROKE_UNIT;
-        paths.push_str(&format!(
-            "<path d=\"{}\" stroke=\"{}\" stroke-width=\"{}\" fill=\"none\" stroke-linecap=\"round\" stroke-linejoin=\"round\" />",
-            data, color, width
-        ));
-        if stroke.points.len() == 1 {
-            let p = stroke.points[0];
-            let cx = p.x as f64;
-            let cy = p.y as f64;
-            let r = width / 2.0;
-            paths.push_str(&format!(
-                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" />",
-                cx, cy, r, color
-            ));
-        }
-    }
+/// The loaded assets of the images among `strokes`, by hash. Images still loading are left
+/// out of exports.
+fn loaded_images<'a, 'b>(
+    state: &'a State,
+    strokes: impl IntoIterator<Item = &'b Stroke>,
+) -> HashMap<String, &'a HtmlImageElement> {
+    strokes
+        .into_iter()
+        .filter_map(|stroke| stroke.image.as_ref())
+        .filter_map(|image| {
+            let element = state
+                .images
+                .get(&image.hash)
+                .filter(|element| is_ready(element))?;
+            Some((image.hash.clone(), element))
+        })
+        .collect()
+}
 
-    let background = if include_background {
-        "<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\" />"
-    } else {
-        ""
+/// Builds a vector PDF of the whole board, one board page after another; `None` when the
+/// layout would need more than `MAX_PDF_PAGES` pages.
+pub fn build_pdf(
+    document: &Document,
+    state: &State,
+    layout: &str,
+    paper: &str,
+    landscape: bool,
+) -> Option<Vec<u8>> {
+    let paper = PaperSize::from_name(paper);
+    let layout =
+        PdfLayout::from_name(layout, paper, landscape).unwrap_or(PdfLayout::FitToContent { paper });
+    let pages = state.pages_snapshot();
+    let options = PdfOptions {
+        layout,
+        images: loaded_images(state, pages.iter().flat_map(|page| &page.strokes))
+            .into_iter()
+            .filter_map(|(hash, element)| Some((hash, image_pixels(document, element)?)))
+            .collect(),
+        layers: state.layers.clone(),
+        ..PdfOptions::default()
     };
-
-    format!(
-        "<!doctype html><html><head><meta charset=\"utf-8\" /><style>@page{{margin:0;size:auto;}}html,body{{margin:0;padding:0;}}body{{display:block;}}svg{{display:block;width:100vw;height:100vh;}}</style></head><body><svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{min_x} {min_y} {width} {height}\" preserveAspectRatio=\"xMidYMid meet\">{background}{paths}</svg><script>window.onload=()=>{{window.print();}}</script></body></html>",
-        min_x = min_x,
-        min_y = min_y,
-        width = width,
-        height = height,
-        background = background,
-        paths = paths
-    )
+    let boards = pages
+        .iter()
+        .map(|page| page.strokes.as_slice())
+        .collect::<Vec<_>>();
+    render_pdf(&boards, &options)
 }
 
-fn pdf_bounds(state: &State) -> (f64, f64, f64, f64) {
-    let mut min_x = f64::MAX;
-    let mut min_y = f64::MAX;
-    let mut max_x = f64::MIN;
-    let mut max_y = f64::MIN;
-    let mut max_size: f64 = 0.0;
-    for stroke in &state.strokes {
-        max_size = max_size.max(stroke.size as f64 * STROKE_UNIT);
-        for point in &stroke.points {
-            let x = point.x as f64;
-            let y = point.y as f64;
-            min_x = min_x.min(x);
-            min_y = min_y.min(y);
-            max_x = max_x.max(x);
-            max_y = max_y.max(y);
+/// Builds a standalone SVG of `region` on the page on screen; `None` when exporting an empty
+/// selection.
+pub fn build_svg(
+    document: &Document,
+    state: &State,
+    region: ExportRegion,
+    background: bool,
+    padding: f64,
+) -> Option<String> {
+    let (strokes, region) = match region {
+        ExportRegion::Board => (state.strokes.clone(), None),
+        ExportRegion::Viewport => (
+            state.strokes.clone(),
+            Some(SvgRect {
+                x: -state.pan_x / state.zoom,
+                y: -state.pan_y / state.zoom,
+                width: state.board_width / state.zoom,
+                height: state.board_height / state.zoom,
+            }),
+        ),
+        ExportRegion::Selection => {
+            let Mode::Select(select) = &state.mode else {
+                return None;
+            };
+            let strokes = selected_strokes(&state.strokes, select);
+            if strokes.is_empty() {
+                return None;
+            }
+            (strokes, None)
         }
-    }
-    if min_x == f64::MAX {
-        return (0.0, 0.0, 1.0, 1.0);
-    }
-    let pad = (max_size / 2.0).max(1.0);
-    min_x -= pad;
-    min_y -= pad;
-    max_x += pad;
-    max_y += pad;
-    let width = (max_x - min_x).max(1.0);
-    let height = (max_y - min_y).max(1.0);
-    (min_x, min_y, width, height)
+    };
+    let options = SvgOptions {
+        background: background.then(|| "#ffffff".to_string()),
+        padding: if padding.is_finite() { padding } else { 0.0 },
+        region,
+        layers: state.layers.clone(),
+        image_hrefs: loaded_images(state, &strokes)
+            .into_iter()
+            .filter_map(|(hash, element)| Some((hash, image_data_url(document, element)?)))
+            .collect(),
+    };
+    Some(render_svg_document(&strokes, &options))
 }
 
-pub fn open_print_window(document: &Document, html: &str) {
-    let iframe: HtmlIFrameElement = match document
-        .create_element("iframe")
-        .ok()
-        .and_then(|element| element.dyn_into::<HtmlIFrameElement>().ok())
-    {
-        Some(frame) => frame,
-        None => return,
+pub fn download_bytes(document: &Document, bytes: &[u8], filename: &str, mime: &str) {
+    let array = js_sys::Uint8Array::from(bytes);
+    let parts = js_sys::Array::new();
+    parts.push(&array.buffer());
+    let options = BlobPropertyBag::new();
+    options.set_type(mime);
+    let blob = match Blob::new_with_u8_array_sequence_and_options(&parts, &options) {
+        Ok(blob) => blob,
+        Err(_) => return,
     };
-    let _ = iframe.set_attribute(
-        "style",
-        "position:fixed;right:0;bottom:0;width:0;height:0;border:0;",
-    );
-    iframe.set_srcdoc(html);
-    if let Some(body) = document.body() {
-        let _ = body.append_child(&iframe);
-    }
-    let iframe_for_load = iframe.clone();
-    let onload = Closure::<dyn FnMut(Event)>::new(move |_| {
-        if let Some(window) = iframe_for_load.content_window() {
-            let _ = window.focus();
-            let _ = window.print();
+    let href = match Url::create_object_url_with_blob(&blob) {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    if let Ok(element) = document.create_element("a") {
+        if let Ok(anchor) = element.dyn_into::<HtmlAnchorElement>() {
+            anchor.set_href(&href);
+            anchor.set_download(filename);
+            anchor.click();
         }
-    });
-    iframe.set_onload(Some(onload.as_ref().unchecked_ref()));
-    onload.forget();
+    }
+    let _ = Url::revoke_object_url(&href);
 }
@@ -0,0 +1,360 @@
+use yumboard_shared::{Point, Shape, ShapeKind, Stroke};
+
+/// How long (ms) the pen has to rest at the end of a stroke for it to be snapped to a shape.
+pub const HOLD_TO_SNAP_MS: f64 = 500.0;
+/// Screen pixels the pen may wander while resting.
+pub const HOLD_TOLERANCE: f64 = 4.0;
+
+/// Strokes smaller than this many screen pixels are left alone.
+const MIN_SHAPE_SIZE: f64 = 16.0;
+/// Points the outline of a closed stroke is resampled to before fitting.
+const SAMPLES: usize = 64;
+/// Largest distance from the chord, relative to its length, for a stroke to count as a line.
+const MAX_LINE_DEVIATION: f64 = 0.06;
+/// Largest gap between the ends, relative to the stroke length, for it to count as closed.
+const MAX_CLOSE_GAP: f64 = 0.2;
+/// Corner detection tolerance, relative to the larger side of the bounding box.
+const CORNER_TOLERANCE: f64 = 0.08;
+/// Corners turning less than this (radians) are merged into a straight side.
+const MIN_CORNER_TURN: f64 = 0.35;
+/// Mean fitting error, relative to the larger side of the bounding box, that is still accepted.
+const MAX_POLYGON_ERROR: f64 = 0.045;
+const MAX_ELLIPSE_ERROR: f64 = 0.06;
+/// Rectangles and ellipses within this angle (radians) of the axes are straightened.
+const AXIS_SNAP: f64 = 0.14;
+/// Ellipses whose axes differ by less than this ratio become circles.
+const CIRCLE_RATIO: f64 = 1.1;
+
+type Xy = (f64, f64);
+
+/// The clean shape a freehand stroke was meant to be, keeping its id, color and size: a line,
+/// a rectangle or an ellipse shape, or a closed three-point polyline for a triangle. `None` if
+/// nothing fits well enough.
+pub fn recognize_shape(stroke: &Stroke, zoom: f64) -> Option<Stroke> {
+    if stroke.shape.is_some() || stroke.text.is_some() || stroke.image.is_some() {
+        return None;
+    }
+    let mut points: Vec<Xy> = Vec::with_capacity(stroke.points.len());
+    for point in &stroke.points {
+        let xy = (point.x as f64, point.y as f64);
+        if points.last() != Some(&xy) {
+            points.push(xy);
+        }
+    }
+    if points.len() < 2 {
+        return None;
+    }
+    let zoom = zoom.max(0.01);
+    let length = path_length(&points);
+    if length * zoom < MIN_SHAPE_SIZE {
+        return None;
+    }
+    let (start, end) = (points[0], points[points.len() - 1]);
+    let gap = distance(start, end);
+
+    let deviation = points
+        .iter()
+        .map(|point| segment_distance(*point, start, end))
+        .fold(0.0, f64::max);
+    if deviation <= gap * MAX_LINE_DEVIATION {
+        return Some(snapped(stroke, Some(ShapeKind::Line), vec![start, end]));
+    }
+    if gap > length * MAX_CLOSE_GAP {
+        return None;
+    }
+
+    let mut closed = points.clone();
+    closed.push(start);
+    let ring = resample(&closed, SAMPLES);
+    let (min, max) = ring.iter().fold(
+        ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+        |(min, max), p| {
+            (
+                (min.0.min(p.0), min.1.min(p.1)),
+                (max.0.max(p.0), max.1.max(p.1)),
+            )
+        },
+    );
+    let scale = (max.0 - min.0).max(max.1 - min.1);
+    if scale * zoom < MIN_SHAPE_SIZE {
+        return None;
+    }
+
+    let ellipse = fit_ellipse(&ring).map(|(corners, error)| (corners, error / scale));
+    let corners = polygon_corners(&ring, scale * CORNER_TOLERANCE);
+    let polygon = matches!(corners.len(), 3 | 4).then(|| {
+        let error = ring
+            .iter()
+            .map(|point| polygon_distance(*point, &corners))
+            .sum::<f64>()
+            / ring.len() as f64;
+        (corners, error / scale)
+    });
+
+    let ellipse_error = ellipse.as_ref().map_or(f64::MAX, |(_, error)| *error);
+    if let Some((corners, error)) = polygon {
+        if error <= MAX_POLYGON_ERROR && error < ellipse_error {
+            return Some(if corners.len() == 3 {
+                let mut outline = corners.clone();
+                outline.push(corners[0]);
+                snapped(stroke, None, outline)
+            } else {
+                snapped(stroke, Some(ShapeKind::Rect), fit_rectangle(&corners))
+            });
+        }
+    }
+    match ellipse {
+        Some((corners, error)) if error <= MAX_ELLIPSE_ERROR => {
+            Some(snapped(stroke, Some(ShapeKind::Ellipse), corners))
+        }
+        _ => None,
+    }
+}
+
+fn snapped(stroke: &Stroke, kind: Option<ShapeKind>, points: Vec<Xy>) -> Stroke {
+    Stroke {
+        points: points
+            .into_iter()
+            .map(|(x, y)| Point {
+                x: x as f32,
+                y: y as f32,
+                pressure: None,
+            })
+            .collect(),
+        shape: kind.map(|kind| Shape { kind, fill: None }),
+        ..stroke.clone()
+    }
+}
+
+/// The corner box of the ellipse through `ring` (in `Stroke::points` order) and the mean
+/// distance of the ring from it.
+fn fit_ellipse(ring: &[Xy]) -> Option<(Vec<Xy>, f64)> {
+    let count = ring.len() as f64;
+    let center = (
+        ring.iter().map(|p| p.0).sum::<f64>() / count,
+        ring.iter().map(|p| p.1).sum::<f64>() / count,
+    );
+    let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+    for p in ring {
+        let (dx, dy) = (p.0 - center.0, p.1 - center.1);
+        xx += dx * dx;
+        xy += dx * dy;
+        yy += dy * dy;
+    }
+    let (xx, xy, yy) = (xx / count, xy / count, yy / count);
+    let angle = snap_to_axes(0.5 * (2.0 * xy).atan2(xx - yy));
+    let mut u = (angle.cos(), angle.sin());
+    let mut v = (-u.1, u.0);
+    // Least-squares fit of `(s / a)² + (t / b)² = 1` in the principal axes, linear in `1 / a²`
+    // and `1 / b²`.
+    let local = ring
+        .iter()
+        .map(|p| {
+            let (dx, dy) = (p.0 - center.0, p.1 - center.1);
+            let (s, t) = (dx * u.0 + dy * u.1, dx * v.0 + dy * v.1);
+            (s * s, t * t)
+        })
+        .collect::<Vec<_>>();
+    let (mut ss, mut st, mut tt, mut s1, mut t1) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (s, t) in &local {
+        ss += s * s;
+        st += s * t;
+        tt += t * t;
+        s1 += s;
+        t1 += t;
+    }
+    let determinant = ss * tt - st * st;
+    if determinant.abs() <= f64::EPSILON {
+        return None;
+    }
+    let inverse_a = (s1 * tt - t1 * st) / determinant;
+    let inverse_b = (t1 * ss - s1 * st) / determinant;
+    if inverse_a <= 0.0 || inverse_b <= 0.0 {
+        return None;
+    }
+    let (mut a, mut b) = (inverse_a.sqrt().recip(), inverse_b.sqrt().recip());
+    let error = local
+        .iter()
+        .map(|(s, t)| ((s * inverse_a + t * inverse_b).sqrt() - 1.0).abs())
+        .sum::<f64>()
+        / count;
+    // Circles have no meaningful orientation, so keep their corner box upright.
+    if a.max(b) / a.min(b) < CIRCLE_RATIO {
+        let r = (a + b) / 2.0;
+        (a, b) = (r, r);
+        (u, v) = ((1.0, 0.0), (0.0, 1.0));
+    }
+    let at = |s: f64, t: f64| {
+        (
+            center.0 + u.0 * a * s + v.0 * b * t,
+            center.1 + u.1 * a * s + v.1 * b * t,
+        )
+    };
+    Some((
+        vec![at(-1.0, -1.0), at(1.0, -1.0), at(1.0, 1.0), at(-1.0, 1.0)],
+        error * (a + b) / 2.0,
+    ))
+}
+
+/// A right-angled rectangle through four rough corners, in `Stroke::points` order.
+fn fit_rectangle(corners: &[Xy]) -> Vec<Xy> {
+    // Average the side directions modulo 90°, weighted by length.
+    let (mut sum_cos, mut sum_sin) = (0.0, 0.0);
+    for index in 0..corners.len() {
+        let (from, to) = (corners[index], corners[(index + 1) % corners.len()]);
+        let angle = (to.1 - from.1).atan2(to.0 - from.0);
+        let weight = distance(from, to);
+        sum_cos += (4.0 * angle).cos() * weight;
+        sum_sin += (4.0 * angle).sin() * weight;
+    }
+    let angle = snap_to_axes(sum_sin.atan2(sum_cos) / 4.0);
+    let u = (angle.cos(), angle.sin());
+    let v = (-u.1, u.0);
+    let extent = |axis: Xy| {
+        let mut values = corners
+            .iter()
+            .map(|p| p.0 * axis.0 + p.1 * axis.1)
+            .collect::<Vec<_>>();
+        values.sort_by(f64::total_cmp);
+        ((values[0] + values[1]) / 2.0, (values[2] + values[3]) / 2.0)
+    };
+    let (left, right) = extent(u);
+    let (top, bottom) = extent(v);
+    let at = |s: f64, t: f64| (u.0 * s + v.0 * t, u.1 * s + v.1 * t);
+    vec![
+        at(left, top),
+        at(right, top),
+        at(right, bottom),
+        at(left, bottom),
+    ]
+}
+
+/// `angle` (an axis direction, so only meaningful modulo 90°) turned to the nearest axis when it
+/// is within `AXIS_SNAP` of it.
+fn snap_to_axes(angle: f64) -> f64 {
+    let quarter = std::f64::consts::FRAC_PI_2;
+    let nearest = (angle / quarter).round() * quarter;
+    if (angle - nearest).abs() < AXIS_SNAP {
+        nearest
+    } else {
+        angle
+    }
+}
+
+/// Corners of a closed ring: Ramer-Douglas-Peucker on both halves, then straight-through
+/// vertices merged away.
+fn polygon_corners(ring: &[Xy], tolerance: f64) -> Vec<Xy> {
+    let split = (1..ring.len())
+        .max_by(|a, b| distance(ring[0], ring[*a]).total_cmp(&distance(ring[0], ring[*b])))
+        .unwrap_or(0);
+    if split == 0 {
+        return Vec::new();
+    }
+    let mut loop_points = ring.to_vec();
+    loop_points.push(ring[0]);
+    let mut corners = simplify(&loop_points[..=split], tolerance);
+    corners.pop();
+    corners.extend(simplify(&loop_points[split..], tolerance));
+    corners.pop();
+    loop {
+        let count = corners.len();
+        if count <= 3 {
+            break;
+        }
+        let straight = (0..count).find(|index| {
+            let previous = corners[(index + count - 1) % count];
+            let current = corners[*index];
+            let next = corners[(index + 1) % count];
+            let (incoming, outgoing) = (
+                (current.0 - previous.0, current.1 - previous.1),
+                (next.0 - current.0, next.1 - current.1),
+            );
+            let cross = incoming.0 * outgoing.1 - incoming.1 * outgoing.0;
+            let dot = incoming.0 * outgoing.0 + incoming.1 * outgoing.1;
+            cross.atan2(dot).abs() < MIN_CORNER_TURN
+        });
+        match straight {
+            Some(index) => {
+                corners.remove(index);
+            }
+            None => break,
+        }
+    }
+    corners
+}
+
+fn simplify(points: &[Xy], tolerance: f64) -> Vec<Xy> {
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let mut farthest = None;
+        let mut max_distance = tolerance;
+        for index in start + 1..end {
+            let distance = segment_distance(points[index], points[start], points[end]);
+            if distance > max_distance {
+                max_distance = distance;
+                farthest = Some(index);
+            }
+        }
+        if let Some(index) = farthest {
+            keep[index] = true;
+            ranges.push((start, index));
+            ranges.push((index, end));
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| *point)
+        .collect()
+}
+
+/// `count` points evenly spaced along the polyline.
+fn resample(points: &[Xy], count: usize) -> Vec<Xy> {
+    let step = path_length(points) / count as f64;
+    let mut out = vec![points[0]];
+    let mut carried = 0.0;
+    for pair in points.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let segment = distance(from, to);
+        let mut along = step - carried;
+        while along <= segment && out.len() < count {
+            let t = along / segment;
+            out.push((from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t));
+            along += step;
+        }
+        carried = segment - (along - step);
+    }
+    out
+}
+
+fn polygon_distance(point: Xy, corners: &[Xy]) -> f64 {
+    (0..corners.len())
+        .map(|index| segment_distance(point, corners[index], corners[(index + 1) % corners.len()]))
+        .fold(f64::MAX, f64::min)
+}
+
+fn path_length(points: &[Xy]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| distance(pair[0], pair[1]))
+        .sum()
+}
+
+fn distance(a: Xy, b: Xy) -> f64 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+fn segment_distance(point: Xy, start: Xy, end: Xy) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 {
+        (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (point.0 - (start.0 + dx * t)).hypot(point.1 - (start.1 + dy * t))
+}
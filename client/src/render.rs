@@ -1,8 +1,21 @@
 use web_sys::CanvasRenderingContext2d;
-use yumboard_shared::{Color, Point, Stroke};
+use yumboard_shared::curve::curved_stroke;
+use yumboard_shared::highlight::paint_order;
+use yumboard_shared::image::image_matrix;
+use yumboard_shared::pressure::{
+    has_pressure, point_width, segment_outline, stroke_outline, OutlinePart,
+};
+use yumboard_shared::shape::{arrow_head, shape_path, PathSegment};
+use yumboard_shared::text::{wrap_lines, TEXT_ASCENT, TEXT_FONT_FAMILY, TEXT_LINE_HEIGHT};
+use yumboard_shared::{Blend, Color, Point, ShapeKind, Stroke, ZOrder, STROKE_UNIT};
 
-use crate::geometry::{selection_bounds, world_to_screen};
-use crate::state::{Mode, SelectMode, SelectState, State, STROKE_UNIT};
+use crate::geometry::{order_handles, selection_bounds, world_to_screen};
+use crate::images::is_ready;
+use crate::state::{Mode, SelectMode, SelectState, ShapeState, State, TextState};
+use crate::util::peer_label;
+
+/// Screen pixels between samples of the curve through freehand points.
+const CURVE_SPACING: f64 = 3.0;
 
 pub fn draw_dot(
     ctx: &CanvasRenderingContext2d,
@@ -14,7 +27,7 @@ pub fn draw_dot(
     size: f32,
 ) {
     let scale = zoom;
-    let weight = size as f64 * zoom * STROKE_UNIT;
+    let weight = point_width(size, point) * zoom;
     let x = point.x as f64 * scale + pan_x;
     let y = point.y as f64 * scale + pan_y;
     ctx.set_fill_style_str(&color.to_rgba_css());
@@ -33,8 +46,19 @@ pub fn draw_segment(
     color: Color,
     size: f32,
 ) {
+    if from.pressure.is_some() || to.pressure.is_some() {
+        fill_outline(
+            ctx,
+            zoom,
+            pan_x,
+            pan_y,
+            &segment_outline(from, to, size),
+            color,
+        );
+        return;
+    }
     let scale = zoom;
-    let weight = size as f64 * zoom * STROKE_UNIT;
+    let weight = point_width(size, from) * zoom;
     let from_x = from.x as f64 * scale + pan_x;
     let from_y = from.y as f64 * scale + pan_y;
     let to_x = to.x as f64 * scale + pan_x;
@@ -54,6 +78,19 @@ pub fn draw_stroke(ctx: &CanvasRenderingContext2d, state: &State, stroke: &Strok
     if stroke.points.is_empty() {
         return;
     }
+    if stroke.text.is_some() {
+        draw_text(ctx, state.zoom, state.pan_x, state.pan_y, stroke);
+        return;
+    }
+    if stroke.image.is_some() {
+        draw_image(ctx, state, stroke);
+        return;
+    }
+    if stroke.shape.is_some() {
+        draw_shape(ctx, state.zoom, state.pan_x, state.pan_y, stroke);
+        return;
+    }
+    let stroke = &*curved_stroke(stroke, CURVE_SPACING / state.zoom.max(0.01));
     if stroke.points.len() == 1 {
         draw_dot(
             ctx,
@@ -66,6 +103,21 @@ pub fn draw_stroke(ctx: &CanvasRenderingContext2d, state: &State, stroke: &Strok
         );
         return;
     }
+    if has_pressure(stroke) {
+        fill_outline(
+            ctx,
+            state.zoom,
+            state.pan_x,
+            state.pan_y,
+            &stroke_outline(stroke),
+            stroke.color,
+        );
+        return;
+    }
+    if stroke.blend == Blend::Highlight {
+        draw_polyline(ctx, state.zoom, state.pan_x, state.pan_y, stroke);
+        return;
+    }
     for i in 1..stroke.points.len() {
         draw_segment(
             ctx,
@@ -80,14 +132,234 @@ pub fn draw_stroke(ctx: &CanvasRenderingContext2d, state: &State, stroke: &Strok
     }
 }
 
+/// Strokes a freehand line as one path, so translucent ink does not darken where it overlaps.
+fn draw_polyline(
+    ctx: &CanvasRenderingContext2d,
+    zoom: f64,
+    pan_x: f64,
+    pan_y: f64,
+    stroke: &Stroke,
+) {
+    ctx.set_stroke_style_str(&stroke.color.to_rgba_css());
+    ctx.set_line_cap("round");
+    ctx.set_line_join("round");
+    ctx.set_line_width(stroke.size as f64 * STROKE_UNIT * zoom);
+    ctx.begin_path();
+    for (index, point) in stroke.points.iter().enumerate() {
+        let x = point.x as f64 * zoom + pan_x;
+        let y = point.y as f64 * zoom + pan_y;
+        if index == 0 {
+            ctx.move_to(x, y);
+        } else {
+            ctx.line_to(x, y);
+        }
+    }
+    ctx.stroke();
+}
+
+/// Draws an image into its corner parallelogram, or a placeholder while the asset loads.
+fn draw_image(ctx: &CanvasRenderingContext2d, state: &State, stroke: &Stroke) {
+    let (Some(image), Some([a, b, c, d, e, f])) = (&stroke.image, image_matrix(stroke)) else {
+        return;
+    };
+    let zoom = state.zoom;
+    ctx.save();
+    // Map the unit square onto the corners on screen.
+    let _ = ctx.transform(
+        a * zoom,
+        b * zoom,
+        c * zoom,
+        d * zoom,
+        e * zoom + state.pan_x,
+        f * zoom + state.pan_y,
+    );
+    match state
+        .images
+        .get(&image.hash)
+        .filter(|element| is_ready(element))
+    {
+        Some(element) => {
+            let _ =
+                ctx.draw_image_with_html_image_element_and_dw_and_dh(element, 0.0, 0.0, 1.0, 1.0);
+        }
+        None => {
+            ctx.set_fill_style_str("rgba(148, 163, 184, 0.35)");
+            ctx.fill_rect(0.0, 0.0, 1.0, 1.0);
+        }
+    }
+    ctx.restore();
+}
+
+fn draw_shape(ctx: &CanvasRenderingContext2d, zoom: f64, pan_x: f64, pan_y: f64, stroke: &Stroke) {
+    let Some(shape) = stroke.shape else {
+        return;
+    };
+    let screen = |(x, y): (f64, f64)| (x * zoom + pan_x, y * zoom + pan_y);
+    ctx.begin_path();
+    for segment in shape_path(stroke) {
+        match segment {
+            PathSegment::MoveTo(x, y) => {
+                let (x, y) = screen((x, y));
+                ctx.move_to(x, y);
+            }
+            PathSegment::LineTo(x, y) => {
+                let (x, y) = screen((x, y));
+                ctx.line_to(x, y);
+            }
+            PathSegment::CubicTo(c1, c2, end) => {
+                let (c1, c2, end) = (screen(c1), screen(c2), screen(end));
+                ctx.bezier_curve_to(c1.0, c1.1, c2.0, c2.1, end.0, end.1);
+            }
+            PathSegment::Close => ctx.close_path(),
+        }
+    }
+    if let Some(fill) = shape.fill.filter(|_| shape.kind.is_closed()) {
+        ctx.set_fill_style_str(&fill.to_rgba_css());
+        ctx.fill();
+    }
+    ctx.set_stroke_style_str(&stroke.color.to_rgba_css());
+    ctx.set_line_cap("round");
+    ctx.set_line_join("round");
+    ctx.set_line_width(stroke.size as f64 * STROKE_UNIT * zoom);
+    ctx.stroke();
+    if let Some([tip, left, right]) =
+        arrow_head(&stroke.points, stroke.size).filter(|_| shape.kind == ShapeKind::Arrow)
+    {
+        let (tip, left, right) = (screen(tip), screen(left), screen(right));
+        ctx.set_fill_style_str(&stroke.color.to_rgba_css());
+        ctx.begin_path();
+        ctx.move_to(tip.0, tip.1);
+        ctx.line_to(left.0, left.1);
+        ctx.line_to(right.0, right.1);
+        ctx.close_path();
+        ctx.fill();
+    }
+}
+
+/// Draws a text box line by line with the shared wrapping, so breaks match the exports.
+fn draw_text(ctx: &CanvasRenderingContext2d, zoom: f64, pan_x: f64, pan_y: f64, stroke: &Stroke) {
+    let (Some(text), Some(origin)) = (&stroke.text, stroke.points.first()) else {
+        return;
+    };
+    let em = text.font_size as f64;
+    let x = origin.x as f64 * zoom + pan_x;
+    ctx.save();
+    ctx.set_font(&format!("{}px {}", em * zoom, TEXT_FONT_FAMILY));
+    ctx.set_text_baseline("alphabetic");
+    ctx.set_fill_style_str(&stroke.color.to_rgba_css());
+    for (index, line) in wrap_lines(text).iter().enumerate() {
+        let baseline = origin.y as f64 + (index as f64 * TEXT_LINE_HEIGHT + TEXT_ASCENT) * em;
+        let _ = ctx.fill_text(line, x, baseline * zoom + pan_y);
+    }
+    ctx.restore();
+}
+
+/// Fills a pressure outline in one path so translucent ink is painted once.
+fn fill_outline(
+    ctx: &CanvasRenderingContext2d,
+    zoom: f64,
+    pan_x: f64,
+    pan_y: f64,
+    parts: &[OutlinePart],
+    color: Color,
+) {
+    ctx.set_fill_style_str(&color.to_rgba_css());
+    ctx.begin_path();
+    for part in parts {
+        match part {
+            OutlinePart::Circle { x, y, radius } => {
+                let x = x * zoom + pan_x;
+                let y = y * zoom + pan_y;
+                let radius = radius * zoom;
+                ctx.move_to(x + radius, y);
+                let _ = ctx.arc_with_anticlockwise(
+                    x,
+                    y,
+                    radius,
+                    0.0,
+                    -std::f64::consts::PI * 2.0,
+                    true,
+                );
+            }
+            OutlinePart::Quad(corners) => {
+                for (index, (x, y)) in corners.iter().enumerate() {
+                    let x = x * zoom + pan_x;
+                    let y = y * zoom + pan_y;
+                    if index == 0 {
+                        ctx.move_to(x, y);
+                    } else {
+                        ctx.line_to(x, y);
+                    }
+                }
+                ctx.close_path();
+            }
+        }
+    }
+    ctx.fill();
+}
+
 pub fn redraw(ctx: &CanvasRenderingContext2d, state: &mut State) {
     ctx.clear_rect(0.0, 0.0, state.board_width, state.board_height);
-    for stroke in &state.strokes {
+    // The text box being edited is shown by the editor overlay instead.
+    let editing = match &state.mode {
+        Mode::Text(TextState { editing, .. }) => editing.as_ref(),
+        _ => None,
+    };
+    for stroke in paint_order(&state.strokes, &state.layers) {
+        if Some(&stroke.id) == editing {
+            continue;
+        }
         draw_stroke(ctx, state, stroke);
     }
+    if let Mode::Shape(ShapeState {
+        drag: Some(drag), ..
+    }) = &state.mode
+    {
+        draw_stroke(ctx, state, &drag.stroke);
+    }
     if let Mode::Select(select) = &state.mode {
         draw_selection_overlay(ctx, state, select);
     }
+    draw_peer_cursors(ctx, state);
+}
+
+pub fn draw_peer_cursors(ctx: &CanvasRenderingContext2d, state: &State) {
+    ctx.save();
+    ctx.set_font("600 12px sans-serif");
+    ctx.set_text_baseline("middle");
+    for (peer, presence) in &state.peers {
+        let Some(point) = presence
+            .cursor
+            .filter(|_| presence.page == Some(state.page))
+        else {
+            continue;
+        };
+        let (x, y) = world_to_screen(state, point);
+        let color = presence.color.to_rgba_css();
+
+        ctx.begin_path();
+        ctx.move_to(x, y);
+        ctx.line_to(x + 4.0, y + 14.0);
+        ctx.line_to(x + 7.0, y + 8.0);
+        ctx.line_to(x + 13.0, y + 6.0);
+        ctx.close_path();
+        ctx.set_fill_style_str(&color);
+        ctx.fill();
+        ctx.set_stroke_style_str("#fff");
+        ctx.set_line_width(1.5);
+        ctx.stroke();
+
+        let label = peer_label(*peer, presence.name.as_deref());
+        let text_width = ctx
+            .measure_text(&label)
+            .map(|metrics| metrics.width())
+            .unwrap_or(0.0);
+        ctx.set_fill_style_str(&color);
+        ctx.fill_rect(x + 12.0, y + 12.0, text_width + 10.0, 18.0);
+        ctx.set_fill_style_str("#fff");
+        let _ = ctx.fill_text(&label, x + 17.0, y + 21.0);
+    }
+    ctx.restore();
 }
 
 pub fn draw_selection_overlay(ctx: &CanvasRenderingContext2d, state: &State, select: &SelectState) {
@@ -123,6 +395,7 @@ pub fn draw_selection_overlay(ctx: &CanvasRenderingContext2d, state: &State, sel
             Point {
                 x: bounds.min_x as f32,
                 y: bounds.min_y as f32,
+                pressure: None,
             },
         );
         let (right, bottom) = world_to_screen(
@@ -130,6 +403,7 @@ pub fn draw_selection_overlay(ctx: &CanvasRenderingContext2d, state: &State, sel
             Point {
                 x: bounds.max_x as f32,
                 y: bounds.max_y as f32,
+                pressure: None,
             },
         );
         let width = right - left;
@@ -149,6 +423,9 @@ pub fn draw_selection_overlay(ctx: &CanvasRenderingContext2d, state: &State, sel
         draw_handle(ctx, right, (top + bottom) / 2.0, handle);
         draw_handle_circle(ctx, center_x, rotate_y, 6.0);
         draw_trash_handle(ctx, right + 18.0, top - 18.0, handle);
+        for (to, x, y) in order_handles(right, top) {
+            draw_order_handle(ctx, x, y, handle, to);
+        }
     }
 
     ctx.restore();
@@ -180,3 +457,26 @@ fn draw_trash_handle(ctx: &CanvasRenderingContext2d, x: f64, y: f64, size: f64)
     ctx.line_to(x - half + 2.0, y + half - 2.0);
     ctx.stroke();
 }
+
+/// A handle with one chevron for a step forward or backward, two for front or back.
+fn draw_order_handle(ctx: &CanvasRenderingContext2d, x: f64, y: f64, size: f64, to: ZOrder) {
+    let half = size / 2.0;
+    ctx.set_fill_style_str("rgba(26, 31, 42, 0.85)");
+    ctx.fill_rect(x - half, y - half, size, size);
+    let (up, offsets): (bool, &[f64]) = match to {
+        ZOrder::Front => (true, &[-1.5, 1.5]),
+        ZOrder::Forward => (true, &[0.0]),
+        ZOrder::Backward => (false, &[0.0]),
+        ZOrder::Back => (false, &[-1.5, 1.5]),
+    };
+    let tip = if up { -1.5 } else { 1.5 };
+    ctx.set_stroke_style_str("#fff");
+    ctx.set_line_width(1.5);
+    ctx.begin_path();
+    for offset in offsets {
+        ctx.move_to(x - half + 2.0, y + offset - tip);
+        ctx.line_to(x, y + offset + tip);
+        ctx.line_to(x + half - 2.0, y + offset - tip);
+    }
+    ctx.stroke();
+}
@@ -0,0 +1,96 @@
+use web_sys::Window;
+
+use yumboard_shared::Point;
+
+const SMOOTHING_KEY: &str = "yumboard:smoothing";
+pub const DEFAULT_SMOOTHING: f64 = 0.5;
+
+/// How far (in screen pixels) the simplified polyline may stray from the raw one at full strength.
+const MAX_SIMPLIFY_TOLERANCE: f64 = 1.5;
+
+pub fn load_smoothing(window: &Window) -> f64 {
+    window
+        .local_storage()
+        .ok()
+        .flatten()
+        .and_then(|storage| storage.get_item(SMOOTHING_KEY).ok().flatten())
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .map(|value| value.clamp(0.0, 1.0))
+        .unwrap_or(DEFAULT_SMOOTHING)
+}
+
+pub fn store_smoothing(window: &Window, strength: f64) {
+    if let Some(storage) = window.local_storage().ok().flatten() {
+        let _ = storage.set_item(SMOOTHING_KEY, &strength.to_string());
+    }
+}
+
+/// Low-pass filters a raw pointer sample towards the previous stored point while drawing.
+pub fn streamline(previous: Option<Point>, raw: Point, strength: f64) -> Point {
+    let Some(previous) = previous else {
+        return raw;
+    };
+    let follow = (1.0 - strength.clamp(0.0, 1.0) * 0.75) as f32;
+    Point {
+        x: previous.x + (raw.x - previous.x) * follow,
+        y: previous.y + (raw.y - previous.y) * follow,
+        pressure: match (previous.pressure, raw.pressure) {
+            (Some(from), Some(to)) => Some(from + (to - from) * follow),
+            (_, pressure) => pressure,
+        },
+    }
+}
+
+/// The points a finished stroke is replaced with: the Ramer-Douglas-Peucker simplification of
+/// what was drawn. Only these control points are sent and stored; renderers draw a curve through
+/// them (`shared::curve::curved_stroke`). `None` leaves the stroke as drawn.
+pub fn simplify_stroke_points(points: &[Point], strength: f64, zoom: f64) -> Option<Vec<Point>> {
+    if strength <= 0.0 || points.len() < 3 {
+        return None;
+    }
+    let simplified = simplify(points, strength * MAX_SIMPLIFY_TOLERANCE / zoom.max(0.01));
+    (simplified.len() < points.len()).then_some(simplified)
+}
+
+fn simplify(points: &[Point], tolerance: f64) -> Vec<Point> {
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let mut farthest = None;
+        let mut max_distance = tolerance;
+        for index in start + 1..end {
+            let distance = segment_distance(points[index], points[start], points[end]);
+            if distance > max_distance {
+                max_distance = distance;
+                farthest = Some(index);
+            }
+        }
+        if let Some(index) = farthest {
+            keep[index] = true;
+            ranges.push((start, index));
+            ranges.push((index, end));
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| *point)
+        .collect()
+}
+
+fn segment_distance(point: Point, start: Point, end: Point) -> f64 {
+    let (px, py) = (point.x as f64, point.y as f64);
+    let (sx, sy) = (start.x as f64, start.y as f64);
+    let (dx, dy) = (end.x as f64 - sx, end.y as f64 - sy);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 {
+        (((px - sx) * dx + (py - sy) * dy) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (px - (sx + dx * t)).hypot(py - (sy + dy * t))
+}
@@ -1,12 +1,15 @@
 use std::collections::{HashMap, HashSet};
 
 use wasm_bindgen::prelude::Closure;
-use web_sys::{FileReader, ProgressEvent};
+use web_sys::{FileReader, HtmlImageElement, ProgressEvent};
 
-use yumboard_shared::{Point, Stroke, StrokeId};
+use yumboard_shared::{
+    Color, Layer, LayerId, Page, PageId, PeerId, Point, ShapeKind, Stroke, StrokeId, ZOrder,
+};
+
+use crate::laser::LaserTrail;
 
 pub const DEFAULT_PALETTE: [&str; 3] = ["#1f1f1f", "#d60000", "#0000d0"];
-pub const STROKE_UNIT: f64 = 1.0;
 
 #[derive(Clone, Copy)]
 pub enum ScaleAxis {
@@ -26,6 +29,7 @@ pub enum SelectionHit {
     Scale(ScaleHandle),
     Rotate,
     Trash,
+    Order(ZOrder),
 }
 
 pub enum DrawMode {
@@ -38,9 +42,45 @@ pub struct DrawState {
     pub palette_selected: usize,
 }
 
+pub struct ShapeDrag {
+    pub start: Point,
+    /// The shape being dragged out, drawn as a preview until the pointer is released.
+    pub stroke: Stroke,
+}
+
+pub struct ShapeState {
+    pub kind: ShapeKind,
+    pub palette_selected: usize,
+    pub drag: Option<ShapeDrag>,
+}
+
+pub struct TextState {
+    pub palette_selected: usize,
+    /// The text box open in the editor overlay.
+    pub editing: Option<StrokeId>,
+}
+
 pub enum EraseMode {
     Idle,
-    Active { hits: HashSet<StrokeId> },
+    Active {
+        hits: HashSet<StrokeId>,
+    },
+    /// Precise erasing; cuts are applied locally and sent as one split per stroke at pen-up.
+    Cutting {
+        last: Point,
+        splits: Vec<SplitEdit>,
+    },
+}
+
+/// A stroke cut during the current precise erase, and the ids of its pieces left so far.
+pub struct SplitEdit {
+    pub before: Stroke,
+    pub fragments: Vec<StrokeId>,
+}
+
+pub enum LaserMode {
+    Idle,
+    Active { pointer_id: i32 },
 }
 
 pub enum PanMode {
@@ -101,6 +141,11 @@ pub struct PinchState {
 pub struct DrawPointerState {
     pub pointer_id: i32,
     pub last_timestamp: f64,
+    // Latest unfiltered sample; smoothing lags behind it, so it is added back at pen-up.
+    pub last_raw: Point,
+    // Where and since when the pen has been resting, for hold-to-snap shape recognition.
+    pub still_point: Point,
+    pub still_since: f64,
 }
 
 pub enum InputActivity {
@@ -110,16 +155,35 @@ pub enum InputActivity {
     Pan(PanMode),
 }
 
+pub struct PeerPresence {
+    pub name: Option<String>,
+    pub cursor: Option<Point>,
+    pub color: Color,
+    /// Cursors and laser trails only show for peers on the same page.
+    pub page: Option<PageId>,
+}
+
 pub enum Mode {
     Draw(DrawState),
+    Shape(ShapeState),
+    Text(TextState),
     Erase(EraseMode),
+    Laser(LaserMode),
     Pan(PanMode),
     Select(SelectState),
     Loading(LoadingState),
 }
 
 pub struct State {
+    /// The strokes of the page on screen.
     pub strokes: Vec<Stroke>,
+    pub page: PageId,
+    /// Every page in order. The entry for `page` is kept empty; its strokes are in `strokes`.
+    pub pages: Vec<Page>,
+    /// Bottom first, shared by all pages.
+    pub layers: Vec<Layer>,
+    /// The layer new strokes go on.
+    pub layer: LayerId,
     pub active_ids: HashSet<StrokeId>,
     pub board_width: f64,
     pub board_height: f64,
@@ -129,12 +193,53 @@ pub struct State {
     pub palette: Vec<String>,
     pub mode: Mode,
     pub pending_points: HashMap<StrokeId, Vec<Point>>,
+    // Strokes drawn here that have not yet shown up in a server `Sync` snapshot.
+    pub unconfirmed_ids: HashSet<StrokeId>,
     pub flush_scheduled: bool,
     pub redraw_scheduled: bool,
     pub ws_offline_prompted: bool,
     pub input_activity: InputActivity,
     pub touch_points: HashMap<i32, (f64, f64)>,
     pub palette_remove_mode: bool,
+    pub display_name: String,
+    /// Stroke smoothing strength in `0..=1`; 0 keeps raw input.
+    pub smoothing: f64,
+    pub view_only: bool,
+    pub edit_link: Option<String>,
+    pub view_link: Option<String>,
+    pub peers: HashMap<PeerId, PeerPresence>,
+    pub pending_cursor: Option<(Option<Point>, Color)>,
+    /// The mouse or pen position over the board, where pasted strokes go.
+    pub pointer: Option<Point>,
+    /// Whether the file picked next in the load dialog is inserted into the board instead of
+    /// replacing it.
+    pub load_insert: bool,
+    pub cursor_flush_scheduled: bool,
+    /// Image assets by hash, fetched the first time a stroke shows them.
+    pub images: HashMap<String, HtmlImageElement>,
+    /// Fading laser pointer trails, local and from peers, drawn on the overlay canvas.
+    pub lasers: Vec<LaserTrail>,
+    pub laser_frame_scheduled: bool,
+    /// Local laser samples waiting to be sent, and whether they begin a new trail.
+    pub pending_laser: Option<(Vec<Point>, bool)>,
+    pub laser_flush_scheduled: bool,
 }
 
-impl State {}
+impl State {
+    /// Every page in order, with the one on screen filled in.
+    pub fn pages_snapshot(&self) -> Vec<Page> {
+        self.pages
+            .iter()
+            .map(|page| {
+                if page.id == self.page {
+                    Page {
+                        id: page.id,
+                        strokes: self.strokes.clone(),
+                    }
+                } else {
+                    page.clone()
+                }
+            })
+            .collect()
+    }
+}
@@ -1,4 +1,4 @@
-use yumboard_shared::StrokeId;
+use yumboard_shared::{ClientId, ClientSecret, LayerId, PageId, PeerId, StrokeId};
 
 fn random_u32() -> u32 {
     (js_sys::Math::random() * (u32::MAX as f64 + 1.0)) as u32
@@ -11,3 +11,26 @@ fn random_u64() -> u64 {
 pub fn make_id() -> StrokeId {
     StrokeId::new([random_u64(), random_u64()])
 }
+
+pub fn make_page_id() -> PageId {
+    PageId::new([random_u64(), random_u64()])
+}
+
+pub fn make_layer_id() -> LayerId {
+    LayerId::new([random_u64(), random_u64()])
+}
+
+pub fn make_client_id() -> ClientId {
+    ClientId::new([random_u64(), random_u64()])
+}
+
+pub fn make_client_secret() -> ClientSecret {
+    ClientSecret::new([random_u64(), random_u64()])
+}
+
+pub fn peer_label(peer: PeerId, name: Option<&str>) -> String {
+    match name {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => format!("Guest {:04x}", peer.value()[1] & 0xffff),
+    }
+}
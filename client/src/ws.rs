@@ -1,4 +1,5 @@
 use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
 
 use js_sys::{Reflect, Uint8Array};
@@ -6,10 +7,16 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{CloseEvent, Event, MessageEvent, WebSocket, Window};
 
-use yumboard_shared::{ClientMessage, ServerMessage};
+use yumboard_shared::{ClientMessage, PageId, ServerMessage, Stroke, StrokeId};
 
 use crate::net::websocket_url;
 
+const RECONNECT_BASE_DELAY_MS: f64 = 500.0;
+const RECONNECT_MAX_DELAY_MS: f64 = 15_000.0;
+const MAX_POINTS_PER_MESSAGE: usize = 128;
+/// Messages kept for replay while offline; past it the oldest queued edits are dropped.
+const MAX_OUTBOX_MESSAGES: usize = 10_000;
+
 #[derive(Debug)]
 pub enum WsEvent {
     Open,
@@ -18,7 +25,7 @@ pub enum WsEvent {
     Message(ServerMessage),
 }
 
-type WsHandler = Rc<RefCell<dyn FnMut(WsEvent)>>;
+type WsHandler = Rc<RefCell<dyn FnMut(&WsSender, WsEvent)>>;
 
 pub struct WsSender {
     socket: RefCell<WebSocket>,
@@ -26,6 +33,17 @@ pub struct WsSender {
     on_event: WsHandler,
     open_reported: Rc<Cell<bool>>,
     beforeunload_bound: Cell<bool>,
+    // Messages are only written to the socket once the server's `Sync` for the current
+    // connection has been handled; everything else waits in the outbox.
+    synced: Cell<bool>,
+    outbox: RefCell<VecDeque<ClientMessage>>,
+    // Set once the outbox has dropped edits since the last sync, so the warning is logged once.
+    outbox_overflowed: Cell<bool>,
+    reconnect_attempts: Cell<u32>,
+    reconnect_timer: Cell<Option<i32>>,
+    closing: Cell<bool>,
+    // Identifies this client to the server; sent ahead of everything else on each connection.
+    hello: RefCell<Option<ClientMessage>>,
 }
 
 impl WsSender {
@@ -33,20 +51,117 @@ impl WsSender {
         self.socket.borrow().ready_state() == WebSocket::OPEN
     }
 
-    pub fn send(&self, message: &ClientMessage) {
-        if !self.is_open() {
+    fn is_connecting_or_open(&self) -> bool {
+        matches!(
+            self.socket.borrow().ready_state(),
+            WebSocket::CONNECTING | WebSocket::OPEN
+        )
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced.get()
+    }
+
+    /// Sends `message`, or queues it for replay after the next sync. Messages that wipe a page
+    /// or the board are dropped instead: replayed later, they would also wipe what peers drew in
+    /// the meantime. Returns `false` if the message was dropped.
+    pub fn send(&self, message: &ClientMessage) -> bool {
+        if self.synced.get() && self.send_now(message) {
+            return true;
+        }
+        if is_destructive(message) {
+            return false;
+        }
+        self.enqueue(message.clone());
+        true
+    }
+
+    /// Sends a message only if the connection is live; nothing is queued for replay.
+    pub fn send_ephemeral(&self, message: &ClientMessage) {
+        if self.synced.get() {
+            let _ = self.send_now(message);
+        }
+    }
+
+    /// Queues a full copy of a stroke the server has not seen, replacing any partial
+    /// start/points/end messages for it that are still waiting in the outbox.
+    pub fn resend_stroke(&self, page: PageId, stroke: &Stroke, ended: bool) {
+        let Some((first, rest)) = stroke.points.split_first() else {
+            return;
+        };
+        let mut outbox = self.outbox.borrow_mut();
+        outbox.retain_mut(|message| {
+            if let ClientMessage::AddStrokes { strokes, .. } = message {
+                strokes.retain(|queued| queued.id != stroke.id);
+                return !strokes.is_empty();
+            }
+            queued_stroke_id(message) != Some(&stroke.id)
+        });
+        if stroke.shape.is_some() {
+            outbox.push_back(ClientMessage::ShapeCreate {
+                page,
+                stroke: stroke.clone(),
+            });
+            return;
+        }
+        if stroke.image.is_some() {
+            outbox.push_back(ClientMessage::ImageCreate {
+                page,
+                stroke: stroke.clone(),
+            });
+            return;
+        }
+        if stroke.text.is_some() {
+            outbox.push_back(ClientMessage::TextCreate {
+                page,
+                stroke: stroke.clone(),
+            });
+            if ended {
+                outbox.push_back(ClientMessage::TextEnd { id: stroke.id });
+            }
             return;
         }
-        if let Ok(payload) = bincode::encode_to_vec(message, bincode::config::standard()) {
-            let _ = self.socket.borrow().send_with_u8_array(&payload);
+        outbox.push_back(ClientMessage::StrokeStart {
+            page,
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            point: *first,
+            blend: stroke.blend,
+            layer: stroke.layer,
+        });
+        for chunk in rest.chunks(MAX_POINTS_PER_MESSAGE) {
+            outbox.push_back(ClientMessage::StrokePoints {
+                id: stroke.id,
+                points: chunk.to_vec(),
+            });
+        }
+        if ended {
+            outbox.push_back(ClientMessage::StrokeEnd {
+                id: stroke.id,
+                points: None,
+            });
         }
     }
 
+    /// Replaces the `hello` sent on every (re)connect and announces it right away if live.
+    pub fn set_hello(&self, message: ClientMessage) {
+        if self.is_open() {
+            let _ = self.send_now(&message);
+        }
+        *self.hello.borrow_mut() = Some(message);
+    }
+
     pub fn reconnect(self: &Rc<Self>) -> Result<(), JsValue> {
+        if let Some(timer) = self.reconnect_timer.take() {
+            self.window.clear_timeout_with_handle(timer);
+        }
         self.open_reported.set(false);
+        self.synced.set(false);
         let socket = create_socket(&self.window)?;
         {
             let mut current = self.socket.borrow_mut();
+            detach_listeners(&current);
             let _ = current.close();
             *current = socket;
         }
@@ -54,19 +169,149 @@ impl WsSender {
         Ok(())
     }
 
+    /// Page additions, removals and moves still waiting in the outbox, in order.
+    pub fn queued_page_edits(&self) -> Vec<ClientMessage> {
+        self.outbox
+            .borrow()
+            .iter()
+            .filter(|message| {
+                matches!(
+                    message,
+                    ClientMessage::PageAdd { .. }
+                        | ClientMessage::PageRemove { .. }
+                        | ClientMessage::PageMove { .. }
+                )
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Drops everything still waiting for replay, e.g. edits made before learning the
+    /// connection is view-only.
+    pub fn discard_outbox(&self) {
+        self.outbox.borrow_mut().clear();
+    }
+
     pub fn close(&self) {
+        self.closing.set(true);
         let _ = self.socket.borrow().close();
     }
 
+    fn send_now(&self, message: &ClientMessage) -> bool {
+        if !self.is_open() {
+            return false;
+        }
+        match bincode::encode_to_vec(message, bincode::config::standard()) {
+            Ok(payload) => self.socket.borrow().send_with_u8_array(&payload).is_ok(),
+            // Unencodable messages would fail the same way on replay, so drop them.
+            Err(_) => true,
+        }
+    }
+
+    /// Queues `message` for replay, keeping the outbox bounded. A `stroke:end` carrying the
+    /// simplified points makes the raw points still queued for that stroke redundant, so they go;
+    /// past `MAX_OUTBOX_MESSAGES` the oldest edits are dropped with every message of their stroke.
+    fn enqueue(&self, message: ClientMessage) {
+        let mut outbox = self.outbox.borrow_mut();
+        if let ClientMessage::StrokeEnd {
+            id,
+            points: Some(_),
+        } = &message
+        {
+            outbox.retain(|queued| {
+                !matches!(
+                    queued,
+                    ClientMessage::StrokeMove { id: queued_id, .. }
+                        | ClientMessage::StrokePoints { id: queued_id, .. }
+                        if queued_id == id
+                )
+            });
+        }
+        outbox.push_back(message);
+        while outbox.len() > MAX_OUTBOX_MESSAGES {
+            let Some(dropped) = outbox.pop_front() else {
+                break;
+            };
+            if let Some(id) = queued_stroke_id(&dropped) {
+                outbox.retain(|queued| queued_stroke_id(queued) != Some(id));
+            }
+            if !self.outbox_overflowed.replace(true) {
+                web_sys::console::warn_1(
+                    &format!(
+                        "Offline for too long: more than {MAX_OUTBOX_MESSAGES} edits are waiting, \
+                         so the oldest ones will not reach the server"
+                    )
+                    .into(),
+                );
+            }
+        }
+    }
+
+    fn finish_sync(&self) {
+        self.synced.set(true);
+        self.outbox_overflowed.set(false);
+        self.reconnect_attempts.set(0);
+        loop {
+            let Some(message) = self.outbox.borrow_mut().pop_front() else {
+                break;
+            };
+            if !self.send_now(&message) {
+                self.outbox.borrow_mut().push_front(message);
+                self.synced.set(false);
+                break;
+            }
+        }
+    }
+
+    fn schedule_reconnect(self: &Rc<Self>) {
+        self.synced.set(false);
+        if self.closing.get() {
+            return;
+        }
+        if self.reconnect_timer.get().is_some() {
+            return;
+        }
+        let attempts = self.reconnect_attempts.get();
+        self.reconnect_attempts.set(attempts.saturating_add(1));
+        let delay = reconnect_delay_ms(attempts);
+        let weak = Rc::downgrade(self);
+        let callback = Closure::once_into_js(move || {
+            let Some(sender) = weak.upgrade() else {
+                return;
+            };
+            sender.reconnect_timer.set(None);
+            // Something else (the `online` event, the Reconnect button) got there first.
+            if sender.is_connecting_or_open() {
+                return;
+            }
+            if sender.reconnect().is_err() {
+                sender.schedule_reconnect();
+            }
+        });
+        if let Ok(timer) = self
+            .window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(callback.unchecked_ref(), delay)
+        {
+            self.reconnect_timer.set(Some(timer));
+        }
+    }
+
     fn attach_listeners(self: &Rc<Self>) -> Result<(), JsValue> {
         let socket = self.socket.borrow().clone();
 
         {
             let on_event = self.on_event.clone();
             let open_reported = self.open_reported.clone();
+            let weak = Rc::downgrade(self);
             let onopen = Closure::<dyn FnMut(Event)>::new(move |_| {
+                let Some(sender) = weak.upgrade() else {
+                    return;
+                };
                 open_reported.set(true);
-                on_event.borrow_mut()(WsEvent::Open);
+                if let Some(hello) = sender.hello.borrow().as_ref() {
+                    let _ = sender.send_now(hello);
+                }
+                on_event.borrow_mut()(&sender, WsEvent::Open);
             });
             socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
             onopen.forget();
@@ -75,9 +320,14 @@ impl WsSender {
         {
             let on_event = self.on_event.clone();
             let open_reported = self.open_reported.clone();
+            let weak = Rc::downgrade(self);
             let onclose = Closure::<dyn FnMut(CloseEvent)>::new(move |_| {
+                let Some(sender) = weak.upgrade() else {
+                    return;
+                };
                 open_reported.set(false);
-                on_event.borrow_mut()(WsEvent::Close);
+                sender.schedule_reconnect();
+                on_event.borrow_mut()(&sender, WsEvent::Close);
             });
             socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
             onclose.forget();
@@ -86,9 +336,14 @@ impl WsSender {
         {
             let on_event = self.on_event.clone();
             let open_reported = self.open_reported.clone();
+            let weak = Rc::downgrade(self);
             let onerror = Closure::<dyn FnMut(Event)>::new(move |_| {
+                let Some(sender) = weak.upgrade() else {
+                    return;
+                };
                 open_reported.set(false);
-                on_event.borrow_mut()(WsEvent::Error);
+                sender.schedule_reconnect();
+                on_event.borrow_mut()(&sender, WsEvent::Error);
             });
             socket.set_onerror(Some(onerror.as_ref().unchecked_ref()));
             onerror.forget();
@@ -97,9 +352,13 @@ impl WsSender {
         {
             let on_event = self.on_event.clone();
             let open_reported = self.open_reported.clone();
+            let weak = Rc::downgrade(self);
             let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                let Some(sender) = weak.upgrade() else {
+                    return;
+                };
                 if !open_reported.replace(true) {
-                    on_event.borrow_mut()(WsEvent::Open);
+                    on_event.borrow_mut()(&sender, WsEvent::Open);
                 }
 
                 let message = if let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
@@ -142,7 +401,11 @@ impl WsSender {
                     return;
                 };
 
-                on_event.borrow_mut()(WsEvent::Message(message));
+                let is_sync = matches!(message, ServerMessage::Sync { .. });
+                on_event.borrow_mut()(&sender, WsEvent::Message(message));
+                if is_sync {
+                    sender.finish_sync();
+                }
             });
             socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
             onmessage.forget();
@@ -168,6 +431,18 @@ impl WsSender {
         }
 
         if !self.beforeunload_bound.replace(true) {
+            let sender = self.clone();
+            let ononline = Closure::<dyn FnMut(Event)>::new(move |_| {
+                // A socket still connecting is left alone; otherwise retry right away, which
+                // also cancels a pending backoff timer so it cannot open a second socket.
+                if !sender.is_connecting_or_open() {
+                    let _ = sender.reconnect();
+                }
+            });
+            self.window
+                .add_event_listener_with_callback("online", ononline.as_ref().unchecked_ref())?;
+            ononline.forget();
+
             let sender = self.clone();
             let onbeforeunload = Closure::<dyn FnMut(Event)>::new(move |_| {
                 sender.close();
@@ -215,6 +490,41 @@ fn ping_url() -> String {
     format!("/ping?t={now}")
 }
 
+fn reconnect_delay_ms(attempts: u32) -> i32 {
+    let exponential = RECONNECT_BASE_DELAY_MS * 2f64.powi(attempts.min(16) as i32);
+    let capped = exponential.min(RECONNECT_MAX_DELAY_MS);
+    let jitter = 0.75 + js_sys::Math::random() * 0.5;
+    (capped * jitter) as i32
+}
+
+fn is_destructive(message: &ClientMessage) -> bool {
+    matches!(
+        message,
+        ClientMessage::Clear { .. } | ClientMessage::Load { .. }
+    )
+}
+
+fn queued_stroke_id(message: &ClientMessage) -> Option<&StrokeId> {
+    match message {
+        ClientMessage::StrokeStart { id, .. }
+        | ClientMessage::StrokeMove { id, .. }
+        | ClientMessage::StrokePoints { id, .. }
+        | ClientMessage::StrokeEnd { id, .. } => Some(id),
+        ClientMessage::ShapeCreate { stroke, .. }
+        | ClientMessage::TextCreate { stroke, .. }
+        | ClientMessage::ImageCreate { stroke, .. } => Some(&stroke.id),
+        ClientMessage::TextUpdate { id, .. } | ClientMessage::TextEnd { id } => Some(id),
+        _ => None,
+    }
+}
+
+fn detach_listeners(socket: &WebSocket) {
+    socket.set_onopen(None);
+    socket.set_onclose(None);
+    socket.set_onerror(None);
+    socket.set_onmessage(None);
+}
+
 fn create_socket(window: &Window) -> Result<WebSocket, JsValue> {
     let ws_url = websocket_url(window)?;
     let socket = WebSocket::new(&ws_url)?;
@@ -228,7 +538,7 @@ fn create_socket(window: &Window) -> Result<WebSocket, JsValue> {
 
 pub fn connect_ws(
     window: &Window,
-    on_event: impl 'static + FnMut(WsEvent),
+    on_event: impl 'static + FnMut(&WsSender, WsEvent),
 ) -> Result<Rc<WsSender>, JsValue> {
     let socket = create_socket(window)?;
     let sender = Rc::new(WsSender {
@@ -237,6 +547,13 @@ pub fn connect_ws(
         on_event: Rc::new(RefCell::new(on_event)),
         open_reported: Rc::new(Cell::new(false)),
         beforeunload_bound: Cell::new(false),
+        synced: Cell::new(false),
+        outbox: RefCell::new(VecDeque::new()),
+        outbox_overflowed: Cell::new(false),
+        reconnect_attempts: Cell::new(0),
+        reconnect_timer: Cell::new(None),
+        closing: Cell::new(false),
+        hello: RefCell::new(None),
     });
     sender.attach_listeners()?;
     Ok(sender)
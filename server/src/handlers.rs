@@ -1,36 +1,366 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use axum::body::Bytes;
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE};
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Redirect};
+use axum::Json;
+use base64::Engine;
 use futures_util::{SinkExt, StreamExt};
-use tokio::sync::mpsc;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
-use yumboard_shared::{ClientMessage, ServerMessage};
+use yumboard_shared::image::{is_asset_hash, png_dimensions, AssetInfo, MAX_IMAGE_SIDE};
+use yumboard_shared::pdf::{render_pdf, PaperSize, PdfLayout, PdfOptions};
+use yumboard_shared::svg::{render_svg_document, SvgOptions};
+use yumboard_shared::{ClientMessage, Layer, Role, ServerMessage, Stroke};
 
-use crate::logic::{apply_client_message, broadcast_all, broadcast_except};
+use crate::logic::{apply_client_message, broadcast_all, broadcast_except, peer_id, peer_infos};
+use crate::render::{raster_image, render_png, DEFAULT_RENDER_WIDTH};
 use crate::sessions::{
-    get_or_create_session, new_session_id, normalize_session_id, save_session, SessionLoadError,
+    get_or_create_session, new_session_id, normalize_session_id, save_session, session_snapshot,
+    SessionLoadError,
 };
-use crate::state::AppState;
+use crate::state::{role_for_token, shows_asset, AppState, PersistentSessionData, Session};
+use crate::storage::StorageError;
+
+#[derive(Deserialize)]
+pub struct AccessQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RenderQuery {
+    token: Option<String>,
+    width: Option<u32>,
+    /// 1-based; the first page when absent.
+    page: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct PdfQuery {
+    token: Option<String>,
+    layout: Option<String>,
+    paper: Option<String>,
+    #[serde(default)]
+    landscape: bool,
+}
+
+const RENDER_PADDING: f64 = 16.0;
+
+/// Loads the board for a render endpoint, answering with the error status if it can't be shown.
+async fn render_snapshot(
+    state: &AppState,
+    session_id: &str,
+    token: Option<&str>,
+) -> Result<PersistentSessionData, StatusCode> {
+    authorized_snapshot(state, session_id, token)
+        .await
+        .map(|(data, _)| data)
+}
+
+/// The session `session_id` if it is loaded in memory.
+async fn live_session(state: &AppState, session_id: &str) -> Option<Arc<RwLock<Session>>> {
+    let session_id = normalize_session_id(session_id)?;
+    state.sessions.read().await.get(&session_id).cloned()
+}
+
+/// Like `render_snapshot`, also returning the role `token` grants.
+async fn authorized_snapshot(
+    state: &AppState,
+    session_id: &str,
+    token: Option<&str>,
+) -> Result<(PersistentSessionData, Role), StatusCode> {
+    let session_id = normalize_session_id(session_id).ok_or(StatusCode::NOT_FOUND)?;
+    let data = match session_snapshot(state, &session_id).await {
+        Ok(Some(data)) => data,
+        Ok(None) | Err(SessionLoadError::NotFound) => return Err(StatusCode::NOT_FOUND),
+        Err(SessionLoadError::Storage(error)) => {
+            eprintln!("Session load error for {session_id}: {error}");
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+    let Some(role) = role_for_token(data.access.as_ref(), token) else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    Ok((data, role))
+}
+
+/// The strokes of the 1-based `page` of a snapshot with the session's layers, or `None` if
+/// there is no such page.
+fn page_strokes(
+    data: PersistentSessionData,
+    page: Option<usize>,
+) -> Option<(Vec<Stroke>, Vec<Layer>)> {
+    let index = page.unwrap_or(1).checked_sub(1)?;
+    let strokes = data.pages.into_iter().nth(index)?.strokes;
+    Some((strokes, data.layers))
+}
+
+/// The bytes of every image asset drawn in `strokes`. Missing or unreadable assets are left out
+/// and the renderers skip their images.
+async fn load_assets<'a>(
+    state: &AppState,
+    strokes: impl IntoIterator<Item = &'a Stroke>,
+) -> HashMap<String, Vec<u8>> {
+    let mut assets = HashMap::new();
+    for image in strokes
+        .into_iter()
+        .filter_map(|stroke| stroke.image.as_ref())
+    {
+        if assets.contains_key(&image.hash) {
+            continue;
+        }
+        match state.storage.load_asset(&image.hash).await {
+            Ok(bytes) => {
+                assets.insert(image.hash.clone(), bytes);
+            }
+            Err(error) => eprintln!("Asset load error for {}: {error}", image.hash),
+        }
+    }
+    assets
+}
+
+/// Stores a PNG for the session's images and answers with its hash and size. Requires a role
+/// that can edit.
+pub async fn upload_asset_handler(
+    Path(session_id): Path<String>,
+    Query(query): Query<AccessQuery>,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> impl IntoResponse {
+    match authorized_snapshot(&state, &session_id, query.token.as_deref()).await {
+        Ok((_, role)) if role.can_edit() => {}
+        Ok(_) => return StatusCode::FORBIDDEN.into_response(),
+        Err(status) => return status.into_response(),
+    }
+    let Some((width, height)) = png_dimensions(&body) else {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    };
+    if width > MAX_IMAGE_SIDE || height > MAX_IMAGE_SIDE {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    let hash = format!("{:x}", Sha256::digest(&body));
+    if let Err(error) = state.storage.save_asset(&hash, &body).await {
+        eprintln!("Asset save error for {session_id}: {error}");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    if let Some(session) = live_session(&state, &session_id).await {
+        session.write().await.assets.insert(hash.clone());
+    }
+    Json(AssetInfo {
+        hash,
+        width,
+        height,
+    })
+    .into_response()
+}
+
+pub async fn asset_handler(
+    Path((session_id, hash)): Path<(String, String)>,
+    Query(query): Query<AccessQuery>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if !is_asset_hash(&hash) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let data = match render_snapshot(&state, &session_id, query.token.as_deref()).await {
+        Ok(data) => data,
+        Err(status) => return status.into_response(),
+    };
+    // Only assets this session shows or just received, so a hash alone opens nothing.
+    let shown = data
+        .pages
+        .iter()
+        .flat_map(|page| &page.strokes)
+        .any(|stroke| shows_asset(stroke, &hash));
+    let uploaded = match live_session(&state, &session_id).await {
+        Some(session) => session.read().await.assets.contains(&hash),
+        None => false,
+    };
+    if !shown && !uploaded {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match state.storage.load_asset(&hash).await {
+        Ok(bytes) => (
+            [
+                (CONTENT_TYPE, "image/png"),
+                // Assets never change under the same hash.
+                (CACHE_CONTROL, "private, max-age=31536000, immutable"),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(StorageError::NotFound) => StatusCode::NOT_FOUND.into_response(),
+        Err(StorageError::Other(error)) => {
+            eprintln!("Asset load error for {hash}: {error}");
+            StatusCode::SERVICE_UNAVAILABLE.into_response()
+        }
+    }
+}
+
+pub async fn render_svg_handler(
+    Path(session_id): Path<String>,
+    Query(query): Query<RenderQuery>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let data = match render_snapshot(&state, &session_id, query.token.as_deref()).await {
+        Ok(data) => data,
+        Err(status) => return status.into_response(),
+    };
+    let Some((strokes, layers)) = page_strokes(data, query.page) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let image_hrefs = load_assets(&state, &strokes)
+        .await
+        .into_iter()
+        .map(|(hash, bytes)| {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+            (hash, format!("data:image/png;base64,{encoded}"))
+        })
+        .collect();
+    let options = SvgOptions {
+        background: Some("#ffffff".to_string()),
+        padding: RENDER_PADDING,
+        region: None,
+        layers,
+        image_hrefs,
+    };
+    let svg = render_svg_document(&strokes, &options);
+    ([(CONTENT_TYPE, "image/svg+xml")], svg).into_response()
+}
+
+pub async fn render_png_handler(
+    Path(session_id): Path<String>,
+    Query(query): Query<RenderQuery>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let data = match render_snapshot(&state, &session_id, query.token.as_deref()).await {
+        Ok(data) => data,
+        Err(status) => return status.into_response(),
+    };
+    let Some((strokes, layers)) = page_strokes(data, query.page) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let width = query.width.unwrap_or(DEFAULT_RENDER_WIDTH);
+    let assets = load_assets(&state, &strokes).await;
+    let rendered = tokio::task::spawn_blocking(move || {
+        render_png(&strokes, &layers, &assets, width, RENDER_PADDING)
+    })
+    .await;
+    match rendered {
+        Ok(Ok(png)) => ([(CONTENT_TYPE, "image/png")], png).into_response(),
+        Ok(Err(error)) => {
+            eprintln!("Render failed for {session_id}: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(error) => {
+            eprintln!("Render task failed for {session_id}: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn export_pdf_handler(
+    Path(session_id): Path<String>,
+    Query(query): Query<PdfQuery>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let paper = match query.paper.as_deref() {
+        Some(name) => match PaperSize::from_name(name) {
+            Some(paper) => Some(paper),
+            None => return StatusCode::BAD_REQUEST.into_response(),
+        },
+        None => None,
+    };
+    let Some(layout) = PdfLayout::from_name(
+        query.layout.as_deref().unwrap_or("fit"),
+        paper,
+        query.landscape,
+    ) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let data = match render_snapshot(&state, &session_id, query.token.as_deref()).await {
+        Ok(data) => data,
+        Err(status) => return status.into_response(),
+    };
+    let strokes = data
+        .pages
+        .iter()
+        .flat_map(|page| &page.strokes)
+        .collect::<Vec<_>>();
+    let assets = load_assets(&state, strokes).await;
+    let rendered = tokio::task::spawn_blocking(move || {
+        let options = PdfOptions {
+            layout,
+            images: assets
+                .into_iter()
+                .filter_map(|(hash, bytes)| Some((hash, raster_image(&bytes)?)))
+                .collect(),
+            layers: data.layers.clone(),
+            ..PdfOptions::default()
+        };
+        let boards = data
+            .pages
+            .iter()
+            .map(|page| page.strokes.as_slice())
+            .collect::<Vec<_>>();
+        render_pdf(&boards, &options)
+    })
+    .await;
+    match rendered {
+        Ok(Some(pdf)) => (
+            [
+                (CONTENT_TYPE, "application/pdf"),
+                (CONTENT_DISPOSITION, "inline; filename=\"yumboard.pdf\""),
+            ],
+            pdf,
+        )
+            .into_response(),
+        // The tiled layout would need more than `MAX_PDF_PAGES` pages.
+        Ok(None) => StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+        Err(error) => {
+            eprintln!("PDF export task failed for {session_id}: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
 
 pub async fn ping_handler() -> impl IntoResponse {
     StatusCode::NO_CONTENT
 }
 
-pub async fn root_handler(State(state): State<AppState>) -> impl IntoResponse {
+/// Creates a fresh session and returns its id with the owner's share URL query.
+async fn create_owned_session(state: &AppState) -> (String, String) {
     let session_id = new_session_id();
-    if let Err(SessionLoadError::Storage(error)) =
-        get_or_create_session(&state, &session_id, true).await
-    {
-        eprintln!("Session load error for {session_id}: {error}");
+    match get_or_create_session(state, &session_id, true).await {
+        Ok(session) => {
+            let query = match &session.read().await.access {
+                Some(access) => format!("?token={}", access.owner_secret),
+                None => String::new(),
+            };
+            (session_id, query)
+        }
+        Err(SessionLoadError::NotFound) => (session_id, String::new()),
+        Err(SessionLoadError::Storage(error)) => {
+            eprintln!("Session load error for {session_id}: {error}");
+            (session_id, String::new())
+        }
     }
-    Redirect::to(&format!("/s/{session_id}"))
+}
+
+pub async fn root_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let (session_id, query) = create_owned_session(&state).await;
+    Redirect::to(&format!("/s/{session_id}{query}"))
 }
 
 pub async fn session_handler(
     Path(session_id): Path<String>,
+    Query(query): Query<AccessQuery>,
     State(state): State<AppState>,
     axum::Extension(index_file): axum::Extension<std::path::PathBuf>,
 ) -> impl IntoResponse {
@@ -38,13 +368,33 @@ pub async fn session_handler(
         Some(id) => id,
         None => return StatusCode::NOT_FOUND.into_response(),
     };
-    if let Err(SessionLoadError::Storage(error)) =
-        get_or_create_session(&state, &session_id, false).await
-    {
-        eprintln!("Session load error for {session_id}: {error}");
-        let new_session_id = new_session_id();
-        let _ = get_or_create_session(&state, &new_session_id, true).await;
-        return Redirect::to(&format!("/s/{new_session_id}?load_error=1")).into_response();
+    match get_or_create_session(&state, &session_id, false).await {
+        Ok(session) => {
+            if session
+                .read()
+                .await
+                .role_for_token(query.token.as_deref())
+                .is_none()
+            {
+                return (
+                    StatusCode::FORBIDDEN,
+                    "This link is not valid for this board.",
+                )
+                    .into_response();
+            }
+        }
+        Err(SessionLoadError::NotFound) => {
+            return (StatusCode::NOT_FOUND, "This board does not exist.").into_response();
+        }
+        Err(SessionLoadError::Storage(error)) => {
+            eprintln!("Session load error for {session_id}: {error}");
+            let (new_session_id, query) = create_owned_session(&state).await;
+            let separator = if query.is_empty() { '?' } else { '&' };
+            return Redirect::to(&format!(
+                "/s/{new_session_id}{query}{separator}load_error=1"
+            ))
+            .into_response();
+        }
     }
     match tokio::fs::read_to_string(index_file).await {
         Ok(contents) => Html(contents).into_response(),
@@ -57,6 +407,7 @@ pub async fn session_handler(
 
 pub async fn ws_handler(
     Path(session_id): Path<String>,
+    Query(query): Query<AccessQuery>,
     State(state): State<AppState>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
@@ -66,8 +417,15 @@ pub async fn ws_handler(
     };
     match get_or_create_session(&state, &session_id, false).await {
         Ok(session) => {
-            ws.on_upgrade(move |socket| handle_socket(socket, state, session_id, session))
+            let role = session.read().await.role_for_token(query.token.as_deref());
+            match role {
+                Some(role) => ws.on_upgrade(move |socket| {
+                    handle_socket(socket, state, session_id, session, role)
+                }),
+                None => StatusCode::FORBIDDEN.into_response(),
+            }
         }
+        Err(SessionLoadError::NotFound) => StatusCode::NOT_FOUND.into_response(),
         Err(SessionLoadError::Storage(error)) => {
             eprintln!("Session load error for {session_id}: {error}");
             StatusCode::SERVICE_UNAVAILABLE.into_response()
@@ -79,34 +437,75 @@ async fn handle_socket(
     socket: WebSocket,
     state: AppState,
     session_id: String,
-    session: Arc<tokio::sync::RwLock<crate::state::Session>>,
+    session: Arc<tokio::sync::RwLock<Session>>,
+    role: Role,
 ) {
     let (mut socket_sender, mut socket_receiver) = socket.split();
     let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
     let connection_id = Uuid::new_v4();
 
-    {
+    let existing_peers = {
         let mut session = session.write().await;
+        let existing_peers = peer_infos(&session, connection_id);
         session.peers.insert(connection_id, tx);
+        session.roles.insert(connection_id, role);
+        // Anonymous until `hello` moves it to (or swaps it for) the client's own history.
+        let anonymous_id = session.client_id(connection_id);
         session
             .histories
-            .insert(connection_id, crate::state::ClientHistory::default());
+            .insert(anonymous_id, crate::state::ClientHistory::default());
         eprintln!(
             "WS connected session={session_id} conn={connection_id} peers={}",
             session.peers.len()
         );
+        existing_peers
+    };
+    broadcast_except(
+        &session,
+        connection_id,
+        ServerMessage::PeerJoin {
+            peer: peer_id(connection_id),
+        },
+    )
+    .await;
+
+    // Sent ahead of `sync` so viewers never replay offline edits.
+    let access_message = {
+        let session = session.read().await;
+        let access = session.access.as_ref();
+        ServerMessage::Access {
+            role,
+            edit_token: access
+                .filter(|_| role == Role::Owner)
+                .map(|access| access.edit_token.clone()),
+            view_token: access
+                .filter(|_| role.can_edit())
+                .map(|access| access.view_token.clone()),
+        }
+    };
+    if let Ok(access_payload) = bincode::encode_to_vec(&access_message, bincode::config::standard())
+    {
+        let _ = socket_sender.send(Message::Binary(access_payload)).await;
     }
 
-    let strokes_snapshot = session.read().await.strokes.clone();
-    let strokes_len = strokes_snapshot.len();
+    let (pages_snapshot, layers_snapshot) = {
+        let session = session.read().await;
+        (session.pages.clone(), session.layers.clone())
+    };
+    let pages_len = pages_snapshot.len();
+    let strokes_len = pages_snapshot
+        .iter()
+        .map(|page| page.strokes.len())
+        .sum::<usize>();
     if let Ok(sync_payload) = bincode::encode_to_vec(
         &ServerMessage::Sync {
-            strokes: strokes_snapshot,
+            pages: pages_snapshot,
+            layers: layers_snapshot,
         },
         bincode::config::standard(),
     ) {
         eprintln!(
-            "WS sync send session={session_id} conn={connection_id} strokes={strokes_len} bytes={}",
+            "WS sync send session={session_id} conn={connection_id} pages={pages_len} strokes={strokes_len} bytes={}",
             sync_payload.len()
         );
         if let Err(error) = socket_sender.send(Message::Binary(sync_payload)).await {
@@ -117,6 +516,14 @@ async fn handle_socket(
     } else {
         eprintln!("WS sync serialize failed session={session_id} conn={connection_id}");
     }
+    if let Ok(peers_payload) = bincode::encode_to_vec(
+        &ServerMessage::Peers {
+            peers: existing_peers,
+        },
+        bincode::config::standard(),
+    ) {
+        let _ = socket_sender.send(Message::Binary(peers_payload)).await;
+    }
 
     let send_task = tokio::spawn(async move {
         while let Some(message) = rx.recv().await {
@@ -181,9 +588,7 @@ async fn handle_socket(
 
     {
         let mut session = session.write().await;
-        session.peers.remove(&connection_id);
-        session.histories.remove(&connection_id);
-        session.transform_sessions.remove(&connection_id);
+        session.detach_connection(connection_id);
         eprintln!(
             "WS disconnected session={session_id} conn={connection_id} peers={}",
             session.peers.len()
@@ -196,13 +601,21 @@ async fn handle_socket(
         }
     }
     send_task.abort();
+    broadcast_all(
+        &session,
+        ServerMessage::PeerLeave {
+            peer: peer_id(connection_id),
+        },
+    )
+    .await;
 
     let mut should_remove = false;
     let mut maybe_data = None;
     {
         let session_guard = session.read().await;
         if session_guard.peers.is_empty() {
-            should_remove = true;
+            // Detached undo histories keep the session in memory until they expire.
+            should_remove = session_guard.histories.is_empty();
             if session_guard.dirty {
                 maybe_data = Some(session_guard.to_persistent_session_data());
             }
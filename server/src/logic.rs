@@ -3,50 +3,123 @@ use std::sync::Arc;
 
 use tokio::sync::RwLock;
 use uuid::Uuid;
-use yumboard_shared::{ClientMessage, Point, ServerMessage, Stroke, StrokeId, TransformOp};
+use yumboard_shared::image::image_is_valid;
+use yumboard_shared::order::{arrange, reordered};
+use yumboard_shared::pressure::normalize_pressure;
+use yumboard_shared::shape::shape_is_valid;
+use yumboard_shared::text::{sanitize_text, scale_text};
+use yumboard_shared::{
+    sanitize_display_name, Blend, ClientMessage, Layer, Page, PageId, PeerId, PeerInfo, Point,
+    ServerMessage, Stroke, StrokeId, TransformOp, MAX_LASER_POINTS, MAX_LAYERS, MAX_PAGES,
+    MAX_POINTS_PER_STROKE, MAX_STROKES,
+};
 
-use crate::state::{Action, Session, TransformSession, MAX_POINTS_PER_STROKE, MAX_STROKES};
+use crate::state::{Action, PeerIdentity, Session, TextEditSession, TransformSession};
 
 pub fn apply_client_message(
     session: &mut Session,
     sender: Uuid,
     message: ClientMessage,
 ) -> Option<(Vec<ServerMessage>, bool)> {
-    session.dirty = true;
+    let mutating = !matches!(
+        message,
+        ClientMessage::CursorMove { .. }
+            | ClientMessage::Hello { .. }
+            | ClientMessage::LaserMove { .. }
+            | ClientMessage::PageSwitch { .. }
+    );
+    // The laser pointer is for editors but leaves the board untouched.
+    let ephemeral = matches!(message, ClientMessage::LaserMove { .. });
+    if (mutating || ephemeral) && !session.role(sender).can_edit() {
+        return None;
+    }
+    if mutating {
+        session.dirty = true;
+    }
     match message {
+        ClientMessage::Hello {
+            client_id,
+            secret,
+            name,
+        } => {
+            let name = sanitize_display_name(&name);
+            let client_id = match session.identities.get(&sender) {
+                Some(identity) => identity.client_id,
+                None => {
+                    let fallback = session.client_id(sender);
+                    let claimed = match session.client_secrets.get(&client_id) {
+                        Some(known) => *known == secret,
+                        None => {
+                            session.client_secrets.insert(client_id, secret);
+                            true
+                        }
+                    };
+                    // A wrong secret keeps the connection on its anonymous id.
+                    let client_id = if claimed { client_id } else { fallback };
+                    let anonymous = session.histories.remove(&fallback).unwrap_or_default();
+                    let history = session.histories.entry(client_id).or_insert(anonymous);
+                    history.detached_at = None;
+                    client_id
+                }
+            };
+            session.identities.insert(
+                sender,
+                PeerIdentity {
+                    client_id,
+                    name: name.clone(),
+                },
+            );
+            Some((
+                vec![ServerMessage::PeerName {
+                    peer: peer_id(sender),
+                    name,
+                }],
+                false,
+            ))
+        }
         ClientMessage::StrokeStart {
+            page,
             id,
             color,
             size,
             point,
+            blend,
+            layer,
         } => {
             let point = normalize_point(point)?;
             let size = sanitize_size(size);
+            if session.stroke(&id).is_some() || !session.layer_editable(layer) {
+                return None;
+            }
+            let author = session.author_id(sender);
             let stroke = Stroke {
-                id: id.clone(),
+                id,
                 color,
                 size,
                 points: vec![point],
+                shape: None,
+                text: None,
+                image: None,
+                blend,
+                layer,
+                author: Some(author),
             };
 
-            session.strokes.push(stroke);
-            let overflow = session.strokes.len().saturating_sub(MAX_STROKES);
-            if overflow > 0 {
-                let removed = session.strokes.drain(0..overflow).collect::<Vec<_>>();
-                for stroke in removed {
-                    session.active_ids.remove(&stroke.id);
-                    session.owners.remove(&stroke.id);
-                }
+            if !add_stroke(session, page, stroke) {
+                return None;
             }
-            session.active_ids.insert(id.clone());
-            session.owners.insert(id.clone(), sender);
+            session.active_ids.insert(id);
 
             Some((
                 vec![ServerMessage::StrokeStart {
+                    page,
                     id,
                     color,
                     size,
                     point,
+                    blend,
+                    layer,
+                    author,
                 }],
                 false,
             ))
@@ -56,12 +129,7 @@ pub fn apply_client_message(
             if !session.active_ids.contains(&id) {
                 return None;
             }
-            let id_ref = &id;
-            if let Some(stroke) = session
-                .strokes
-                .iter_mut()
-                .find(|stroke| &stroke.id == id_ref)
-            {
+            if let Some(stroke) = session.stroke_mut(&id) {
                 if stroke.points.len() < MAX_POINTS_PER_STROKE {
                     stroke.points.push(point);
 
@@ -77,14 +145,7 @@ pub fn apply_client_message(
             if !session.active_ids.contains(&id) {
                 return None;
             }
-            let id_ref = &id;
-            let Some(stroke) = session
-                .strokes
-                .iter_mut()
-                .find(|stroke| &stroke.id == id_ref)
-            else {
-                return None;
-            };
+            let stroke = session.stroke_mut(&id)?;
             if stroke.points.len() >= MAX_POINTS_PER_STROKE {
                 return None;
             }
@@ -113,320 +174,1099 @@ pub fn apply_client_message(
                 false,
             ))
         }
-        ClientMessage::StrokeEnd { id } => {
-            session.active_ids.remove(&id);
-            if let Some(owner) = session.owners.get(&id) {
-                if *owner == sender {
-                    let stroke = session
-                        .strokes
-                        .iter()
-                        .find(|stroke| &stroke.id == &id)
-                        .cloned();
-                    if let Some(stroke) = stroke {
-                        if let Some(history) = session.histories.get_mut(&sender) {
-                            history.undo.push(Action::AddStroke(stroke));
-                            history.redo.clear();
+        ClientMessage::StrokeEnd { id, points } => {
+            let was_active = session.active_ids.remove(&id);
+            let mut messages = Vec::new();
+            let author = session.stroke(&id).and_then(|stroke| stroke.author);
+            if let Some(author) = author {
+                if author == session.author_id(sender) {
+                    let fitted = points
+                        .filter(|_| was_active)
+                        .map(|points| {
+                            points
+                                .into_iter()
+                                .filter_map(normalize_point)
+                                .take(MAX_POINTS_PER_STROKE)
+                                .collect::<Vec<_>>()
+                        })
+                        .filter(|points| !points.is_empty());
+                    let page = session.locate(&id).map(|(page, _)| page);
+                    let stroke = session.stroke_mut(&id).map(|stroke| {
+                        if let Some(points) = fitted {
+                            stroke.points = points;
+                            messages.push(ServerMessage::StrokeReplace {
+                                stroke: stroke.clone(),
+                            });
+                        }
+                        stroke.clone()
+                    });
+                    if let (Some(page), Some(stroke)) = (page, stroke) {
+                        if let Some(history) = session.history_mut(sender) {
+                            history.record(Action::AddStroke(page, stroke));
                         }
                     }
                 }
             }
-            Some((vec![ServerMessage::StrokeEnd { id }], false))
+            messages.push(ServerMessage::StrokeEnd { id });
+            Some((messages, false))
         }
-        ClientMessage::Clear => {
-            let cleared = session.strokes.drain(..).collect::<Vec<_>>();
-            session.active_ids.clear();
-            session.owners.clear();
-            session.transform_sessions.clear();
+        ClientMessage::Clear { page } => {
+            let cleared = clear_page(session, page)?;
 
-            if let Some(history) = session.histories.get_mut(&sender) {
-                history.undo.push(Action::Clear { strokes: cleared });
-                history.redo.clear();
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::Clear {
+                    page,
+                    strokes: cleared,
+                });
             }
-            Some((vec![ServerMessage::Clear], false))
+            Some((vec![ServerMessage::Clear { page }], false))
         }
         ClientMessage::Undo => {
-            let action = session
-                .histories
-                .get_mut(&sender)
-                .and_then(|history| history.undo.pop())?;
-
-            match action {
-                Action::AddStroke(stroke) => {
-                    let stroke_id = stroke.id.clone();
-                    if remove_stroke(session, &stroke_id) {
-                        if let Some(history) = session.histories.get_mut(&sender) {
-                            history.redo.push(Action::AddStroke(stroke));
-                        }
-                        Some((vec![ServerMessage::StrokeRemove { id: stroke_id }], true))
-                    } else {
-                        None
-                    }
+            let action = session.history_mut(sender).and_then(|history| {
+                history.end_batch();
+                history.undo.pop()
+            })?;
+            let mut skipped = Vec::new();
+            let result = undo_action(session, sender, action, &mut skipped);
+            report_skipped(session, sender, skipped);
+            result
+        }
+        ClientMessage::Redo => {
+            let action = session.history_mut(sender).and_then(|history| {
+                history.end_batch();
+                history.redo.pop()
+            })?;
+            let mut skipped = Vec::new();
+            let result = redo_action(session, sender, action, &mut skipped);
+            report_skipped(session, sender, skipped);
+            result
+        }
+        ClientMessage::BatchStart => {
+            if let Some(history) = session.history_mut(sender) {
+                history.start_batch();
+            }
+            None
+        }
+        ClientMessage::BatchEnd => {
+            if let Some(history) = session.history_mut(sender) {
+                history.end_batch();
+            }
+            None
+        }
+        ClientMessage::Erase { id } => {
+            if !session.stroke_editable(&id) {
+                return None;
+            }
+            let (page, stroke) = remove_stroke_full(session, &id)?;
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::EraseStroke(page, stroke));
+            }
+
+            Some((vec![ServerMessage::StrokeRemove { id }], true))
+        }
+        ClientMessage::StrokeSplit { id, fragments } => {
+            let (page, _) = session.locate(&id)?;
+            let before = session.stroke(&id)?;
+            let freehand = |stroke: &Stroke| {
+                stroke.shape.is_none() && stroke.text.is_none() && stroke.image.is_none()
+            };
+            if !freehand(before)
+                || !session.layer_editable(before.layer)
+                || session.active_ids.contains(&id)
+                || fragments.len() > before.points.len()
+            {
+                return None;
+            }
+            let before = before.clone();
+            let mut fragments = fragments
+                .into_iter()
+                .map(|mut stroke| {
+                    stroke.points.truncate(MAX_POINTS_PER_STROKE);
+                    sanitize_stroke(stroke)
+                })
+                .collect::<Option<Vec<_>>>()?;
+            for stroke in &mut fragments {
+                stroke.blend = before.blend;
+                stroke.layer = before.layer;
+                stroke.author = before.author;
+            }
+            let mut ids = HashSet::new();
+            let fresh = fragments.iter().all(|stroke| {
+                freehand(stroke) && ids.insert(stroke.id) && session.stroke(&stroke.id).is_none()
+            });
+            if !fresh {
+                return None;
+            }
+            let remove = vec![id];
+            splice_strokes(session, page, &remove, fragments.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::SplitStroke {
+                    page,
+                    before,
+                    fragments: fragments.clone(),
+                });
+            }
+            Some((
+                vec![ServerMessage::StrokeSplice {
+                    page,
+                    remove,
+                    insert: fragments,
+                }],
+                false,
+            ))
+        }
+        ClientMessage::StrokeReplace { stroke } => {
+            let mut stroke = sanitize_stroke(stroke)?;
+            if !session.stroke_editable(&stroke.id)
+                || !session.layer_editable(stroke.layer)
+                || !image_known(session, &stroke)
+            {
+                return None;
+            }
+            stroke.author = session.stroke(&stroke.id)?.author;
+            let before = replace_stroke(session, stroke.clone())?;
+            let in_transform = session.transform_sessions.contains_key(&sender);
+            if !in_transform {
+                if let Some(history) = session.history_mut(sender) {
+                    history.record(Action::ReplaceStroke {
+                        before,
+                        after: stroke.clone(),
+                    });
                 }
-                Action::EraseStroke(stroke) => {
-                    add_stroke(session, stroke.clone(), Some(sender));
-                    if let Some(history) = session.histories.get_mut(&sender) {
-                        history.redo.push(Action::EraseStroke(stroke.clone()));
-                    }
-                    Some((vec![ServerMessage::StrokeRestore { stroke }], true))
+            }
+            Some((vec![ServerMessage::StrokeReplace { stroke }], false))
+        }
+        ClientMessage::ShapeCreate { page, stroke } => {
+            let mut stroke = sanitize_stroke(stroke)?;
+            stroke.shape?;
+            if session.stroke(&stroke.id).is_some() || !session.layer_editable(stroke.layer) {
+                return None;
+            }
+            stroke.author = Some(session.author_id(sender));
+            if !add_stroke(session, page, stroke.clone()) {
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::AddStroke(page, stroke.clone()));
+            }
+            Some((vec![ServerMessage::ShapeCreate { page, stroke }], false))
+        }
+        ClientMessage::AddStrokes { page, strokes } => {
+            // Unlike drawing, a batch never pushes the page's oldest strokes out: undoing it
+            // could not bring them back.
+            let room = MAX_STROKES.saturating_sub(session.page(page)?.strokes.len());
+            let author = session.author_id(sender);
+            let requested = strokes.iter().map(|stroke| stroke.id).collect::<Vec<_>>();
+            let strokes = sanitize_strokes(strokes, &mut HashSet::new())
+                .into_iter()
+                .filter(|stroke| {
+                    session.stroke(&stroke.id).is_none()
+                        && session.layer_editable(stroke.layer)
+                        && image_known(session, stroke)
+                })
+                .take(room)
+                .map(|stroke| Stroke {
+                    author: Some(author),
+                    ..stroke
+                })
+                .collect::<Vec<_>>();
+            // Ids already on the board are a replay of strokes the server has, not rejects.
+            let rejected = requested
+                .into_iter()
+                .filter(|id| {
+                    session.stroke(id).is_none() && !strokes.iter().any(|stroke| stroke.id == *id)
+                })
+                .collect::<Vec<_>>();
+            report_rejected(session, sender, page, rejected);
+            if strokes.is_empty() {
+                return None;
+            }
+            splice_strokes(session, page, &[], strokes.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::AddStrokes {
+                    page,
+                    strokes: strokes.clone(),
+                });
+            }
+            Some((
+                vec![ServerMessage::StrokeSplice {
+                    page,
+                    remove: Vec::new(),
+                    insert: strokes,
+                }],
+                false,
+            ))
+        }
+        ClientMessage::ImageCreate { page, stroke } => {
+            let mut stroke = sanitize_stroke(stroke)?;
+            stroke.image.as_ref()?;
+            if !image_known(session, &stroke)
+                || session.stroke(&stroke.id).is_some()
+                || !session.layer_editable(stroke.layer)
+            {
+                return None;
+            }
+            stroke.author = Some(session.author_id(sender));
+            if !add_stroke(session, page, stroke.clone()) {
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::AddStroke(page, stroke.clone()));
+            }
+            Some((vec![ServerMessage::ImageCreate { page, stroke }], false))
+        }
+        ClientMessage::ShapeUpdate {
+            id,
+            color,
+            size,
+            shape,
+        } => {
+            let before = session
+                .stroke(&id)
+                .filter(|stroke| stroke.shape.is_some() && session.layer_editable(stroke.layer))?
+                .clone();
+            let after = sanitize_stroke(Stroke {
+                color,
+                size,
+                shape: Some(shape),
+                ..before.clone()
+            })?;
+            replace_stroke(session, after.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::ReplaceStroke {
+                    before,
+                    after: after.clone(),
+                });
+            }
+            Some((
+                vec![ServerMessage::ShapeUpdate {
+                    id,
+                    color: after.color,
+                    size: after.size,
+                    shape: after.shape?,
+                }],
+                false,
+            ))
+        }
+        ClientMessage::TextCreate { page, stroke } => {
+            let mut stroke = sanitize_stroke(stroke)?;
+            stroke.text.as_ref()?;
+            if session.stroke(&stroke.id).is_some()
+                || session.page(page).is_none()
+                || !session.layer_editable(stroke.layer)
+            {
+                return None;
+            }
+            let mut messages = finish_text_edit(session, sender);
+            stroke.author = Some(session.author_id(sender));
+            add_stroke(session, page, stroke.clone());
+            session.text_edits.insert(
+                sender,
+                TextEditSession {
+                    id: stroke.id,
+                    before: None,
+                },
+            );
+            messages.push(ServerMessage::TextCreate { page, stroke });
+            Some((messages, false))
+        }
+        ClientMessage::TextUpdate { id, text } => {
+            let text = sanitize_text(text)?;
+            let before = session
+                .stroke(&id)
+                .filter(|stroke| stroke.text.is_some() && session.layer_editable(stroke.layer))?
+                .clone();
+            let mut messages = Vec::new();
+            let editing = session
+                .text_edits
+                .get(&sender)
+                .is_some_and(|edit| edit.id == id);
+            if !editing {
+                messages = finish_text_edit(session, sender);
+                session.text_edits.insert(
+                    sender,
+                    TextEditSession {
+                        id,
+                        before: Some(before.clone()),
+                    },
+                );
+            }
+            replace_stroke(
+                session,
+                Stroke {
+                    text: Some(text.clone()),
+                    ..before
+                },
+            )?;
+            messages.push(ServerMessage::TextUpdate { id, text });
+            Some((messages, false))
+        }
+        ClientMessage::TextEnd { id } => {
+            if !session
+                .text_edits
+                .get(&sender)
+                .is_some_and(|edit| edit.id == id)
+            {
+                return None;
+            }
+            Some((finish_text_edit(session, sender), false))
+        }
+        ClientMessage::TransformUpdate { ids, op } => {
+            let session_ids = match session.transform_sessions.get(&sender) {
+                Some(session_info) => session_info.ids.clone(),
+                None => return None,
+            };
+            let mut ids = sanitize_ids(ids);
+            if ids.is_empty() {
+                return None;
+            }
+            ids.retain(|id| {
+                session_ids.iter().any(|session_id| session_id == id) && session.stroke_editable(id)
+            });
+            if ids.is_empty() {
+                return None;
+            }
+            let op = sanitize_transform_op(op)?;
+            if !apply_transform(session, &ids, &op) {
+                return None;
+            }
+            Some((vec![ServerMessage::TransformUpdate { ids, op }], false))
+        }
+        ClientMessage::TransformStart { ids } => {
+            let mut ids = sanitize_ids(ids);
+            ids.retain(|id| session.stroke_editable(id));
+            if ids.is_empty() {
+                return None;
+            }
+            let before = session
+                .strokes()
+                .filter(|stroke| ids.iter().any(|id| id == &stroke.id))
+                .cloned()
+                .collect::<Vec<_>>();
+            session
+                .transform_sessions
+                .insert(sender, TransformSession { ids, before });
+            None
+        }
+        ClientMessage::TransformEnd { ids: _ } => {
+            let session_info = session.transform_sessions.remove(&sender);
+            let Some(session_info) = session_info else {
+                return None;
+            };
+            let after = session
+                .strokes()
+                .filter(|stroke| session_info.ids.iter().any(|id| id == &stroke.id))
+                .cloned()
+                .collect::<Vec<_>>();
+            if session_info.before.is_empty() || after.is_empty() {
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::Transform {
+                    before: session_info.before,
+                    after,
+                });
+            }
+            None
+        }
+        ClientMessage::Remove { ids } => {
+            if ids.is_empty() {
+                return None;
+            }
+            let mut removed = Vec::new();
+            for id in ids {
+                if !session.stroke_editable(&id) {
+                    continue;
                 }
-                Action::Clear { strokes } => {
-                    for stroke in &strokes {
-                        add_stroke(session, stroke.clone(), None);
-                    }
-                    if let Some(history) = session.histories.get_mut(&sender) {
-                        history.redo.push(Action::Clear {
-                            strokes: strokes.clone(),
-                        });
-                    }
-                    let messages = strokes
-                        .into_iter()
-                        .map(|stroke| ServerMessage::StrokeRestore { stroke })
-                        .collect::<Vec<_>>();
-                    Some((messages, true))
+                let stroke = remove_stroke_full(session, &id);
+                if let Some(stroke) = stroke {
+                    removed.push(stroke);
                 }
-                Action::ReplaceStroke { before, after } => {
-                    let replaced = replace_stroke(session, before.clone());
-                    if replaced.is_some() {
-                        if let Some(history) = session.histories.get_mut(&sender) {
-                            history.redo.push(Action::ReplaceStroke {
-                                before: before.clone(),
-                                after,
-                            });
-                        }
-                        Some((vec![ServerMessage::StrokeReplace { stroke: before }], true))
-                    } else {
-                        None
-                    }
+            }
+            if removed.is_empty() {
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.record_all(
+                    removed
+                        .iter()
+                        .map(|(page, stroke)| Action::EraseStroke(*page, stroke.clone()))
+                        .collect(),
+                );
+            }
+            let messages = removed
+                .into_iter()
+                .map(|(_, stroke)| ServerMessage::StrokeRemove { id: stroke.id })
+                .collect::<Vec<_>>();
+            Some((messages, false))
+        }
+        ClientMessage::Reorder { ids, to } => {
+            let (page, _) = session.locate(ids.first()?)?;
+            let ids = ids
+                .into_iter()
+                .filter(|id| {
+                    session.stroke_editable(id)
+                        && session.locate(id).is_some_and(|(other, _)| other == page)
+                })
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                return None;
+            }
+            let before = page_order(session, page)?;
+            let after = reordered(&session.page(page)?.strokes, &ids, to);
+            if after == before {
+                return None;
+            }
+            let message = arrange_page(session, page, &after)?;
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::Reorder {
+                    page,
+                    before,
+                    after,
+                });
+            }
+            Some((vec![message], false))
+        }
+        ClientMessage::Load { pages, layers } => {
+            let layers = sanitize_layers(layers);
+            let mut pages = sanitize_pages(pages, &layers);
+            for page in &mut pages {
+                page.strokes.retain(|stroke| image_known(session, stroke));
+            }
+            session.pages = pages.clone();
+            session.layers = layers.clone();
+            session.active_ids.clear();
+            session.transform_sessions.clear();
+            session.text_edits.clear();
+
+            for history in session.histories.values_mut() {
+                history.undo.clear();
+                history.redo.clear();
+                history.batch = None;
+            }
+            Some((vec![ServerMessage::Sync { pages, layers }], true))
+        }
+        ClientMessage::CursorMove { point, color } => {
+            let point = match point {
+                Some(point) => Some(normalize_point(point)?),
+                None => None,
+            };
+            Some((
+                vec![ServerMessage::PeerCursor {
+                    peer: peer_id(sender),
+                    point,
+                    color,
+                }],
+                false,
+            ))
+        }
+        ClientMessage::LaserMove { points, start } => {
+            let points = points
+                .into_iter()
+                .filter_map(normalize_point)
+                .take(MAX_LASER_POINTS)
+                .collect::<Vec<_>>();
+            if points.is_empty() {
+                return None;
+            }
+            Some((
+                vec![ServerMessage::PeerLaser {
+                    peer: peer_id(sender),
+                    points,
+                    start,
+                }],
+                false,
+            ))
+        }
+        ClientMessage::PageAdd { page, index } => {
+            if session.page(page).is_some() || session.pages.len() >= MAX_PAGES {
+                return None;
+            }
+            let index = index.min(session.pages.len());
+            session.pages.insert(index, Page::new(page));
+            Some((vec![ServerMessage::PageAdd { page, index }], false))
+        }
+        ClientMessage::PageRemove { page } => {
+            let (removed, index) = remove_page(session, page)?;
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::RemovePage {
+                    page: removed,
+                    index,
+                });
+            }
+            Some((vec![ServerMessage::PageRemove { page }], false))
+        }
+        ClientMessage::PageMove { page, index } => {
+            let from = session.pages.iter().position(|item| item.id == page)?;
+            let moved = session.pages.remove(from);
+            let index = index.min(session.pages.len());
+            session.pages.insert(index, moved);
+            Some((vec![ServerMessage::PageMove { page, index }], false))
+        }
+        ClientMessage::PageSwitch { page } => {
+            session.page(page)?;
+            session.viewing.insert(sender, page);
+            Some((
+                vec![ServerMessage::PeerPage {
+                    peer: peer_id(sender),
+                    page,
+                }],
+                false,
+            ))
+        }
+        ClientMessage::LayerAdd { layer, index } => {
+            if session.layers.len() >= MAX_LAYERS
+                || session.layers.iter().any(|item| item.id == layer.id)
+            {
+                return None;
+            }
+            let layer = sanitize_layer(layer)?;
+            let index = index.min(session.layers.len());
+            session.layers.insert(index, layer);
+            Some((layers_message(session), true))
+        }
+        ClientMessage::LayerUpdate { layer } => {
+            let layer = sanitize_layer(layer)?;
+            let slot = session.layers.iter_mut().find(|item| item.id == layer.id)?;
+            *slot = layer;
+            Some((layers_message(session), true))
+        }
+        ClientMessage::LayerMove { layer, index } => {
+            let from = session.layers.iter().position(|item| item.id == layer)?;
+            let moved = session.layers.remove(from);
+            let index = index.min(session.layers.len());
+            session.layers.insert(index, moved);
+            Some((layers_message(session), true))
+        }
+        ClientMessage::LayerRemove { layer } => {
+            if session.layers.len() <= 1 || session.strokes().any(|stroke| stroke.layer == layer) {
+                return None;
+            }
+            let index = session.layers.iter().position(|item| item.id == layer)?;
+            session.layers.remove(index);
+            Some((layers_message(session), true))
+        }
+    }
+}
+
+/// Reverts `action` and pushes its redo onto the history of `sender`. Strokes someone else
+/// changed since are left alone and added to `skipped`.
+fn undo_action(
+    session: &mut Session,
+    sender: Uuid,
+    action: Action,
+    skipped: &mut Vec<StrokeId>,
+) -> Option<(Vec<ServerMessage>, bool)> {
+    match action {
+        Action::Batch(actions) => {
+            let mark = session
+                .history_mut(sender)
+                .map_or(0, |history| history.redo.len());
+            let mut messages = Vec::new();
+            for action in actions.into_iter().rev() {
+                if let Some((undone, _)) = undo_action(session, sender, action, skipped) {
+                    messages.extend(undone);
                 }
-                Action::Transform { before, after } => {
-                    let mut replaced = Vec::new();
-                    for stroke in &before {
-                        if replace_stroke(session, stroke.clone()).is_some() {
-                            replaced.push(stroke.clone());
-                        }
-                    }
-                    if replaced.is_empty() {
-                        return None;
-                    }
-                    if let Some(history) = session.histories.get_mut(&sender) {
-                        history.redo.push(Action::Transform { before, after });
-                    }
-                    let messages = replaced
-                        .into_iter()
-                        .map(|stroke| ServerMessage::StrokeReplace { stroke })
-                        .collect::<Vec<_>>();
-                    Some((messages, true))
+            }
+            // Gather the redo entries the parts pushed back into one, in gesture order.
+            if let Some(history) = session.history_mut(sender) {
+                let mut redo = history.redo.split_off(mark.min(history.redo.len()));
+                redo.reverse();
+                if !redo.is_empty() {
+                    history.redo.push(Action::Batch(redo));
                 }
             }
+            (!messages.is_empty()).then_some((messages, true))
         }
-        ClientMessage::Redo => {
-            let action = session
-                .histories
-                .get_mut(&sender)
-                .and_then(|history| history.redo.pop())?;
-
-            match action {
-                Action::AddStroke(stroke) => {
-                    add_stroke(session, stroke.clone(), Some(sender));
-                    if let Some(history) = session.histories.get_mut(&sender) {
-                        history.undo.push(Action::AddStroke(stroke.clone()));
-                    }
-                    Some((vec![ServerMessage::StrokeRestore { stroke }], true))
-                }
-                Action::EraseStroke(stroke) => {
-                    let stroke_id = stroke.id.clone();
-                    if remove_stroke(session, &stroke_id) {
-                        if let Some(history) = session.histories.get_mut(&sender) {
-                            history.undo.push(Action::EraseStroke(stroke));
-                        }
-                        Some((vec![ServerMessage::StrokeRemove { id: stroke_id }], true))
-                    } else {
-                        None
-                    }
+        Action::AddStroke(page, stroke) => {
+            let stroke_id = stroke.id;
+            if !history_editable(session, &stroke, skipped) {
+                return None;
+            }
+            if remove_stroke(session, &stroke_id) {
+                if let Some(history) = session.history_mut(sender) {
+                    history.redo.push(Action::AddStroke(page, stroke));
                 }
-                Action::Clear { strokes } => {
-                    session.strokes.clear();
-                    session.active_ids.clear();
-                    session.owners.clear();
+                Some((vec![ServerMessage::StrokeRemove { id: stroke_id }], true))
+            } else {
+                None
+            }
+        }
+        Action::AddStrokes { page, strokes } => {
+            let strokes = unlocked(session, strokes, |stroke| stroke, skipped);
+            let ids = strokes.iter().map(|stroke| stroke.id).collect::<Vec<_>>();
+            let remove = splice_strokes(session, page, &ids, Vec::new())?;
+            if remove.is_empty() {
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::AddStrokes { page, strokes });
+            }
+            Some((
+                vec![ServerMessage::StrokeSplice {
+                    page,
+                    remove,
+                    insert: Vec::new(),
+                }],
+                true,
+            ))
+        }
+        Action::EraseStroke(page, stroke) => {
+            if !history_editable(session, &stroke, skipped)
+                || !add_stroke(session, page, stroke.clone())
+            {
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::EraseStroke(page, stroke.clone()));
+            }
+            Some((vec![ServerMessage::StrokeRestore { page, stroke }], true))
+        }
+        Action::Clear { page, strokes } => {
+            session.page(page)?;
+            let strokes = unlocked(session, strokes, |stroke| stroke, skipped);
+            if strokes.is_empty() {
+                return None;
+            }
+            for stroke in &strokes {
+                add_stroke(session, page, stroke.clone());
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::Clear {
+                    page,
+                    strokes: strokes.clone(),
+                });
+            }
+            let messages = strokes
+                .into_iter()
+                .map(|stroke| ServerMessage::StrokeRestore { page, stroke })
+                .collect::<Vec<_>>();
+            Some((messages, true))
+        }
+        Action::ReplaceStroke { before, after } => {
+            let (pairs, conflicts) = untouched(session, vec![after], vec![before]);
+            skipped.extend(conflicts);
+            let (after, before) = unlocked(session, pairs, |(_, before)| before, skipped).pop()?;
+            replace_stroke(session, before.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::ReplaceStroke {
+                    before: before.clone(),
+                    after,
+                });
+            }
+            Some((vec![ServerMessage::StrokeReplace { stroke: before }], true))
+        }
+        Action::Transform { before, after } => {
+            let (pairs, conflicts) = untouched(session, after, before);
+            skipped.extend(conflicts);
+            let pairs = unlocked(session, pairs, |(_, before)| before, skipped);
+            if pairs.is_empty() {
+                return None;
+            }
+            let (after, before): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+            for stroke in &before {
+                replace_stroke(session, stroke.clone());
+            }
+            let messages = before
+                .iter()
+                .cloned()
+                .map(|stroke| ServerMessage::StrokeReplace { stroke })
+                .collect::<Vec<_>>();
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::Transform { before, after });
+            }
+            Some((messages, true))
+        }
+        Action::SplitStroke {
+            page,
+            before,
+            fragments,
+        } => {
+            if !history_editable(session, &before, skipped) {
+                return None;
+            }
+            // Bringing the original back over fragments someone changed or erased since would
+            // undo their edit too.
+            let changed = fragments
+                .iter()
+                .filter(|fragment| session.stroke(&fragment.id) != Some(fragment))
+                .map(|fragment| fragment.id)
+                .collect::<Vec<_>>();
+            if !changed.is_empty() {
+                skipped.extend(changed);
+                return None;
+            }
+            let remove = fragments.iter().map(|stroke| stroke.id).collect::<Vec<_>>();
+            let removed = splice_strokes(session, page, &remove, vec![before.clone()])?;
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::SplitStroke {
+                    page,
+                    before: before.clone(),
+                    fragments,
+                });
+            }
+            Some((
+                vec![ServerMessage::StrokeSplice {
+                    page,
+                    remove: removed,
+                    insert: vec![before],
+                }],
+                true,
+            ))
+        }
+        Action::RemovePage { page, index } => {
+            if session.page(page.id).is_some() || session.pages.len() >= MAX_PAGES {
+                return None;
+            }
+            let index = index.min(session.pages.len());
+            session.pages.insert(index, page.clone());
+            let id = page.id;
+            let mut messages = vec![ServerMessage::PageAdd { page: id, index }];
+            messages.extend(
+                page.strokes
+                    .iter()
+                    .cloned()
+                    .map(|stroke| ServerMessage::StrokeRestore { page: id, stroke }),
+            );
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::RemovePage { page, index });
+            }
+            Some((messages, true))
+        }
+        Action::Reorder {
+            page,
+            before,
+            after,
+        } => {
+            if !order_untouched(session, page, &after)? || !order_editable(session, &before, &after)
+            {
+                skipped.extend(moved_ids(&before, &after));
+                return None;
+            }
+            let message = arrange_page(session, page, &before)?;
+            if let Some(history) = session.history_mut(sender) {
+                history.redo.push(Action::Reorder {
+                    page,
+                    before,
+                    after,
+                });
+            }
+            Some((vec![message], true))
+        }
+    }
+}
 
-                    if let Some(history) = session.histories.get_mut(&sender) {
-                        history.undo.push(Action::Clear { strokes });
-                    }
-                    Some((vec![ServerMessage::Clear], true))
-                }
-                Action::ReplaceStroke { before, after } => {
-                    let replaced = replace_stroke(session, after.clone());
-                    if replaced.is_some() {
-                        if let Some(history) = session.histories.get_mut(&sender) {
-                            history.undo.push(Action::ReplaceStroke {
-                                before,
-                                after: after.clone(),
-                            });
-                        }
-                        Some((vec![ServerMessage::StrokeReplace { stroke: after }], true))
-                    } else {
-                        None
-                    }
+/// Reapplies `action` and pushes it back onto the undo history of `sender`. Strokes someone
+/// else changed since are left alone and added to `skipped`.
+fn redo_action(
+    session: &mut Session,
+    sender: Uuid,
+    action: Action,
+    skipped: &mut Vec<StrokeId>,
+) -> Option<(Vec<ServerMessage>, bool)> {
+    match action {
+        Action::Batch(actions) => {
+            let mark = session
+                .history_mut(sender)
+                .map_or(0, |history| history.undo.len());
+            let mut messages = Vec::new();
+            for action in actions {
+                if let Some((redone, _)) = redo_action(session, sender, action, skipped) {
+                    messages.extend(redone);
                 }
-                Action::Transform { before, after } => {
-                    let mut replaced = Vec::new();
-                    for stroke in &after {
-                        if replace_stroke(session, stroke.clone()).is_some() {
-                            replaced.push(stroke.clone());
-                        }
-                    }
-                    if replaced.is_empty() {
-                        return None;
-                    }
-                    if let Some(history) = session.histories.get_mut(&sender) {
-                        history.undo.push(Action::Transform {
-                            before,
-                            after: after.clone(),
-                        });
-                    }
-                    let messages = replaced
-                        .into_iter()
-                        .map(|stroke| ServerMessage::StrokeReplace { stroke })
-                        .collect::<Vec<_>>();
-                    Some((messages, true))
+            }
+            if let Some(history) = session.history_mut(sender) {
+                let undo = history.undo.split_off(mark.min(history.undo.len()));
+                if !undo.is_empty() {
+                    history.undo.push(Action::Batch(undo));
                 }
             }
+            (!messages.is_empty()).then_some((messages, true))
         }
-        ClientMessage::Erase { id } => {
-            let id_ref = &id;
-            let removed = if let Some(index) = session.strokes.iter().position(|s| &s.id == id_ref)
+        Action::AddStroke(page, stroke) => {
+            if !history_editable(session, &stroke, skipped)
+                || !add_stroke(session, page, stroke.clone())
             {
-                Some(session.strokes.remove(index))
-            } else {
-                None
-            };
-
-            if let Some(stroke) = removed {
-                session.active_ids.remove(&id);
-                session.owners.remove(&id);
-                if let Some(history) = session.histories.get_mut(&sender) {
-                    history.undo.push(Action::EraseStroke(stroke));
-                    history.redo.clear();
+                return None;
+            }
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::AddStroke(page, stroke.clone()));
+            }
+            Some((vec![ServerMessage::StrokeRestore { page, stroke }], true))
+        }
+        Action::AddStrokes { page, strokes } => {
+            let room = MAX_STROKES.saturating_sub(session.page(page)?.strokes.len());
+            let strokes = unlocked(session, strokes, |stroke| stroke, skipped);
+            let insert = strokes
+                .iter()
+                .filter(|stroke| session.stroke(&stroke.id).is_none())
+                .take(room)
+                .cloned()
+                .collect::<Vec<_>>();
+            if insert.is_empty() {
+                return None;
+            }
+            splice_strokes(session, page, &[], insert.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::AddStrokes { page, strokes });
+            }
+            Some((
+                vec![ServerMessage::StrokeSplice {
+                    page,
+                    remove: Vec::new(),
+                    insert,
+                }],
+                true,
+            ))
+        }
+        Action::EraseStroke(page, stroke) => {
+            let stroke_id = stroke.id;
+            if !history_editable(session, &stroke, skipped) {
+                return None;
+            }
+            if remove_stroke(session, &stroke_id) {
+                if let Some(history) = session.history_mut(sender) {
+                    history.undo.push(Action::EraseStroke(page, stroke));
                 }
-
-                Some((vec![ServerMessage::StrokeRemove { id }], true))
+                Some((vec![ServerMessage::StrokeRemove { id: stroke_id }], true))
             } else {
                 None
             }
         }
-        ClientMessage::StrokeReplace { stroke } => {
-            let stroke = sanitize_stroke(stroke)?;
-            let before = replace_stroke(session, stroke.clone())?;
-            let in_transform = session.transform_sessions.contains_key(&sender);
-            if !in_transform {
-                if let Some(history) = session.histories.get_mut(&sender) {
-                    history.undo.push(Action::ReplaceStroke {
-                        before,
-                        after: stroke.clone(),
-                    });
-                    history.redo.clear();
-                }
+        Action::Clear { page, .. } => {
+            // Strokes on layers locked since stay, like for a fresh `clear`.
+            let strokes = clear_page(session, page)?;
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::Clear { page, strokes });
             }
-            Some((vec![ServerMessage::StrokeReplace { stroke }], false))
+            Some((vec![ServerMessage::Clear { page }], true))
         }
-        ClientMessage::TransformUpdate { ids, op } => {
-            let session_ids = match session.transform_sessions.get(&sender) {
-                Some(session_info) => session_info.ids.clone(),
-                None => return None,
-            };
-            let mut ids = sanitize_ids(ids);
-            if ids.is_empty() {
-                return None;
-            }
-            ids.retain(|id| session_ids.iter().any(|session_id| session_id == id));
-            if ids.is_empty() {
-                return None;
-            }
-            let op = sanitize_transform_op(op)?;
-            if !apply_transform(session, &ids, &op) {
-                return None;
+        Action::ReplaceStroke { before, after } => {
+            let (pairs, conflicts) = untouched(session, vec![before], vec![after]);
+            skipped.extend(conflicts);
+            let (before, after) = unlocked(session, pairs, |(_, after)| after, skipped).pop()?;
+            replace_stroke(session, after.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::ReplaceStroke {
+                    before,
+                    after: after.clone(),
+                });
             }
-            Some((vec![ServerMessage::TransformUpdate { ids, op }], false))
+            Some((vec![ServerMessage::StrokeReplace { stroke: after }], true))
         }
-        ClientMessage::TransformStart { ids } => {
-            let ids = sanitize_ids(ids);
-            if ids.is_empty() {
+        Action::Transform { before, after } => {
+            let (pairs, conflicts) = untouched(session, before, after);
+            skipped.extend(conflicts);
+            let pairs = unlocked(session, pairs, |(_, after)| after, skipped);
+            if pairs.is_empty() {
                 return None;
             }
-            let before = session
-                .strokes
+            let (before, after): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+            for stroke in &after {
+                replace_stroke(session, stroke.clone());
+            }
+            let messages = after
                 .iter()
-                .filter(|stroke| ids.iter().any(|id| id == &stroke.id))
                 .cloned()
+                .map(|stroke| ServerMessage::StrokeReplace { stroke })
                 .collect::<Vec<_>>();
-            session
-                .transform_sessions
-                .insert(sender, TransformSession { ids, before });
-            None
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::Transform { before, after });
+            }
+            Some((messages, true))
         }
-        ClientMessage::TransformEnd { ids: _ } => {
-            let session_info = session.transform_sessions.remove(&sender);
-            let Some(session_info) = session_info else {
+        Action::SplitStroke {
+            before, fragments, ..
+        } => {
+            let (page, _) = session.locate(&before.id)?;
+            if !history_editable(session, &before, skipped) {
                 return None;
-            };
-            let after = session
-                .strokes
-                .iter()
-                .filter(|stroke| session_info.ids.iter().any(|id| id == &stroke.id))
-                .cloned()
-                .collect::<Vec<_>>();
-            if session_info.before.is_empty() || after.is_empty() {
+            }
+            if session.stroke(&before.id) != Some(&before) {
+                skipped.push(before.id);
                 return None;
             }
-            if let Some(history) = session.histories.get_mut(&sender) {
-                history.undo.push(Action::Transform {
-                    before: session_info.before,
-                    after,
+            let remove = vec![before.id];
+            splice_strokes(session, page, &remove, fragments.clone())?;
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::SplitStroke {
+                    page,
+                    before,
+                    fragments: fragments.clone(),
                 });
-                history.redo.clear();
             }
-            None
+            Some((
+                vec![ServerMessage::StrokeSplice {
+                    page,
+                    remove,
+                    insert: fragments,
+                }],
+                true,
+            ))
         }
-        ClientMessage::Remove { ids } => {
-            if ids.is_empty() {
-                return None;
-            }
-            let mut removed = Vec::new();
-            for id in ids {
-                let stroke = remove_stroke_full(session, &id);
-                if let Some(stroke) = stroke {
-                    removed.push(stroke);
-                }
+        Action::RemovePage { page, .. } => {
+            let (page, index) = remove_page(session, page.id)?;
+            let id = page.id;
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::RemovePage { page, index });
             }
-            if removed.is_empty() {
+            Some((vec![ServerMessage::PageRemove { page: id }], true))
+        }
+        Action::Reorder {
+            page,
+            before,
+            after,
+        } => {
+            if !order_untouched(session, page, &before)?
+                || !order_editable(session, &before, &after)
+            {
+                skipped.extend(moved_ids(&before, &after));
                 return None;
             }
-            if let Some(history) = session.histories.get_mut(&sender) {
-                for stroke in &removed {
-                    history.undo.push(Action::EraseStroke(stroke.clone()));
-                }
-                history.redo.clear();
+            let message = arrange_page(session, page, &after)?;
+            if let Some(history) = session.history_mut(sender) {
+                history.undo.push(Action::Reorder {
+                    page,
+                    before,
+                    after,
+                });
             }
-            let messages = removed
-                .into_iter()
-                .map(|stroke| ServerMessage::StrokeRemove { id: stroke.id })
-                .collect::<Vec<_>>();
-            Some((messages, false))
+            Some((vec![message], true))
         }
-        ClientMessage::Load { strokes } => {
-            let strokes = sanitize_strokes(strokes);
-            session.strokes = strokes.clone();
-            session.active_ids.clear();
-            session.owners.clear();
-            session.transform_sessions.clear();
+    }
+}
 
-            for history in session.histories.values_mut() {
-                history.undo.clear();
-                history.redo.clear();
-            }
-            Some((vec![ServerMessage::Sync { strokes }], true))
+/// Pairs each stroke of `expected` that is still on the board exactly as recorded with its
+/// counterpart (by id) in `target`, the state an undo or redo would write. Strokes changed
+/// since, by anyone, are returned by id instead; strokes gone from the board are dropped.
+fn untouched(
+    session: &Session,
+    expected: Vec<Stroke>,
+    mut target: Vec<Stroke>,
+) -> (Vec<(Stroke, Stroke)>, Vec<StrokeId>) {
+    let mut pairs = Vec::new();
+    let mut conflicts = Vec::new();
+    for stroke in expected {
+        let Some(current) = session.stroke(&stroke.id) else {
+            continue;
+        };
+        let Some(index) = target.iter().position(|item| item.id == stroke.id) else {
+            continue;
+        };
+        if *current == stroke {
+            pairs.push((stroke, target.swap_remove(index)));
+        } else {
+            conflicts.push(stroke.id);
         }
     }
+    (pairs, conflicts)
+}
+
+/// Whether undo or redo may touch `stroke`: both the recorded stroke and its copy on the board,
+/// if any, are on editable layers. If not, it is added to `skipped`.
+fn history_editable(session: &Session, stroke: &Stroke, skipped: &mut Vec<StrokeId>) -> bool {
+    let editable = session.layer_editable(stroke.layer)
+        && session
+            .stroke(&stroke.id)
+            .is_none_or(|current| session.layer_editable(current.layer));
+    if !editable {
+        skipped.push(stroke.id);
+    }
+    editable
+}
+
+/// The `items` whose stroke undo or redo may touch (see `history_editable`); the others are
+/// added to `skipped`.
+fn unlocked<T>(
+    session: &Session,
+    items: Vec<T>,
+    stroke: impl Fn(&T) -> &Stroke,
+    skipped: &mut Vec<StrokeId>,
+) -> Vec<T> {
+    items
+        .into_iter()
+        .filter(|item| history_editable(session, stroke(item), skipped))
+        .collect()
+}
+
+/// Whether every stroke a reorder from `before` to `after` moved is on an editable layer.
+fn order_editable(session: &Session, before: &[StrokeId], after: &[StrokeId]) -> bool {
+    moved_ids(before, after).iter().all(|id| {
+        session
+            .stroke(id)
+            .is_none_or(|stroke| session.layer_editable(stroke.layer))
+    })
+}
+
+/// Whether the strokes of `order` still on `page` are in that relative order, i.e. nobody
+/// reordered them since. Strokes added or removed in the meantime do not count.
+fn order_untouched(session: &Session, page: PageId, order: &[StrokeId]) -> Option<bool> {
+    let current = page_order(session, page)?;
+    let listed = order.iter().collect::<HashSet<_>>();
+    let present = current.iter().collect::<HashSet<_>>();
+    Some(
+        current
+            .iter()
+            .filter(|id| listed.contains(id))
+            .eq(order.iter().filter(|id| present.contains(id))),
+    )
+}
+
+/// The strokes whose place a reorder from `before` to `after` changed.
+fn moved_ids(before: &[StrokeId], after: &[StrokeId]) -> Vec<StrokeId> {
+    after
+        .iter()
+        .enumerate()
+        .filter(|(index, id)| before.get(*index) != Some(id))
+        .map(|(_, id)| *id)
+        .collect()
+}
+
+/// Tells `sender` which of the strokes it added to `page` were dropped.
+fn report_rejected(session: &Session, sender: Uuid, page: PageId, ids: Vec<StrokeId>) {
+    if ids.is_empty() {
+        return;
+    }
+    if let Some(peer) = session.peers.get(&sender) {
+        let _ = peer.send(ServerMessage::StrokesRejected { page, ids });
+    }
+}
+
+/// Tells `sender` which strokes its undo or redo left alone.
+fn report_skipped(session: &Session, sender: Uuid, ids: Vec<StrokeId>) {
+    if ids.is_empty() {
+        return;
+    }
+    if let Some(peer) = session.peers.get(&sender) {
+        let _ = peer.send(ServerMessage::HistorySkipped { ids });
+    }
+}
+
+fn layers_message(session: &Session) -> Vec<ServerMessage> {
+    vec![ServerMessage::Layers {
+        layers: session.layers.clone(),
+    }]
+}
+
+pub fn peer_infos(session: &Session, except: Uuid) -> Vec<PeerInfo> {
+    session
+        .peers
+        .keys()
+        .filter(|id| **id != except)
+        .map(|id| PeerInfo {
+            peer: peer_id(*id),
+            name: session
+                .identities
+                .get(id)
+                .map(|identity| identity.name.clone()),
+            page: session.viewing.get(id).copied(),
+        })
+        .collect()
+}
+
+pub fn peer_id(connection_id: Uuid) -> PeerId {
+    let (high, low) = connection_id.as_u64_pair();
+    PeerId::new([high, low])
 }
 
 pub async fn broadcast_except(
@@ -450,9 +1290,7 @@ pub async fn broadcast_except(
     if !stale.is_empty() {
         let mut session = session.write().await;
         for id in stale {
-            session.peers.remove(&id);
-            session.histories.remove(&id);
-            session.transform_sessions.remove(&id);
+            session.detach_connection(id);
         }
     }
 }
@@ -471,22 +1309,73 @@ pub async fn broadcast_all(session: &Arc<RwLock<Session>>, message: ServerMessag
     if !stale.is_empty() {
         let mut session = session.write().await;
         for id in stale {
-            session.peers.remove(&id);
-            session.histories.remove(&id);
-            session.transform_sessions.remove(&id);
+            session.detach_connection(id);
         }
     }
 }
 
-pub fn sanitize_strokes(strokes: Vec<Stroke>) -> Vec<Stroke> {
-    strokes.into_iter().filter_map(sanitize_stroke).collect()
+/// Keeps the first of any repeated layer id and drops unnamed layers. Never returns an empty
+/// list.
+pub fn sanitize_layers(layers: Vec<Layer>) -> Vec<Layer> {
+    let mut ids = HashSet::new();
+    let mut layers = layers
+        .into_iter()
+        .filter(|layer| ids.insert(layer.id))
+        .filter_map(sanitize_layer)
+        .take(MAX_LAYERS)
+        .collect::<Vec<_>>();
+    if layers.is_empty() {
+        layers.push(Layer::base());
+    }
+    layers
+}
+
+fn sanitize_layer(layer: Layer) -> Option<Layer> {
+    let name = sanitize_display_name(&layer.name);
+    if name.is_empty() {
+        return None;
+    }
+    Some(Layer { name, ..layer })
+}
+
+/// Sanitizes every stroke and keeps the first of any repeated page or stroke id, since strokes
+/// are found by id across pages. Strokes on layers missing from `layers` move to the bottom one.
+/// Never returns an empty list.
+pub fn sanitize_pages(pages: Vec<Page>, layers: &[Layer]) -> Vec<Page> {
+    let bottom = layers.first().map(|layer| layer.id).unwrap_or_default();
+    let mut page_ids = HashSet::new();
+    let mut stroke_ids = HashSet::new();
+    let mut pages = pages
+        .into_iter()
+        .filter(|page| page_ids.insert(page.id))
+        .take(MAX_PAGES)
+        .map(|page| Page {
+            id: page.id,
+            strokes: sanitize_strokes(page.strokes, &mut stroke_ids)
+                .into_iter()
+                .map(|mut stroke| {
+                    if !layers.iter().any(|layer| layer.id == stroke.layer) {
+                        stroke.layer = bottom;
+                    }
+                    stroke
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    if pages.is_empty() {
+        pages.push(Page::new(PageId::INITIAL));
+    }
+    pages
 }
 
 fn normalize_point(point: Point) -> Option<Point> {
     if !point.x.is_finite() || !point.y.is_finite() {
         return None;
     }
-    Some(point)
+    Some(Point {
+        pressure: normalize_pressure(point.pressure),
+        ..point
+    })
 }
 
 fn sanitize_size(size: f32) -> f32 {
@@ -494,6 +1383,16 @@ fn sanitize_size(size: f32) -> f32 {
     size.max(1.0).min(60.0)
 }
 
+/// Sanitizes strokes coming in as a batch, dropping invalid ones and ids already in `seen`
+/// (which collects the ids kept).
+fn sanitize_strokes(strokes: Vec<Stroke>, seen: &mut HashSet<StrokeId>) -> Vec<Stroke> {
+    strokes
+        .into_iter()
+        .filter_map(sanitize_stroke)
+        .filter(|stroke| seen.insert(stroke.id))
+        .collect()
+}
+
 fn sanitize_stroke(mut stroke: Stroke) -> Option<Stroke> {
     stroke.size = sanitize_size(stroke.size);
     stroke.points = stroke
@@ -504,6 +1403,29 @@ fn sanitize_stroke(mut stroke: Stroke) -> Option<Stroke> {
     if stroke.points.is_empty() {
         return None;
     }
+    if let Some(text) = stroke.text.take() {
+        if stroke.shape.is_some() || stroke.points.len() != 1 {
+            return None;
+        }
+        stroke.text = Some(sanitize_text(text)?);
+    }
+    if let Some(image) = &stroke.image {
+        if stroke.shape.is_some() || stroke.text.is_some() || !image_is_valid(image, &stroke.points)
+        {
+            return None;
+        }
+    }
+    if stroke.text.is_some() || stroke.image.is_some() {
+        stroke.blend = Blend::Normal;
+    }
+    if let Some(shape) = &mut stroke.shape {
+        if !shape_is_valid(shape, &stroke.points) {
+            return None;
+        }
+        if !shape.kind.is_closed() {
+            shape.fill = None;
+        }
+    }
     Some(stroke)
 }
 
@@ -551,7 +1473,7 @@ fn apply_transform(session: &mut Session, ids: &[StrokeId], op: &TransformOp) ->
     let mut changed = false;
     match *op {
         TransformOp::Translate { dx, dy } => {
-            for stroke in &mut session.strokes {
+            for stroke in session.strokes_mut() {
                 if !id_set.contains(&stroke.id) {
                     continue;
                 }
@@ -565,7 +1487,7 @@ fn apply_transform(session: &mut Session, ids: &[StrokeId], op: &TransformOp) ->
         TransformOp::Scale { anchor, sx, sy } => {
             let cx = anchor.x as f64;
             let cy = anchor.y as f64;
-            for stroke in &mut session.strokes {
+            for stroke in session.strokes_mut() {
                 if !id_set.contains(&stroke.id) {
                     continue;
                 }
@@ -576,6 +1498,9 @@ fn apply_transform(session: &mut Session, ids: &[StrokeId], op: &TransformOp) ->
                     point.x = (cx + dx * sx) as f32;
                     point.y = (cy + dy * sy) as f32;
                 }
+                if let Some(text) = &mut stroke.text {
+                    scale_text(text, sx, sy);
+                }
             }
         }
         TransformOp::Rotate { center, delta } => {
@@ -583,7 +1508,7 @@ fn apply_transform(session: &mut Session, ids: &[StrokeId], op: &TransformOp) ->
             let cy = center.y as f64;
             let cos = delta.cos();
             let sin = delta.sin();
-            for stroke in &mut session.strokes {
+            for stroke in session.strokes_mut() {
                 if !id_set.contains(&stroke.id) {
                     continue;
                 }
@@ -600,57 +1525,674 @@ fn apply_transform(session: &mut Session, ids: &[StrokeId], op: &TransformOp) ->
     changed
 }
 
-fn remove_stroke(session: &mut Session, id: &StrokeId) -> bool {
-    let removed = if let Some(index) = session.strokes.iter().position(|s| &s.id == id) {
-        session.strokes.remove(index);
-        true
-    } else {
-        false
+/// Closes the sender's open text edit, if any, recording it as one undo step. A box left empty is
+/// removed; the returned messages announce that removal.
+fn finish_text_edit(session: &mut Session, sender: Uuid) -> Vec<ServerMessage> {
+    let Some(edit) = session.text_edits.remove(&sender) else {
+        return Vec::new();
     };
-    if removed {
-        session.active_ids.remove(id);
-        session.owners.remove(id);
+    let Some((page, _)) = session.locate(&edit.id) else {
+        return Vec::new();
+    };
+    let Some(after) = session.stroke(&edit.id).cloned() else {
+        return Vec::new();
+    };
+    let empty = after
+        .text
+        .as_ref()
+        .is_none_or(|text| text.content.trim().is_empty());
+    if empty {
+        remove_stroke(session, &edit.id);
+        if let Some(before) = edit.before {
+            if let Some(history) = session.history_mut(sender) {
+                history.record(Action::EraseStroke(page, before));
+            }
+        }
+        return vec![ServerMessage::StrokeRemove { id: edit.id }];
     }
-    removed
+    let action = match edit.before {
+        None => Action::AddStroke(page, after),
+        Some(before) if before.text != after.text => Action::ReplaceStroke { before, after },
+        Some(_) => return Vec::new(),
+    };
+    if let Some(history) = session.history_mut(sender) {
+        history.record(action);
+    }
+    Vec::new()
+}
+
+fn remove_stroke(session: &mut Session, id: &StrokeId) -> bool {
+    remove_stroke_full(session, id).is_some()
+}
+
+/// Whether `stroke` shows no image, or one whose asset this session knows (see
+/// `Session::asset_known`).
+fn image_known(session: &Session, stroke: &Stroke) -> bool {
+    stroke
+        .image
+        .as_ref()
+        .is_none_or(|image| session.asset_known(&image.hash))
+}
+
+/// Appends `stroke` on top of `page`, dropping that page's oldest strokes beyond
+/// `MAX_STROKES`. Returns `false` if the page does not exist.
+fn add_stroke(session: &mut Session, page: PageId, stroke: Stroke) -> bool {
+    let Some(page) = session.page_mut(page) else {
+        return false;
+    };
+    page.strokes.push(stroke);
+    let overflow = page.strokes.len().saturating_sub(MAX_STROKES);
+    let dropped = page.strokes.drain(0..overflow).collect::<Vec<_>>();
+    forget_strokes(session, &dropped);
+    true
 }
 
-fn add_stroke(session: &mut Session, stroke: Stroke, owner: Option<Uuid>) {
-    session.strokes.push(stroke.clone());
-    let overflow = session.strokes.len().saturating_sub(MAX_STROKES);
-    if overflow > 0 {
-        let removed = session.strokes.drain(0..overflow).collect::<Vec<_>>();
-        for stroke in removed {
-            session.active_ids.remove(&stroke.id);
-            session.owners.remove(&stroke.id);
+/// Removes the `remove` strokes (from any page) and inserts `insert` on `page` at the index of
+/// the first one found there, or on top. Returns the ids that were actually removed, or `None`
+/// (changing nothing) if the page does not exist.
+fn splice_strokes(
+    session: &mut Session,
+    page: PageId,
+    remove: &[StrokeId],
+    insert: Vec<Stroke>,
+) -> Option<Vec<StrokeId>> {
+    session.page(page)?;
+    let mut index = None;
+    let mut removed = Vec::new();
+    for id in remove {
+        let Some((from, position)) = session.locate(id) else {
+            continue;
+        };
+        remove_stroke(session, id);
+        if from == page {
+            index = Some(index.map_or(position, |index: usize| index.min(position)));
         }
+        removed.push(*id);
     }
+    let target = session.page_mut(page)?;
+    let index = index.unwrap_or(target.strokes.len());
+    target.strokes.splice(index..index, insert);
+    let overflow = target.strokes.len().saturating_sub(MAX_STROKES);
+    let dropped = target.strokes.drain(0..overflow).collect::<Vec<_>>();
+    forget_strokes(session, &dropped);
+    Some(removed)
+}
 
-    if let Some(owner) = owner {
-        session.owners.insert(stroke.id.clone(), owner);
+fn replace_stroke(session: &mut Session, stroke: Stroke) -> Option<Stroke> {
+    let slot = session.stroke_mut(&stroke.id)?;
+    Some(std::mem::replace(slot, stroke))
+}
+
+fn remove_stroke_full(session: &mut Session, id: &StrokeId) -> Option<(PageId, Stroke)> {
+    let (page, index) = session.locate(id)?;
+    let stroke = session.page_mut(page)?.strokes.remove(index);
+    forget_strokes(session, std::slice::from_ref(&stroke));
+    Some((page, stroke))
+}
+
+/// Drops the per-stroke bookkeeping of strokes that left the board.
+fn forget_strokes(session: &mut Session, strokes: &[Stroke]) {
+    for stroke in strokes {
+        session.active_ids.remove(&stroke.id);
     }
+    session
+        .text_edits
+        .retain(|_, edit| !strokes.iter().any(|stroke| stroke.id == edit.id));
 }
 
-fn replace_stroke(session: &mut Session, stroke: Stroke) -> Option<Stroke> {
-    let id = &stroke.id;
-    if let Some(index) = session.strokes.iter().position(|s| &s.id == id) {
-        let before = session.strokes[index].clone();
-        session.strokes[index] = stroke;
+/// Takes the strokes of `page` off the board, except those on locked layers, and returns them.
+fn clear_page(session: &mut Session, page: PageId) -> Option<Vec<Stroke>> {
+    let locked = session
+        .layers
+        .iter()
+        .filter(|layer| layer.locked)
+        .map(|layer| layer.id)
+        .collect::<HashSet<_>>();
+    let page = session.page_mut(page)?;
+    let (kept, cleared): (Vec<_>, Vec<_>) = std::mem::take(&mut page.strokes)
+        .into_iter()
+        .partition(|stroke| locked.contains(&stroke.layer));
+    page.strokes = kept;
+    forget_strokes(session, &cleared);
+    Some(cleared)
+}
 
-        Some(before)
-    } else {
-        None
+/// Ids of the strokes on `page`, bottom first.
+fn page_order(session: &Session, page: PageId) -> Option<Vec<StrokeId>> {
+    Some(
+        session
+            .page(page)?
+            .strokes
+            .iter()
+            .map(|stroke| stroke.id)
+            .collect(),
+    )
+}
+
+/// Applies a paint order to `page` and returns the resulting order for every peer, which may
+/// differ from `order` when strokes were added or removed since it was taken.
+fn arrange_page(session: &mut Session, page: PageId, order: &[StrokeId]) -> Option<ServerMessage> {
+    arrange(&mut session.page_mut(page)?.strokes, order);
+    let order = page_order(session, page)?;
+    Some(ServerMessage::Reorder { page, order })
+}
+
+/// Deletes a page unless it is the last one. Returns it with its former index.
+fn remove_page(session: &mut Session, page: PageId) -> Option<(Page, usize)> {
+    if session.pages.len() <= 1 {
+        return None;
     }
+    let index = session.pages.iter().position(|item| item.id == page)?;
+    let removed = session.pages.remove(index);
+    forget_strokes(session, &removed.strokes);
+    Some((removed, index))
 }
 
-fn remove_stroke_full(session: &mut Session, id: &StrokeId) -> Option<Stroke> {
-    let removed = if let Some(index) = session.strokes.iter().position(|s| &s.id == id) {
-        Some(session.strokes.remove(index))
-    } else {
-        None
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use yumboard_shared::{ClientId, ClientSecret, Color, ImageRef, Layer, LayerId, Role, ZOrder};
+
+    use super::*;
+
+    /// Joins `session` as an editor and returns the connection with its outgoing queue.
+    fn join(session: &mut Session, seed: u64) -> (Uuid, mpsc::UnboundedReceiver<ServerMessage>) {
+        let connection = Uuid::new_v4();
+        let (tx, rx) = mpsc::unbounded_channel();
+        session.peers.insert(connection, tx);
+        session.roles.insert(connection, Role::Editor);
+        apply_client_message(
+            session,
+            connection,
+            ClientMessage::Hello {
+                client_id: ClientId::new([seed, seed]),
+                secret: ClientSecret::new([seed, 0]),
+                name: format!("Peer {seed}"),
+            },
+        );
+        (connection, rx)
+    }
+
+    const RED: Color = Color {
+        r: 0xff,
+        g: 0,
+        b: 0,
+        a: 0xff,
     };
-    if removed.is_some() {
-        session.active_ids.remove(id);
-        session.owners.remove(id);
+
+    fn point(x: f32) -> Point {
+        Point {
+            x,
+            y: x,
+            pressure: None,
+        }
+    }
+
+    fn line(id: u64) -> Stroke {
+        Stroke {
+            id: StrokeId::new([id, id]),
+            color: Color::DEFAULT,
+            size: 4.0,
+            points: vec![point(0.0), point(10.0)],
+            shape: None,
+            text: None,
+            image: None,
+            blend: Blend::Normal,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
+
+    fn translate(session: &mut Session, sender: Uuid, ids: &[StrokeId], dx: f64) {
+        apply_client_message(
+            session,
+            sender,
+            ClientMessage::TransformStart { ids: ids.to_vec() },
+        );
+        apply_client_message(
+            session,
+            sender,
+            ClientMessage::TransformUpdate {
+                ids: ids.to_vec(),
+                op: TransformOp::Translate { dx, dy: 0.0 },
+            },
+        );
+        apply_client_message(
+            session,
+            sender,
+            ClientMessage::TransformEnd { ids: ids.to_vec() },
+        );
+    }
+
+    fn x(session: &Session, id: &StrokeId) -> f32 {
+        session.stroke(id).unwrap().points[0].x
+    }
+
+    fn skipped(rx: &mut mpsc::UnboundedReceiver<ServerMessage>) -> Vec<StrokeId> {
+        let mut ids = Vec::new();
+        while let Ok(message) = rx.try_recv() {
+            if let ServerMessage::HistorySkipped { ids: more } = message {
+                ids.extend(more);
+            }
+        }
+        ids
+    }
+
+    fn session_with(strokes: Vec<Stroke>) -> Session {
+        let mut page = Page::new(PageId::INITIAL);
+        page.strokes = strokes;
+        Session::new(vec![page], Vec::new())
+    }
+
+    #[test]
+    fn undo_keeps_strokes_another_editor_moved() {
+        let (a, b) = (line(1).id, line(2).id);
+        let mut session = session_with(vec![line(1), line(2)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        translate(&mut session, alice, &[a, b], 5.0);
+        translate(&mut session, bob, &[b], 100.0);
+        let (messages, _) = apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+
+        assert_eq!(x(&session, &a), 0.0);
+        assert_eq!(x(&session, &b), 105.0);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(skipped(&mut alice_rx), vec![b]);
+
+        // Bob's own undo still applies, since nobody touched his stroke after him.
+        apply_client_message(&mut session, bob, ClientMessage::Undo).unwrap();
+        assert_eq!(x(&session, &b), 5.0);
+
+        // Alice's redo only covers the stroke her undo reverted.
+        apply_client_message(&mut session, alice, ClientMessage::Redo).unwrap();
+        assert_eq!(x(&session, &a), 5.0);
+        assert_eq!(x(&session, &b), 5.0);
+        assert!(skipped(&mut alice_rx).is_empty());
+    }
+
+    #[test]
+    fn undo_skips_replace_after_another_edit() {
+        let id = line(1).id;
+        let mut session = session_with(vec![line(1)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        let red = Stroke {
+            color: RED,
+            ..line(1)
+        };
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::StrokeReplace { stroke: red },
+        );
+        translate(&mut session, bob, &[id], 20.0);
+
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+        let stroke = session.stroke(&id).unwrap();
+        assert_eq!(stroke.color, RED);
+        assert_eq!(stroke.points[0].x, 20.0);
+        assert_eq!(skipped(&mut alice_rx), vec![id]);
+
+        // The skipped step is gone, so a second undo has nothing left to do.
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+    }
+
+    #[test]
+    fn own_edits_undo_in_sequence() {
+        let id = line(1).id;
+        let mut session = session_with(vec![line(1)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        translate(&mut session, alice, &[id], 5.0);
+        translate(&mut session, alice, &[id], 7.0);
+        // An edit to another stroke does not get in the way.
+        apply_client_message(
+            &mut session,
+            bob,
+            ClientMessage::AddStrokes {
+                page: PageId::INITIAL,
+                strokes: vec![line(2)],
+            },
+        );
+
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert_eq!(x(&session, &id), 5.0);
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert_eq!(x(&session, &id), 0.0);
+        apply_client_message(&mut session, alice, ClientMessage::Redo).unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::Redo).unwrap();
+        assert_eq!(x(&session, &id), 12.0);
+        assert!(skipped(&mut alice_rx).is_empty());
+    }
+
+    #[test]
+    fn multi_stroke_remove_undoes_as_one_step() {
+        let (a, b) = (line(1).id, line(2).id);
+        let mut session = session_with(vec![line(1), line(2), line(3)]);
+        let (alice, _) = join(&mut session, 1);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::Remove { ids: vec![a, b] },
+        )
+        .unwrap();
+        assert!(session.stroke(&a).is_none() && session.stroke(&b).is_none());
+
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert!(session.stroke(&a).is_some() && session.stroke(&b).is_some());
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+
+        apply_client_message(&mut session, alice, ClientMessage::Redo).unwrap();
+        assert!(session.stroke(&a).is_none() && session.stroke(&b).is_none());
+        assert!(session.stroke(&line(3).id).is_some());
+    }
+
+    #[test]
+    fn erase_drag_in_batch_undoes_as_one_step() {
+        let (a, b) = (line(1).id, line(2).id);
+        let mut session = session_with(vec![line(1), line(2)]);
+        let (alice, _) = join(&mut session, 1);
+
+        apply_client_message(&mut session, alice, ClientMessage::BatchStart);
+        apply_client_message(&mut session, alice, ClientMessage::Erase { id: a }).unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::Erase { id: b }).unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::BatchEnd);
+
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert!(session.stroke(&a).is_some() && session.stroke(&b).is_some());
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+
+        apply_client_message(&mut session, alice, ClientMessage::Redo).unwrap();
+        assert!(session.stroke(&a).is_none() && session.stroke(&b).is_none());
+    }
+
+    #[test]
+    fn undo_closes_open_batch() {
+        let (a, b, c) = (line(1).id, line(2).id, line(3).id);
+        let mut session = session_with(vec![line(1), line(2), line(3)]);
+        let (alice, _) = join(&mut session, 1);
+
+        apply_client_message(&mut session, alice, ClientMessage::BatchStart);
+        apply_client_message(&mut session, alice, ClientMessage::Erase { id: a }).unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::Erase { id: b }).unwrap();
+        // No `batch:end`: the undo takes what the batch gathered as one step.
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert!(session.stroke(&a).is_some() && session.stroke(&b).is_some());
+
+        // Later edits are no longer part of the batch.
+        apply_client_message(&mut session, alice, ClientMessage::Erase { id: c }).unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert!(session.stroke(&c).is_some());
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+    }
+
+    #[test]
+    fn stroke_author_cannot_claim_its_history() {
+        let mut session = session_with(Vec::new());
+        let (alice, _) = join(&mut session, 1);
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::AddStrokes {
+                page: PageId::INITIAL,
+                strokes: vec![line(1)],
+            },
+        );
+        let author = session.stroke(&line(1).id).unwrap().author.unwrap();
+        assert_eq!(author, session.author_id(alice));
+
+        // Even with the secrets gone (pruned or restarted), the author seen on the stroke is not
+        // Alice's client id, so a `hello` with it gets a history of its own.
+        session.client_secrets.clear();
+        let mallory = Uuid::new_v4();
+        session.roles.insert(mallory, Role::Editor);
+        apply_client_message(
+            &mut session,
+            mallory,
+            ClientMessage::Hello {
+                client_id: ClientId::new(author.value()),
+                secret: ClientSecret::new([9, 9]),
+                name: String::new(),
+            },
+        );
+        assert_ne!(session.client_id(mallory), session.client_id(alice));
+        assert!(apply_client_message(&mut session, mallory, ClientMessage::Undo).is_none());
+        assert!(session.stroke(&line(1).id).is_some());
+    }
+
+    fn recolor(session: &mut Session, sender: Uuid, id: u64) {
+        apply_client_message(
+            session,
+            sender,
+            ClientMessage::StrokeReplace {
+                stroke: Stroke {
+                    color: RED,
+                    ..line(id)
+                },
+            },
+        )
+        .unwrap();
+    }
+
+    fn order(session: &Session) -> Vec<StrokeId> {
+        page_order(session, PageId::INITIAL).unwrap()
+    }
+
+    #[test]
+    fn undo_split_skips_changed_fragment() {
+        let mut session = session_with(vec![line(1)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::StrokeSplit {
+                id: line(1).id,
+                fragments: vec![line(10), line(11)],
+            },
+        )
+        .unwrap();
+        recolor(&mut session, bob, 11);
+
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+        assert!(session.stroke(&line(1).id).is_none());
+        assert_eq!(session.stroke(&line(11).id).unwrap().color, RED);
+        assert_eq!(skipped(&mut alice_rx), vec![line(11).id]);
+    }
+
+    #[test]
+    fn redo_split_skips_changed_original() {
+        let mut session = session_with(vec![line(1)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::StrokeSplit {
+                id: line(1).id,
+                fragments: vec![line(10), line(11)],
+            },
+        )
+        .unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert!(session.stroke(&line(10).id).is_none());
+        recolor(&mut session, bob, 1);
+
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Redo).is_none());
+        assert_eq!(session.stroke(&line(1).id).unwrap().color, RED);
+        assert!(session.stroke(&line(10).id).is_none());
+        assert_eq!(skipped(&mut alice_rx), vec![line(1).id]);
+    }
+
+    #[test]
+    fn undo_reorder_skips_later_reorder() {
+        let (a, b, c) = (line(1).id, line(2).id, line(3).id);
+        let mut session = session_with(vec![line(1), line(2), line(3)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::Reorder {
+                ids: vec![a],
+                to: ZOrder::Front,
+            },
+        )
+        .unwrap();
+        assert_eq!(order(&session), vec![b, c, a]);
+        apply_client_message(
+            &mut session,
+            bob,
+            ClientMessage::Reorder {
+                ids: vec![c],
+                to: ZOrder::Back,
+            },
+        )
+        .unwrap();
+
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+        assert_eq!(order(&session), vec![c, b, a]);
+        assert!(!skipped(&mut alice_rx).is_empty());
+
+        // Bob's own undo is still his to take back.
+        apply_client_message(&mut session, bob, ClientMessage::Undo).unwrap();
+        assert_eq!(order(&session), vec![b, c, a]);
+    }
+
+    #[test]
+    fn undo_reorder_ignores_new_strokes() {
+        let (a, b) = (line(1).id, line(2).id);
+        let mut session = session_with(vec![line(1), line(2)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::Reorder {
+                ids: vec![a],
+                to: ZOrder::Front,
+            },
+        )
+        .unwrap();
+        apply_client_message(
+            &mut session,
+            bob,
+            ClientMessage::AddStrokes {
+                page: PageId::INITIAL,
+                strokes: vec![line(3)],
+            },
+        );
+
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        assert_eq!(order(&session), vec![a, b, line(3).id]);
+        apply_client_message(&mut session, alice, ClientMessage::Redo).unwrap();
+        assert_eq!(order(&session), vec![b, a, line(3).id]);
+        assert!(skipped(&mut alice_rx).is_empty());
+    }
+
+    fn lock_base(session: &mut Session, sender: Uuid) {
+        apply_client_message(
+            session,
+            sender,
+            ClientMessage::LayerUpdate {
+                layer: Layer {
+                    locked: true,
+                    ..Layer::base()
+                },
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn undo_skips_strokes_on_locked_layer() {
+        let (a, b) = (line(1).id, line(2).id);
+        let mut session = session_with(vec![line(1), line(2)]);
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        apply_client_message(&mut session, alice, ClientMessage::Erase { id: a }).unwrap();
+        translate(&mut session, alice, &[b], 5.0);
+        lock_base(&mut session, bob);
+
+        // Neither the move nor the erase is taken back on the locked layer.
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+        assert_eq!(x(&session, &b), 5.0);
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Undo).is_none());
+        assert!(session.stroke(&a).is_none());
+        assert_eq!(skipped(&mut alice_rx), vec![b, a]);
+    }
+
+    #[test]
+    fn redo_skips_strokes_on_locked_layer() {
+        let mut session = session_with(Vec::new());
+        let (alice, mut alice_rx) = join(&mut session, 1);
+        let (bob, _) = join(&mut session, 2);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::AddStrokes {
+                page: PageId::INITIAL,
+                strokes: vec![line(1)],
+            },
+        )
+        .unwrap();
+        apply_client_message(&mut session, alice, ClientMessage::Undo).unwrap();
+        lock_base(&mut session, bob);
+
+        assert!(apply_client_message(&mut session, alice, ClientMessage::Redo).is_none());
+        assert!(session.stroke(&line(1).id).is_none());
+        assert_eq!(skipped(&mut alice_rx), vec![line(1).id]);
+    }
+
+    fn image(id: u64, hash: &str) -> Stroke {
+        Stroke {
+            image: Some(ImageRef {
+                hash: hash.to_string(),
+                width: 1,
+                height: 1,
+            }),
+            points: vec![point(0.0), point(1.0), point(2.0), point(3.0)],
+            ..line(id)
+        }
+    }
+
+    #[test]
+    fn add_strokes_reports_unknown_images() {
+        let known = "a".repeat(64);
+        let unknown = "b".repeat(64);
+        let mut session = session_with(vec![line(1)]);
+        session.assets.insert(known.clone());
+        let (alice, mut alice_rx) = join(&mut session, 1);
+
+        apply_client_message(
+            &mut session,
+            alice,
+            ClientMessage::AddStrokes {
+                page: PageId::INITIAL,
+                // A replay of stroke 1, which the board already has, is not a reject.
+                strokes: vec![line(1), image(2, &known), image(3, &unknown)],
+            },
+        )
+        .unwrap();
+
+        assert!(session.stroke(&line(2).id).is_some());
+        assert!(session.stroke(&line(3).id).is_none());
+        let mut rejected = Vec::new();
+        while let Ok(message) = alice_rx.try_recv() {
+            if let ServerMessage::StrokesRejected { ids, .. } = message {
+                rejected.extend(ids);
+            }
+        }
+        assert_eq!(rejected, vec![line(3).id]);
     }
-    removed
 }
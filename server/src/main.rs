@@ -2,22 +2,28 @@ use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use axum::extract::DefaultBodyLimit;
 use axum::http::header::{CACHE_CONTROL, EXPIRES, PRAGMA};
 use axum::http::HeaderValue;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::Router;
 use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
 use tower_http::services::ServeDir;
 use tower_http::set_header::SetResponseHeaderLayer;
+use yumboard_shared::image::MAX_ASSET_BYTES;
 
 mod handlers;
 mod logic;
+mod render;
 mod sessions;
 mod state;
 mod storage;
 
-use crate::handlers::{ping_handler, root_handler, session_handler, ws_handler};
+use crate::handlers::{
+    asset_handler, export_pdf_handler, ping_handler, render_png_handler, render_svg_handler,
+    root_handler, session_handler, upload_asset_handler, ws_handler,
+};
 use crate::sessions::save_session;
 use crate::state::AppState;
 use crate::storage::{FileStorage, S3Storage, S3StorageConfig, Storage};
@@ -60,6 +66,10 @@ struct Args {
     #[arg(long, default_value_t = 60u64)]
     backup_interval: u64,
 
+    // Seconds a disconnected client's undo history is kept for it to reconnect
+    #[arg(long, default_value_t = 600u64)]
+    history_grace: u64,
+
     #[arg(long, default_value_t = 3000)]
     port: u16,
 }
@@ -75,8 +85,12 @@ async fn save_all_sessions(state: &AppState, reset_dirty: bool) {
     let mut to_check_removeable = vec![];
     for (session_id, session) in sessions {
         let data = {
-            let session = session.read().await;
+            let mut session = session.write().await;
+            session.prune_histories(state.history_grace);
             if !session.dirty {
+                if reset_dirty && session.peers.is_empty() {
+                    to_check_removeable.push(session_id);
+                }
                 continue;
             }
             session.to_persistent_session_data()
@@ -106,7 +120,7 @@ async fn save_all_sessions(state: &AppState, reset_dirty: bool) {
         let removeable = match sessions.get(&session_id) {
             Some(session) => {
                 let session = session.read().await;
-                session.peers.is_empty() && !session.dirty
+                session.peers.is_empty() && session.histories.is_empty() && !session.dirty
             }
             None => false,
         };
@@ -205,6 +219,7 @@ async fn main() {
     let state = AppState {
         sessions: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         storage,
+        history_grace: std::time::Duration::from_secs(args.history_grace),
     };
 
     let backup_state = state.clone();
@@ -215,6 +230,23 @@ async fn main() {
         .route("/", get(root_handler))
         .route("/s/:session_id", get(session_handler))
         .route("/ws/:session_id", get(ws_handler))
+        .route(
+            "/api/sessions/:session_id/render.svg",
+            get(render_svg_handler),
+        )
+        .route(
+            "/api/sessions/:session_id/render.png",
+            get(render_png_handler),
+        )
+        .route(
+            "/api/sessions/:session_id/export.pdf",
+            get(export_pdf_handler),
+        )
+        .route(
+            "/api/sessions/:session_id/assets",
+            post(upload_asset_handler).layer(DefaultBodyLimit::max(MAX_ASSET_BYTES)),
+        )
+        .route("/api/sessions/:session_id/assets/:hash", get(asset_handler))
         .fallback_service(ServeDir::new(public_dir).append_index_html_on_directories(true))
         .layer(SetResponseHeaderLayer::if_not_present(
             CACHE_CONTROL,
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use tiny_skia::{
+    Color as SkiaColor, FillRule, FilterQuality, LineCap, LineJoin, Paint, PathBuilder, Pixmap,
+    PixmapPaint, Stroke as SkiaStroke, Transform,
+};
+use yumboard_shared::curve::{curved_stroke, RENDER_CURVE_SPACING};
+use yumboard_shared::highlight::paint_order;
+use yumboard_shared::image::image_matrix;
+use yumboard_shared::pdf::RasterImage;
+use yumboard_shared::pressure::{circle_curves, has_pressure, stroke_outline, OutlinePart};
+use yumboard_shared::shape::{arrow_head, shape_path, PathSegment};
+use yumboard_shared::svg::{ink_bounds, SvgRect};
+use yumboard_shared::{Blend, Color, Layer, ShapeKind, Stroke, STROKE_UNIT};
+
+pub const DEFAULT_RENDER_WIDTH: u32 = 800;
+pub const MAX_RENDER_SIDE: u32 = 4096;
+
+/// Rasterizes the strokes of the visible `layers` to a PNG `width` pixels wide (the height
+/// follows the ink bounds), drawing each stroke the way the client's `render::draw_stroke` does.
+/// `assets` holds the PNG bytes of the images by hash.
+pub fn render_png(
+    strokes: &[Stroke],
+    layers: &[Layer],
+    assets: &HashMap<String, Vec<u8>>,
+    width: u32,
+    padding: f64,
+) -> Result<Vec<u8>, String> {
+    let painted = paint_order(strokes, layers);
+    let bounds = ink_bounds(painted.iter().copied()).unwrap_or(SvgRect {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    });
+    let bounds = SvgRect {
+        x: bounds.x - padding,
+        y: bounds.y - padding,
+        width: bounds.width + padding * 2.0,
+        height: bounds.height + padding * 2.0,
+    };
+    let width = width.clamp(1, MAX_RENDER_SIDE);
+    let mut scale = width as f64 / bounds.width;
+    let mut height = (bounds.height * scale).ceil();
+    if height > MAX_RENDER_SIDE as f64 {
+        scale *= MAX_RENDER_SIDE as f64 / height;
+        height = MAX_RENDER_SIDE as f64;
+    }
+    let mut pixmap = Pixmap::new(width, (height as u32).max(1))
+        .ok_or_else(|| "Invalid render size".to_string())?;
+    pixmap.fill(SkiaColor::WHITE);
+
+    let transform = Transform::from_row(
+        scale as f32,
+        0.0,
+        0.0,
+        scale as f32,
+        (-bounds.x * scale) as f32,
+        (-bounds.y * scale) as f32,
+    );
+    let images = assets
+        .iter()
+        .filter_map(|(hash, bytes)| Some((hash.as_str(), Pixmap::decode_png(bytes).ok()?)))
+        .collect::<HashMap<_, _>>();
+    for stroke in painted {
+        if let Some(image) = &stroke.image {
+            if let Some(source) = images.get(image.hash.as_str()) {
+                draw_image(&mut pixmap, transform, stroke, source);
+            }
+            continue;
+        }
+        draw_stroke(&mut pixmap, transform, stroke);
+    }
+    pixmap
+        .encode_png()
+        .map_err(|error| format!("Failed to encode png: {error}"))
+}
+
+fn draw_stroke(pixmap: &mut Pixmap, transform: Transform, stroke: &Stroke) {
+    let mut paint = Paint::default();
+    paint.set_color(skia_color(stroke.color));
+    paint.anti_alias = true;
+    let weight = (stroke.size as f64 * STROKE_UNIT) as f32;
+
+    // tiny-skia has no text shaping, so text boxes only appear in SVG and PDF exports.
+    if stroke.text.is_some() {
+        return;
+    }
+    if let Some(shape) = stroke.shape {
+        let Some(path) = segments_path(&shape_path(stroke)) else {
+            return;
+        };
+        if let Some(fill) = shape.fill.filter(|_| shape.kind.is_closed()) {
+            let mut fill_paint = paint.clone();
+            fill_paint.set_color(skia_color(fill));
+            pixmap.fill_path(&path, &fill_paint, FillRule::Winding, transform, None);
+        }
+        let line = SkiaStroke {
+            width: weight,
+            line_cap: LineCap::Round,
+            line_join: LineJoin::Round,
+            ..SkiaStroke::default()
+        };
+        pixmap.stroke_path(&path, &paint, &line, transform, None);
+        if let Some([tip, left, right]) =
+            arrow_head(&stroke.points, stroke.size).filter(|_| shape.kind == ShapeKind::Arrow)
+        {
+            let mut builder = PathBuilder::new();
+            builder.move_to(tip.0 as f32, tip.1 as f32);
+            builder.line_to(left.0 as f32, left.1 as f32);
+            builder.line_to(right.0 as f32, right.1 as f32);
+            builder.close();
+            if let Some(head) = builder.finish() {
+                pixmap.fill_path(&head, &paint, FillRule::Winding, transform, None);
+            }
+        }
+        return;
+    }
+    let stroke = &*curved_stroke(stroke, RENDER_CURVE_SPACING);
+    if has_pressure(stroke) {
+        if let Some(outline) = outline_path(&stroke_outline(stroke)) {
+            pixmap.fill_path(&outline, &paint, FillRule::Winding, transform, None);
+        }
+        return;
+    }
+    if let [point] = stroke.points.as_slice() {
+        if let Some(dot) = PathBuilder::from_circle(point.x, point.y, weight / 2.0) {
+            pixmap.fill_path(&dot, &paint, FillRule::Winding, transform, None);
+        }
+        return;
+    }
+    let line = SkiaStroke {
+        width: weight,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..SkiaStroke::default()
+    };
+    // Highlighter ink is one path so it does not darken where it crosses itself. Other segments
+    // are stroked one by one like on the canvas, so translucent ink overlaps the same.
+    if stroke.blend == Blend::Highlight {
+        let mut builder = PathBuilder::new();
+        builder.move_to(stroke.points[0].x, stroke.points[0].y);
+        for point in &stroke.points[1..] {
+            builder.line_to(point.x, point.y);
+        }
+        if let Some(path) = builder.finish() {
+            pixmap.stroke_path(&path, &paint, &line, transform, None);
+        }
+        return;
+    }
+    for pair in stroke.points.windows(2) {
+        let mut builder = PathBuilder::new();
+        builder.move_to(pair[0].x, pair[0].y);
+        builder.line_to(pair[1].x, pair[1].y);
+        if let Some(path) = builder.finish() {
+            pixmap.stroke_path(&path, &paint, &line, transform, None);
+        }
+    }
+}
+
+/// Decodes a PNG asset into the straight RGBA pixels the PDF writer embeds.
+pub fn raster_image(bytes: &[u8]) -> Option<RasterImage> {
+    let pixmap = Pixmap::decode_png(bytes).ok()?;
+    let rgba = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    Some(RasterImage {
+        width: pixmap.width(),
+        height: pixmap.height(),
+        rgba,
+    })
+}
+
+fn draw_image(pixmap: &mut Pixmap, transform: Transform, stroke: &Stroke, source: &Pixmap) {
+    let Some([a, b, c, d, e, f]) = image_matrix(stroke) else {
+        return;
+    };
+    // Map source pixels onto the unit square, then onto the image corners.
+    let (width, height) = (source.width() as f64, source.height() as f64);
+    let placement = Transform::from_row(
+        (a / width) as f32,
+        (b / width) as f32,
+        (c / height) as f32,
+        (d / height) as f32,
+        e as f32,
+        f as f32,
+    );
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bilinear,
+        ..PixmapPaint::default()
+    };
+    pixmap.draw_pixmap(
+        0,
+        0,
+        source.as_ref(),
+        &paint,
+        transform.pre_concat(placement),
+        None,
+    );
+}
+
+fn segments_path(segments: &[PathSegment]) -> Option<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+    for segment in segments {
+        match *segment {
+            PathSegment::MoveTo(x, y) => builder.move_to(x as f32, y as f32),
+            PathSegment::LineTo(x, y) => builder.line_to(x as f32, y as f32),
+            PathSegment::CubicTo(c1, c2, end) => builder.cubic_to(
+                c1.0 as f32,
+                c1.1 as f32,
+                c2.0 as f32,
+                c2.1 as f32,
+                end.0 as f32,
+                end.1 as f32,
+            ),
+            PathSegment::Close => builder.close(),
+        }
+    }
+    builder.finish()
+}
+
+fn outline_path(parts: &[OutlinePart]) -> Option<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+    for part in parts {
+        match part {
+            OutlinePart::Circle { x, y, radius } => {
+                let (start, curves) = circle_curves(*x, *y, *radius);
+                builder.move_to(start.0 as f32, start.1 as f32);
+                for (c1, c2, end) in curves {
+                    builder.cubic_to(
+                        c1.0 as f32,
+                        c1.1 as f32,
+                        c2.0 as f32,
+                        c2.1 as f32,
+                        end.0 as f32,
+                        end.1 as f32,
+                    );
+                }
+                builder.close();
+            }
+            OutlinePart::Quad(corners) => {
+                builder.move_to(corners[0].0 as f32, corners[0].1 as f32);
+                for (x, y) in &corners[1..] {
+                    builder.line_to(*x as f32, *y as f32);
+                }
+                builder.close();
+            }
+        }
+    }
+    builder.finish()
+}
+
+fn skia_color(color: Color) -> SkiaColor {
+    SkiaColor::from_rgba8(color.r, color.g, color.b, color.a)
+}
@@ -1,20 +1,35 @@
 use std::sync::Arc;
 
-use crate::logic::sanitize_strokes;
+use crate::logic::{sanitize_layers, sanitize_pages};
 use crate::state::{AppState, PersistentSessionData, Session};
 use crate::storage::StorageError;
 use uuid::Uuid;
+use yumboard_shared::SessionAccess;
 
 pub fn new_session_id() -> String {
     Uuid::now_v7().to_string()
 }
 
+fn new_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+pub fn new_session_access() -> SessionAccess {
+    SessionAccess {
+        owner_secret: new_token(),
+        edit_token: new_token(),
+        view_token: new_token(),
+    }
+}
+
 pub fn normalize_session_id(value: &str) -> Option<String> {
     let parsed = Uuid::parse_str(value).ok()?;
     Some(parsed.to_string())
 }
 
 pub enum SessionLoadError {
+    /// No such session; only `/` makes new ones, so each has an owner secret.
+    NotFound,
     Storage(String),
 }
 
@@ -28,16 +43,20 @@ pub async fn get_or_create_session(
     }
     let data = if create_new {
         eprintln!("Creating new session {session_id}...");
-        PersistentSessionData::default()
+        PersistentSessionData {
+            pages: Vec::new(),
+            layers: Vec::new(),
+            access: Some(new_session_access()),
+        }
     } else {
-        eprintln!("Loading/Creating session {session_id}...");
+        eprintln!("Loading session {session_id}...");
         let res = state.storage.load_session(session_id).await;
 
         match res {
             Ok(data) => data,
             Err(StorageError::NotFound) => {
-                eprintln!("Session {session_id} not found. Creating new session.");
-                PersistentSessionData::default()
+                eprintln!("Session {session_id} not found.");
+                return Err(SessionLoadError::NotFound);
             }
             Err(StorageError::Other(err)) => {
                 eprintln!("Could not load session {session_id}: {err}");
@@ -46,12 +65,16 @@ pub async fn get_or_create_session(
         }
     };
 
+    let layers = sanitize_layers(data.layers);
     let sanitized = PersistentSessionData {
-        strokes: sanitize_strokes(data.strokes),
+        pages: sanitize_pages(data.pages, &layers),
+        layers,
+        access: data.access,
     };
-    let session = Arc::new(tokio::sync::RwLock::new(
-        Session::from_persistent_session_data(sanitized),
-    ));
+    let mut session = Session::from_persistent_session_data(sanitized);
+    // Fresh share tokens must reach storage even if nobody ever draws.
+    session.dirty = create_new;
+    let session = Arc::new(tokio::sync::RwLock::new(session));
     let mut sessions = state.sessions.write().await;
     let entry = sessions
         .entry(session_id.to_string())
@@ -59,6 +82,29 @@ pub async fn get_or_create_session(
     Ok(entry.clone())
 }
 
+/// The current board of a session without keeping it in memory: the live snapshot if it is
+/// loaded, otherwise the stored copy. `None` if it has never been saved.
+pub async fn session_snapshot(
+    state: &AppState,
+    session_id: &str,
+) -> Result<Option<PersistentSessionData>, SessionLoadError> {
+    if let Some(session) = state.sessions.read().await.get(session_id).cloned() {
+        return Ok(Some(session.read().await.to_persistent_session_data()));
+    }
+    match state.storage.load_session(session_id).await {
+        Ok(data) => {
+            let layers = sanitize_layers(data.layers);
+            Ok(Some(PersistentSessionData {
+                pages: sanitize_pages(data.pages, &layers),
+                layers,
+                access: data.access,
+            }))
+        }
+        Err(StorageError::NotFound) => Ok(None),
+        Err(StorageError::Other(err)) => Err(SessionLoadError::Storage(err)),
+    }
+}
+
 pub async fn save_session(
     state: &AppState,
     session_id: &str,
@@ -1,47 +1,141 @@
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use sha2::{Digest, Sha256};
 use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
-use yumboard_shared::{Stroke, StrokeId};
+use yumboard_shared::{
+    AuthorId, ClientId, ClientSecret, Layer, LayerId, Page, PageId, Role, SessionAccess, Stroke,
+    StrokeId,
+};
 
 use crate::storage::Storage;
 use bincode::{Decode, Encode};
 
-pub const MAX_STROKES: usize = 2000;
-pub const MAX_POINTS_PER_STROKE: usize = 5000;
+pub const MAX_HISTORY_DEPTH: usize = 200;
 
 #[derive(Clone)]
 pub struct AppState {
     pub sessions: Arc<RwLock<HashMap<String, Arc<RwLock<Session>>>>>,
     pub storage: Arc<dyn Storage>,
+    // How long an undo history outlives its last connection.
+    pub history_grace: Duration,
 }
 
 #[derive(Clone, Debug, Default, Encode, Decode)]
 pub struct PersistentSessionData {
-    pub strokes: Vec<Stroke>,
+    pub pages: Vec<Page>,
+    pub layers: Vec<Layer>,
+    pub access: Option<SessionAccess>,
 }
 
 pub struct Session {
-    pub strokes: Vec<Stroke>,
+    /// Never empty.
+    pub pages: Vec<Page>,
+    /// Bottom first; never empty.
+    pub layers: Vec<Layer>,
     pub active_ids: HashSet<StrokeId>,
-    pub owners: HashMap<StrokeId, Uuid>,
-    pub histories: HashMap<Uuid, ClientHistory>,
+    pub histories: HashMap<ClientId, ClientHistory>,
+    pub client_secrets: HashMap<ClientId, ClientSecret>,
     pub peers: HashMap<Uuid, mpsc::UnboundedSender<yumboard_shared::ServerMessage>>,
+    pub identities: HashMap<Uuid, PeerIdentity>,
+    // `None` for sessions created before share tokens existed; those stay editable by anyone.
+    pub access: Option<SessionAccess>,
+    pub roles: HashMap<Uuid, Role>,
+    /// The page each connection last announced with `page:switch`.
+    pub viewing: HashMap<Uuid, PageId>,
     pub transform_sessions: HashMap<Uuid, TransformSession>,
+    pub text_edits: HashMap<Uuid, TextEditSession>,
+    /// Hashes of assets uploaded through this session since it was loaded.
+    pub assets: HashSet<String>,
     pub dirty: bool,
 }
 
+pub struct PeerIdentity {
+    pub client_id: ClientId,
+    pub name: String,
+}
+
 #[derive(Default)]
 pub struct ClientHistory {
     pub undo: Vec<Action>,
     pub redo: Vec<Action>,
+    /// Actions recorded since `batch:start`, pushed as one `Action::Batch` at `batch:end`.
+    pub batch: Option<Vec<Action>>,
+    // Set when the last connection using this history closes; cleared when one reattaches.
+    pub detached_at: Option<Instant>,
+}
+
+impl ClientHistory {
+    /// Records a new user action, dropping the redo stack and the oldest undo entries
+    /// beyond `MAX_HISTORY_DEPTH`. Inside a batch the action waits for the batch to end; a
+    /// batch reaching `MAX_HISTORY_DEPTH` actions becomes an undo step and a new one opens, so
+    /// a batch that never ends stays bounded.
+    pub fn record(&mut self, action: Action) {
+        self.redo.clear();
+        let Some(batch) = &mut self.batch else {
+            self.push_undo(action);
+            return;
+        };
+        batch.push(action);
+        if batch.len() >= MAX_HISTORY_DEPTH {
+            self.start_batch();
+        }
+    }
+
+    /// Records `actions`, taken together, as one user action.
+    pub fn record_all(&mut self, actions: Vec<Action>) {
+        if let Some(action) = Self::group(actions) {
+            self.record(action);
+        }
+    }
+
+    /// Opens a batch, closing any batch left open.
+    pub fn start_batch(&mut self) {
+        self.end_batch();
+        self.batch = Some(Vec::new());
+    }
+
+    /// Closes the open batch, recording what it gathered as one undo step.
+    pub fn end_batch(&mut self) {
+        let Some(actions) = self.batch.take() else {
+            return;
+        };
+        if let Some(action) = Self::group(actions) {
+            self.push_undo(action);
+        }
+    }
+
+    /// One step for `actions`: a single action stays as it is, several become an
+    /// `Action::Batch`, and none is nothing.
+    fn group(mut actions: Vec<Action>) -> Option<Action> {
+        match actions.len() {
+            0 => None,
+            1 => actions.pop(),
+            _ => Some(Action::Batch(actions)),
+        }
+    }
+
+    fn push_undo(&mut self, action: Action) {
+        self.undo.push(action);
+        let overflow = self.undo.len().saturating_sub(MAX_HISTORY_DEPTH);
+        if overflow > 0 {
+            self.undo.drain(0..overflow);
+        }
+    }
 }
 
 pub enum Action {
-    AddStroke(Stroke),
-    EraseStroke(Stroke),
+    AddStroke(PageId, Stroke),
+    /// Strokes added together by `strokes:add`.
+    AddStrokes {
+        page: PageId,
+        strokes: Vec<Stroke>,
+    },
+    EraseStroke(PageId, Stroke),
     Clear {
+        page: PageId,
         strokes: Vec<Stroke>,
     },
     ReplaceStroke {
@@ -52,6 +146,25 @@ pub enum Action {
         before: Vec<Stroke>,
         after: Vec<Stroke>,
     },
+    /// A precise erase that replaced `before` by `fragments` at the same depth.
+    SplitStroke {
+        page: PageId,
+        before: Stroke,
+        fragments: Vec<Stroke>,
+    },
+    /// A deleted page with its strokes and former position.
+    RemovePage {
+        page: Page,
+        index: usize,
+    },
+    /// A change of paint order on `page`, as the page's ids bottom first.
+    Reorder {
+        page: PageId,
+        before: Vec<StrokeId>,
+        after: Vec<StrokeId>,
+    },
+    /// Actions of one gesture, in the order they happened; undone last first as one step.
+    Batch(Vec<Action>),
 }
 
 pub struct TransformSession {
@@ -59,26 +172,221 @@ pub struct TransformSession {
     pub before: Vec<Stroke>,
 }
 
+/// An open text box edit. `before` is `None` when the box was created by this edit.
+pub struct TextEditSession {
+    pub id: StrokeId,
+    pub before: Option<Stroke>,
+}
+
+/// The public author id of `client_id`: the first half of the SHA-256 of its bytes, so a peer
+/// seeing it on strokes cannot turn it back into an id to `hello` with.
+pub fn author_id(client_id: ClientId) -> AuthorId {
+    let [high, low] = client_id.value();
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&high.to_le_bytes());
+    bytes[8..].copy_from_slice(&low.to_le_bytes());
+    let digest = Sha256::digest(bytes);
+    let word = |range: std::ops::Range<usize>| {
+        let mut word = [0; 8];
+        word.copy_from_slice(&digest[range]);
+        u64::from_le_bytes(word)
+    };
+    AuthorId::new([word(0..8), word(8..16)])
+}
+
+/// Whether `stroke` is an image showing asset `hash`.
+pub fn shows_asset(stroke: &Stroke, hash: &str) -> bool {
+    stroke
+        .image
+        .as_ref()
+        .is_some_and(|image| image.hash == hash)
+}
+
+/// The role granted by a share URL token, or `None` if the token does not open the session.
+pub fn role_for_token(access: Option<&SessionAccess>, token: Option<&str>) -> Option<Role> {
+    let Some(access) = access else {
+        return Some(Role::Editor);
+    };
+    let token = token?;
+    if token == access.owner_secret {
+        Some(Role::Owner)
+    } else if token == access.edit_token {
+        Some(Role::Editor)
+    } else if token == access.view_token {
+        Some(Role::Viewer)
+    } else {
+        None
+    }
+}
+
 impl Session {
-    pub fn new(strokes: Vec<Stroke>) -> Self {
+    pub fn new(mut pages: Vec<Page>, mut layers: Vec<Layer>) -> Self {
+        if pages.is_empty() {
+            pages.push(Page::new(PageId::INITIAL));
+        }
+        if layers.is_empty() {
+            layers.push(Layer::base());
+        }
         Self {
-            strokes,
+            pages,
+            layers,
             active_ids: HashSet::new(),
-            owners: HashMap::new(),
             histories: HashMap::new(),
+            client_secrets: HashMap::new(),
             peers: HashMap::new(),
+            identities: HashMap::new(),
+            access: None,
+            roles: HashMap::new(),
+            viewing: HashMap::new(),
             transform_sessions: HashMap::new(),
+            text_edits: HashMap::new(),
+            assets: HashSet::new(),
             dirty: false,
         }
     }
 
     pub fn from_persistent_session_data(data: PersistentSessionData) -> Self {
-        Self::new(data.strokes)
+        let mut session = Self::new(data.pages, data.layers);
+        session.access = data.access;
+        session
+    }
+
+    pub fn role_for_token(&self, token: Option<&str>) -> Option<Role> {
+        role_for_token(self.access.as_ref(), token)
+    }
+
+    pub fn role(&self, connection_id: Uuid) -> Role {
+        self.roles
+            .get(&connection_id)
+            .copied()
+            .unwrap_or(Role::Viewer)
+    }
+
+    /// The stable identity of a connection, falling back to one derived from the connection
+    /// id until the client has sent `hello`.
+    /// The author stamped on strokes `connection_id` creates.
+    pub fn author_id(&self, connection_id: Uuid) -> AuthorId {
+        author_id(self.client_id(connection_id))
+    }
+
+    pub fn client_id(&self, connection_id: Uuid) -> ClientId {
+        match self.identities.get(&connection_id) {
+            Some(identity) => identity.client_id,
+            None => {
+                let (high, low) = connection_id.as_u64_pair();
+                ClientId::new([high, low])
+            }
+        }
+    }
+
+    pub fn history_mut(&mut self, connection_id: Uuid) -> Option<&mut ClientHistory> {
+        let client_id = self.client_id(connection_id);
+        self.histories.get_mut(&client_id)
+    }
+
+    /// Forgets a closed connection. Its undo history is kept (detached) for reclaiming by a
+    /// later `hello` with the same client id and secret, unless it was never identified.
+    pub fn detach_connection(&mut self, connection_id: Uuid) {
+        let client_id = self.client_id(connection_id);
+        let identified = self.identities.remove(&connection_id).is_some();
+        self.peers.remove(&connection_id);
+        self.roles.remove(&connection_id);
+        self.viewing.remove(&connection_id);
+        self.transform_sessions.remove(&connection_id);
+        self.text_edits.remove(&connection_id);
+        let still_attached = self
+            .identities
+            .values()
+            .any(|identity| identity.client_id == client_id);
+        if still_attached {
+            return;
+        }
+        if identified {
+            if let Some(history) = self.histories.get_mut(&client_id) {
+                history.end_batch();
+                history.detached_at = Some(Instant::now());
+            }
+        } else {
+            self.histories.remove(&client_id);
+        }
+    }
+
+    /// Drops detached histories (and their secrets) older than `grace`.
+    pub fn prune_histories(&mut self, grace: Duration) {
+        let expired = self
+            .histories
+            .iter()
+            .filter(|(_, history)| {
+                history
+                    .detached_at
+                    .is_some_and(|detached_at| detached_at.elapsed() >= grace)
+            })
+            .map(|(client_id, _)| *client_id)
+            .collect::<Vec<_>>();
+        for client_id in expired {
+            self.histories.remove(&client_id);
+            self.client_secrets.remove(&client_id);
+        }
+    }
+
+    pub fn page(&self, page: PageId) -> Option<&Page> {
+        self.pages.iter().find(|item| item.id == page)
+    }
+
+    pub fn page_mut(&mut self, page: PageId) -> Option<&mut Page> {
+        self.pages.iter_mut().find(|item| item.id == page)
+    }
+
+    /// Every stroke of every page.
+    pub fn strokes(&self) -> impl Iterator<Item = &Stroke> {
+        self.pages.iter().flat_map(|page| page.strokes.iter())
+    }
+
+    pub fn strokes_mut(&mut self) -> impl Iterator<Item = &mut Stroke> {
+        self.pages
+            .iter_mut()
+            .flat_map(|page| page.strokes.iter_mut())
+    }
+
+    pub fn stroke(&self, id: &StrokeId) -> Option<&Stroke> {
+        self.strokes().find(|stroke| &stroke.id == id)
+    }
+
+    /// Whether images here may show asset `hash`: it was uploaded through this session or a
+    /// stroke here already shows it.
+    pub fn asset_known(&self, hash: &str) -> bool {
+        self.assets.contains(hash) || self.strokes().any(|stroke| shows_asset(stroke, hash))
+    }
+
+    pub fn stroke_mut(&mut self, id: &StrokeId) -> Option<&mut Stroke> {
+        self.strokes_mut().find(|stroke| &stroke.id == id)
+    }
+
+    /// The page holding stroke `id`, and its index there.
+    pub fn locate(&self, id: &StrokeId) -> Option<(PageId, usize)> {
+        self.pages.iter().find_map(|page| {
+            let index = page.strokes.iter().position(|stroke| &stroke.id == id)?;
+            Some((page.id, index))
+        })
+    }
+
+    /// Whether strokes on `layer` may be added or edited: the layer exists and is not locked.
+    pub fn layer_editable(&self, layer: LayerId) -> bool {
+        self.layers
+            .iter()
+            .any(|item| item.id == layer && !item.locked)
+    }
+
+    pub fn stroke_editable(&self, id: &StrokeId) -> bool {
+        self.stroke(id)
+            .is_some_and(|stroke| self.layer_editable(stroke.layer))
     }
 
     pub fn to_persistent_session_data(&self) -> PersistentSessionData {
         PersistentSessionData {
-            strokes: self.strokes.clone(),
+            pages: self.pages.clone(),
+            layers: self.layers.clone(),
+            access: self.access.clone(),
         }
     }
 }
@@ -33,6 +33,10 @@ pub trait Storage: Send + Sync {
         session_id: &str,
         data: &PersistentSessionData,
     ) -> Result<(), String>;
+    /// Image assets are PNG files named by the hex SHA-256 of their bytes, shared by all
+    /// sessions.
+    async fn load_asset(&self, hash: &str) -> Result<Vec<u8>, StorageError>;
+    async fn save_asset(&self, hash: &str, bytes: &[u8]) -> Result<(), String>;
 }
 
 pub struct FileStorage {
@@ -71,11 +75,47 @@ impl Storage for FileStorage {
         }
         Ok(())
     }
+
+    async fn load_asset(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        let path = self.session_dir.join("assets").join(format!("{hash}.png"));
+        tokio::fs::read(path).await.map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                StorageError::NotFound
+            } else {
+                StorageError::Other(format!("Failed to read asset {hash}: {e}"))
+            }
+        })
+    }
+
+    async fn save_asset(&self, hash: &str, bytes: &[u8]) -> Result<(), String> {
+        let dir = self.session_dir.join("assets");
+        let path = dir.join(format!("{hash}.png"));
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(());
+        }
+        if let Err(error) = tokio::fs::create_dir_all(&dir).await {
+            return Err(format!("Failed to create asset dir: {error}"));
+        }
+        // Written aside and renamed into place, so an interrupted write never leaves a truncated
+        // file that the existence check above would keep forever.
+        let temp = dir.join(format!("{hash}.{}.tmp", uuid::Uuid::new_v4()));
+        if let Err(error) = tokio::fs::write(&temp, bytes).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(format!("Failed to save asset {hash}: {error}"));
+        }
+        if let Err(error) = tokio::fs::rename(&temp, path).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(format!("Failed to save asset {hash}: {error}"));
+        }
+        Ok(())
+    }
 }
 
 fn encode_data(data: &PersistentSessionData) -> Vec<u8> {
     let file = SessionFileData {
-        strokes: data.strokes.clone(),
+        pages: data.pages.clone(),
+        layers: data.layers.clone(),
+        access: data.access.clone(),
     };
     encode_session_file(&file)
 }
@@ -83,7 +123,9 @@ fn encode_data(data: &PersistentSessionData) -> Vec<u8> {
 fn decode_data(payload: &[u8]) -> Result<PersistentSessionData, String> {
     match decode_session_file(payload) {
         Ok(data) => Ok(PersistentSessionData {
-            strokes: data.strokes,
+            pages: data.pages,
+            layers: data.layers,
+            access: data.access,
         }),
         Err(SessionFileDecodeError::UnsupportedVersion(version)) => {
             Err(format!("Unsupported session file version: {version}"))
@@ -166,6 +208,14 @@ impl S3Storage {
             format!("{}/{session_id}.ybss", self.prefix)
         }
     }
+
+    fn asset_key(&self, hash: &str) -> String {
+        if self.prefix.is_empty() {
+            format!("assets/{hash}.png")
+        } else {
+            format!("{}/assets/{hash}.png", self.prefix)
+        }
+    }
 }
 
 #[async_trait]
@@ -226,4 +276,50 @@ impl Storage for S3Storage {
         }
         Ok(())
     }
+
+    async fn load_asset(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.asset_key(hash))
+            .send()
+            .await;
+        let output = match response {
+            Ok(output) => output,
+            Err(error) => {
+                if let Some(service_error) = error.as_service_error() {
+                    if service_error.is_no_such_key() {
+                        return Err(StorageError::NotFound);
+                    }
+                }
+                return Err(StorageError::Other(format!(
+                    "Failed to load asset {hash} from s3: {error:?}"
+                )));
+            }
+        };
+        match output.body.collect().await {
+            Ok(collected) => Ok(collected.into_bytes().to_vec()),
+            Err(error) => Err(StorageError::Other(format!(
+                "Failed to read asset {hash} from s3 response: {error:?}"
+            ))),
+        }
+    }
+
+    async fn save_asset(&self, hash: &str, bytes: &[u8]) -> Result<(), String> {
+        // Content-addressed, so an upload of the same bytes simply overwrites them.
+        if let Err(error) = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.asset_key(hash))
+            .content_type("image/png")
+            .body(ByteStream::from(bytes.to_vec()))
+            .send()
+            .await
+        {
+            return Err(format!("Failed to save asset {hash} to s3: {error:?}"));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,61 @@
+use std::borrow::Cow;
+
+use crate::{Point, Stroke};
+
+/// Board units between curve samples where there is no zoom to go by: exports and server renders.
+pub const RENDER_CURVE_SPACING: f64 = 1.0;
+const MAX_SAMPLES_PER_SEGMENT: usize = 16;
+
+/// `stroke` as every renderer draws it. Freehand ink follows a Catmull-Rom curve through its
+/// points, sampled about every `spacing` board units, so the few points left by simplification
+/// still draw smooth; shapes, text and images are returned as they are.
+pub fn curved_stroke(stroke: &Stroke, spacing: f64) -> Cow<'_, Stroke> {
+    if stroke.shape.is_some()
+        || stroke.text.is_some()
+        || stroke.image.is_some()
+        || stroke.points.len() < 3
+    {
+        return Cow::Borrowed(stroke);
+    }
+    Cow::Owned(Stroke {
+        points: catmull_rom(&stroke.points, spacing),
+        ..stroke.clone()
+    })
+}
+
+/// Uniform Catmull-Rom spline through `points`, sampled about every `spacing` board units.
+/// Pressure is interpolated linearly along each segment.
+pub fn catmull_rom(points: &[Point], spacing: f64) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut out = vec![points[0]];
+    for index in 0..points.len() - 1 {
+        let p0 = points[index.saturating_sub(1)];
+        let p1 = points[index];
+        let p2 = points[index + 1];
+        let p3 = points[(index + 2).min(points.len() - 1)];
+        let length = (p2.x as f64 - p1.x as f64).hypot(p2.y as f64 - p1.y as f64);
+        let samples =
+            ((length / spacing.max(0.01)).ceil() as usize).clamp(1, MAX_SAMPLES_PER_SEGMENT);
+        for step in 1..=samples {
+            let t = step as f32 / samples as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            let blend = |a: f32, b: f32, c: f32, d: f32| {
+                0.5 * (2.0 * b
+                    + (c - a) * t
+                    + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+                    + (3.0 * b - a - 3.0 * c + d) * t3)
+            };
+            out.push(Point {
+                x: blend(p0.x, p1.x, p2.x, p3.x),
+                y: blend(p0.y, p1.y, p2.y, p3.y),
+                pressure: match (p1.pressure, p2.pressure) {
+                    (Some(from), Some(to)) => Some(from + (to - from) * t),
+                    (from, to) => to.or(from),
+                },
+            });
+        }
+    }
+    out
+}
@@ -0,0 +1,24 @@
+use crate::layer::{layer_index, layer_visible};
+use crate::{Blend, Layer, Stroke};
+
+/// Alpha of highlighter ink.
+pub const HIGHLIGHT_ALPHA: u8 = 0x66;
+/// Highlighter strokes are this many times wider than pen strokes of the same slider size.
+pub const HIGHLIGHT_SIZE_SCALE: f32 = 3.0;
+
+/// The strokes of visible layers in the order every renderer paints them: layer by layer from
+/// the bottom, and within a layer highlighter ink first, so it sits under normal ink, then
+/// everything else, each group in document order. An empty `layers` is one visible layer.
+pub fn paint_order<'a>(strokes: &'a [Stroke], layers: &[Layer]) -> Vec<&'a Stroke> {
+    let mut ordered = strokes
+        .iter()
+        .filter(|stroke| layer_visible(layers, stroke.layer))
+        .collect::<Vec<_>>();
+    ordered.sort_by_key(|stroke| {
+        (
+            layer_index(layers, stroke.layer),
+            stroke.blend != Blend::Highlight,
+        )
+    });
+    ordered
+}
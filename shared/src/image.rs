@@ -0,0 +1,82 @@
+use crate::{ImageRef, Point, Stroke};
+
+/// Longest side, in pixels, of an uploaded image; the client downscales larger ones.
+pub const MAX_IMAGE_SIDE: u32 = 4096;
+pub const MAX_ASSET_BYTES: usize = 16 * 1024 * 1024;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Response of the asset upload endpoint.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AssetInfo {
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Assets are named by the lowercase hex SHA-256 of their bytes.
+pub fn is_asset_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Width and height from the IHDR chunk of a PNG file; `None` if `bytes` is not one.
+pub fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.len() < 24 || bytes[..8] != PNG_SIGNATURE || &bytes[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// Whether `points` are the four corners an image stroke keeps.
+pub fn image_is_valid(image: &ImageRef, points: &[Point]) -> bool {
+    points.len() == 4 && is_asset_hash(&image.hash) && image.width > 0 && image.height > 0
+}
+
+/// Corners of an image `width` by `height` board units with its top-left corner at `origin`, in the
+/// order `Stroke::points` keeps them: top-left, top-right, bottom-right, bottom-left.
+pub fn image_points(origin: Point, width: f32, height: f32) -> Vec<Point> {
+    let point = |x: f32, y: f32| Point {
+        x,
+        y,
+        pressure: None,
+    };
+    vec![
+        point(origin.x, origin.y),
+        point(origin.x + width, origin.y),
+        point(origin.x + width, origin.y + height),
+        point(origin.x, origin.y + height),
+    ]
+}
+
+/// The affine map `(a, b, c, d, e, f)` taking the unit square (y down) onto the image's corners,
+/// so `(u, v)` lands at `(a u + c v + e, b u + d v + f)`. Transforms keep the corners a
+/// parallelogram, so corner 2 follows from the other three.
+pub fn image_matrix(stroke: &Stroke) -> Option<[f64; 6]> {
+    let image = stroke.image.as_ref()?;
+    if !image_is_valid(image, &stroke.points) {
+        return None;
+    }
+    let p = |index: usize| (stroke.points[index].x as f64, stroke.points[index].y as f64);
+    let (origin, right, down) = (p(0), p(1), p(3));
+    Some([
+        right.0 - origin.0,
+        right.1 - origin.1,
+        down.0 - origin.0,
+        down.1 - origin.1,
+        origin.0,
+        origin.1,
+    ])
+}
+
+/// The closed outline of an image, for hit-testing, bounds and lasso selection.
+pub fn image_outline(stroke: &Stroke) -> Option<Vec<Point>> {
+    let image = stroke.image.as_ref()?;
+    if !image_is_valid(image, &stroke.points) {
+        return None;
+    }
+    let mut corners = stroke.points.clone();
+    corners.push(stroke.points[0]);
+    Some(corners)
+}
@@ -0,0 +1,15 @@
+use crate::{Layer, LayerId};
+
+/// Position of `layer` from the bottom; strokes on layers missing from the list paint with the
+/// bottom one.
+pub fn layer_index(layers: &[Layer], layer: LayerId) -> usize {
+    layers.iter().position(|item| item.id == layer).unwrap_or(0)
+}
+
+/// Whether strokes on `layer` are drawn. Layers missing from the list are.
+pub fn layer_visible(layers: &[Layer], layer: LayerId) -> bool {
+    layers
+        .iter()
+        .find(|item| item.id == layer)
+        .is_none_or(|item| item.visible)
+}
@@ -1,10 +1,21 @@
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
+pub mod curve;
+pub mod highlight;
+pub mod image;
+pub mod layer;
+pub mod order;
+pub mod pdf;
+pub mod pressure;
 pub mod session_format;
+pub mod shape;
+pub mod svg;
+pub mod text;
 
 pub use session_format::{
-    decode_session_file, encode_session_file, SessionFileData, SessionFileDecodeError,
+    decode_session_file, encode_session_file, SessionAccess, SessionFileData,
+    SessionFileDecodeError,
 };
 
 #[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -17,10 +28,145 @@ impl StrokeId {
     }
 }
 
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct PageId([u64; 2]);
+
+impl PageId {
+    /// The page a new session starts with; files saved before pages existed load onto it.
+    pub const INITIAL: PageId = PageId([0, 0]);
+
+    pub fn new(value: [u64; 2]) -> Self {
+        Self(value)
+    }
+}
+
+#[derive(
+    Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(transparent)]
+pub struct LayerId([u64; 2]);
+
+impl LayerId {
+    /// The layer a new session starts with; strokes saved before layers are on it.
+    pub const BASE: LayerId = LayerId([0, 0]);
+
+    pub fn new(value: [u64; 2]) -> Self {
+        Self(value)
+    }
+
+    pub fn is_base(&self) -> bool {
+        *self == LayerId::BASE
+    }
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct PeerId([u64; 2]);
+
+impl PeerId {
+    pub fn new(value: [u64; 2]) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> [u64; 2] {
+        self.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct ClientId([u64; 2]);
+
+impl ClientId {
+    pub fn new(value: [u64; 2]) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> [u64; 2] {
+        self.0
+    }
+}
+
+/// The public name of a client on the strokes it created, derived one way from its `ClientId`
+/// by the server. Unlike the `ClientId` it is shown to every peer and saved with the board, and
+/// a `hello` never accepts it.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct AuthorId([u64; 2]);
+
+impl AuthorId {
+    pub fn new(value: [u64; 2]) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> [u64; 2] {
+        self.0
+    }
+}
+
+/// Proof that a `hello` comes from the client that first claimed its `ClientId`.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ClientSecret([u64; 2]);
+
+impl ClientSecret {
+    pub fn new(value: [u64; 2]) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> [u64; 2] {
+        self.0
+    }
+}
+
+/// What a connection may do, decided by the token in its share URL.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    #[serde(rename = "owner")]
+    Owner,
+    #[serde(rename = "editor")]
+    Editor,
+    #[serde(rename = "viewer")]
+    Viewer,
+}
+
+impl Role {
+    pub fn can_edit(self) -> bool {
+        !matches!(self, Role::Viewer)
+    }
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug)]
+pub struct PeerInfo {
+    pub peer: PeerId,
+    pub name: Option<String>,
+    /// The page the peer is looking at, once it has said.
+    pub page: Option<PageId>,
+}
+
+pub const MAX_DISPLAY_NAME_CHARS: usize = 40;
+
+/// Drops control characters and surrounding whitespace and keeps at most
+/// `MAX_DISPLAY_NAME_CHARS` characters. The server applies it to display and layer names; the
+/// client to the name it stores and announces.
+pub fn sanitize_display_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(MAX_DISPLAY_NAME_CHARS)
+        .collect()
+}
+
 #[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq)]
 pub struct Point {
     pub x: f32,
     pub y: f32,
+    /// Pen pressure in `0..=1`; `None` for mice, touch and strokes saved before pressure.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<f32>,
 }
 
 impl Point {
@@ -28,6 +174,7 @@ impl Point {
         Self {
             x: clamp_unit(self.x),
             y: clamp_unit(self.y),
+            ..self
         }
     }
 }
@@ -36,12 +183,184 @@ fn clamp_unit(value: f32) -> f32 {
     value.max(0.0).min(1.0)
 }
 
-#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug)]
+/// Points the server keeps per stroke; later points are dropped.
+pub const MAX_POINTS_PER_STROKE: usize = 5000;
+
+/// Points relayed per `laser:move`; later points are dropped.
+pub const MAX_LASER_POINTS: usize = 64;
+
+/// Strokes per page. Drawing past it drops the page's oldest strokes; `strokes:add` only fills
+/// the room left.
+pub const MAX_STROKES: usize = 2000;
+
+/// Pages per session; `page:add` beyond it is ignored.
+pub const MAX_PAGES: usize = 100;
+
+/// Layers per session; `layer:add` beyond it is ignored.
+pub const MAX_LAYERS: usize = 20;
+
+/// Board units covered by one step of `Stroke::size`.
+pub const STROKE_UNIT: f64 = 1.0;
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug, PartialEq)]
 pub struct Stroke {
     pub id: StrokeId,
     pub color: Color,
     pub size: f32,
     pub points: Vec<Point>,
+    /// Set for geometric shapes, whose `points` are control points rather than a freehand path:
+    /// the four corners (in order around the outline) of a rectangle or ellipse, or the two ends of
+    /// a line or arrow. `color` and `size` style the outline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<Shape>,
+    /// Set for text boxes, whose single point is the top-left corner of the box. `color` is the
+    /// text color and `size` is unused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextBox>,
+    /// Set for images, whose four points are the corners of the picture (top-left, top-right,
+    /// bottom-right, bottom-left of the source). `color` and `size` are unused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageRef>,
+    /// How the ink mixes with the board; highlighter strokes keep their translucency in `color`.
+    #[serde(default, skip_serializing_if = "Blend::is_normal")]
+    pub blend: Blend,
+    /// The layer the stroke is on; layers are shared by all pages.
+    #[serde(default, skip_serializing_if = "LayerId::is_base")]
+    pub layer: LayerId,
+    /// The client that created the stroke, stamped by the server. `None` for strokes saved
+    /// before authors, and on a client's own new strokes until its next `sync`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<AuthorId>,
+}
+
+/// One board of a session, in paint order. Stroke ids are unique across all pages, so
+/// messages naming a stroke by id need no page.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug)]
+pub struct Page {
+    pub id: PageId,
+    pub strokes: Vec<Stroke>,
+}
+
+impl Page {
+    pub fn new(id: PageId) -> Self {
+        Self {
+            id,
+            strokes: Vec::new(),
+        }
+    }
+}
+
+/// A named level of the board. Layers paint bottom first, in the order of the session's list;
+/// within a layer, strokes keep page order.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub id: LayerId,
+    pub name: String,
+    /// Hidden layers are left out of the board, exports and every tool.
+    pub visible: bool,
+    /// Locked layers are drawn but their strokes cannot be edited, and nothing can be added to
+    /// them.
+    pub locked: bool,
+}
+
+impl Layer {
+    pub fn new(id: LayerId, name: String) -> Self {
+        Self {
+            id,
+            name,
+            visible: true,
+            locked: false,
+        }
+    }
+
+    /// The only layer of a new session, and of boards saved before layers.
+    pub fn base() -> Self {
+        Self::new(LayerId::BASE, "Layer 1".to_string())
+    }
+}
+
+#[derive(
+    Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub enum Blend {
+    #[default]
+    #[serde(rename = "normal")]
+    Normal,
+    /// Highlighter ink: painted as one path per stroke, so it does not darken where it overlaps
+    /// itself, and underneath all normal ink (see `highlight::paint_order`).
+    #[serde(rename = "highlight")]
+    Highlight,
+}
+
+impl Blend {
+    pub fn is_normal(&self) -> bool {
+        *self == Blend::Normal
+    }
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShapeKind {
+    #[serde(rename = "rect")]
+    Rect,
+    #[serde(rename = "ellipse")]
+    Ellipse,
+    #[serde(rename = "line")]
+    Line,
+    #[serde(rename = "arrow")]
+    Arrow,
+}
+
+impl ShapeKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rect" => Some(ShapeKind::Rect),
+            "ellipse" => Some(ShapeKind::Ellipse),
+            "line" => Some(ShapeKind::Line),
+            "arrow" => Some(ShapeKind::Arrow),
+            _ => None,
+        }
+    }
+
+    /// Number of control points a shape of this kind keeps in `Stroke::points`.
+    pub fn point_count(self) -> usize {
+        match self {
+            ShapeKind::Rect | ShapeKind::Ellipse => 4,
+            ShapeKind::Line | ShapeKind::Arrow => 2,
+        }
+    }
+
+    pub fn is_closed(self) -> bool {
+        matches!(self, ShapeKind::Rect | ShapeKind::Ellipse)
+    }
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq)]
+pub struct Shape {
+    pub kind: ShapeKind,
+    /// Interior of a rectangle or ellipse; `None` leaves it hollow. Ignored for open shapes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill: Option<Color>,
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug, PartialEq)]
+pub struct TextBox {
+    pub content: String,
+    /// Em size in board units.
+    pub font_size: f32,
+    /// Lines wrap at this width in board units (see `text::wrap_lines`).
+    pub wrap_width: f32,
+}
+
+pub const MAX_TEXT_CHARS: usize = 10_000;
+
+/// An uploaded PNG asset of the session, stored once per content hash.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct ImageRef {
+    /// Lowercase hex SHA-256 of the PNG bytes (see `image::is_asset_hash`).
+    pub hash: String,
+    /// Pixel size of the asset.
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -102,32 +421,97 @@ pub enum TransformOp {
     Rotate { center: Point, delta: f64 },
 }
 
+/// Where `reorder` moves strokes in their page's paint order.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZOrder {
+    #[serde(rename = "front")]
+    Front,
+    #[serde(rename = "forward")]
+    Forward,
+    #[serde(rename = "backward")]
+    Backward,
+    #[serde(rename = "back")]
+    Back,
+}
+
 #[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug)]
 #[serde(tag = "type")]
 pub enum ClientMessage {
+    /// Sent first on every connection; repeating it later only renames the peer.
+    #[serde(rename = "hello")]
+    Hello {
+        client_id: ClientId,
+        secret: ClientSecret,
+        name: String,
+    },
     #[serde(rename = "stroke:start")]
     StrokeStart {
+        page: PageId,
         id: StrokeId,
         color: Color,
         size: f32,
         point: Point,
+        #[serde(default, skip_serializing_if = "Blend::is_normal")]
+        blend: Blend,
+        #[serde(default, skip_serializing_if = "LayerId::is_base")]
+        layer: LayerId,
     },
     #[serde(rename = "stroke:move")]
     StrokeMove { id: StrokeId, point: Point },
     #[serde(rename = "stroke:points")]
     StrokePoints { id: StrokeId, points: Vec<Point> },
+    /// `points`, when set, replaces the stroke's points with the client's smoothed version before
+    /// the stroke is recorded for undo.
     #[serde(rename = "stroke:end")]
-    StrokeEnd { id: StrokeId },
+    StrokeEnd {
+        id: StrokeId,
+        #[serde(default)]
+        points: Option<Vec<Point>>,
+    },
     #[serde(rename = "clear")]
-    Clear,
+    Clear { page: PageId },
     #[serde(rename = "undo")]
     Undo,
     #[serde(rename = "redo")]
     Redo,
     #[serde(rename = "erase")]
     Erase { id: StrokeId },
+    /// Replaces a freehand stroke by the pieces a precise eraser left of it; `fragments`
+    /// carry fresh ids and may be empty.
+    #[serde(rename = "stroke:split")]
+    StrokeSplit {
+        id: StrokeId,
+        fragments: Vec<Stroke>,
+    },
     #[serde(rename = "stroke:replace")]
     StrokeReplace { stroke: Stroke },
+    /// Adds a finished shape (`stroke.shape` must be set).
+    #[serde(rename = "shape:create")]
+    ShapeCreate { page: PageId, stroke: Stroke },
+    /// Restyles a shape; its geometry is edited through transforms.
+    #[serde(rename = "shape:update")]
+    ShapeUpdate {
+        id: StrokeId,
+        color: Color,
+        size: f32,
+        shape: Shape,
+    },
+    /// Adds a text box (`stroke.text` must be set) and starts editing it.
+    #[serde(rename = "text:create")]
+    TextCreate { page: PageId, stroke: Stroke },
+    /// Live edit of a text box; the first one after `text:end` starts a new edit.
+    #[serde(rename = "text:update")]
+    TextUpdate { id: StrokeId, text: TextBox },
+    /// Finishes an edit, recording it as one undo step. Empty text boxes are removed.
+    #[serde(rename = "text:end")]
+    TextEnd { id: StrokeId },
+    /// Adds an image (`stroke.image` must be set) whose asset was uploaded beforehand.
+    #[serde(rename = "image:create")]
+    ImageCreate { page: PageId, stroke: Stroke },
+    /// Adds finished strokes with fresh ids on top of `page` in one step that undoes as one,
+    /// e.g. a pasted selection.
+    #[serde(rename = "strokes:add")]
+    AddStrokes { page: PageId, strokes: Vec<Stroke> },
     #[serde(rename = "transform:update")]
     TransformUpdate {
         ids: Vec<StrokeId>,
@@ -138,23 +522,74 @@ pub enum ClientMessage {
     TransformStart { ids: Vec<StrokeId> },
     #[serde(rename = "transform:end")]
     TransformEnd { ids: Vec<StrokeId> },
+    /// Opens a gesture, such as an erase drag, whose edits undo together until `batch:end`.
+    #[serde(rename = "batch:start")]
+    BatchStart,
+    #[serde(rename = "batch:end")]
+    BatchEnd,
+    /// Deletes strokes as one undo step.
     #[serde(rename = "remove")]
     Remove { ids: Vec<StrokeId> },
+    /// Moves strokes within their page's paint order; ids on other pages than the first are
+    /// ignored.
+    #[serde(rename = "reorder")]
+    Reorder { ids: Vec<StrokeId>, to: ZOrder },
     #[serde(rename = "load")]
-    Load { strokes: Vec<Stroke> },
+    Load {
+        pages: Vec<Page>,
+        #[serde(default)]
+        layers: Vec<Layer>,
+    },
+    /// Pointer position for other peers; `None` hides the cursor.
+    #[serde(rename = "cursor:move")]
+    CursorMove { point: Option<Point>, color: Color },
+    /// Laser pointer samples, relayed to peers and never stored; `start` begins a new trail.
+    #[serde(rename = "laser:move")]
+    LaserMove { points: Vec<Point>, start: bool },
+    /// Inserts an empty page at `index` (clamped to the page count).
+    #[serde(rename = "page:add")]
+    PageAdd { page: PageId, index: usize },
+    /// Deletes a page with everything on it; the last page cannot be removed.
+    #[serde(rename = "page:remove")]
+    PageRemove { page: PageId },
+    #[serde(rename = "page:move")]
+    PageMove { page: PageId, index: usize },
+    /// The page this client is looking at, for peers' presence; changes nothing on the board.
+    #[serde(rename = "page:switch")]
+    PageSwitch { page: PageId },
+    /// Inserts `layer` at `index` (clamped to the layer count), bottom first.
+    #[serde(rename = "layer:add")]
+    LayerAdd { layer: Layer, index: usize },
+    /// Renames, hides or locks the layer with the same id.
+    #[serde(rename = "layer:update")]
+    LayerUpdate { layer: Layer },
+    #[serde(rename = "layer:move")]
+    LayerMove { layer: LayerId, index: usize },
+    /// Deletes a layer without strokes on any page; the last layer cannot be removed.
+    #[serde(rename = "layer:remove")]
+    LayerRemove { layer: LayerId },
 }
 
 #[derive(Serialize, Deserialize, Encode, Decode, Clone, Debug)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
     #[serde(rename = "sync")]
-    Sync { strokes: Vec<Stroke> },
+    Sync {
+        pages: Vec<Page>,
+        layers: Vec<Layer>,
+    },
     #[serde(rename = "stroke:start")]
     StrokeStart {
+        page: PageId,
         id: StrokeId,
         color: Color,
         size: f32,
         point: Point,
+        #[serde(default, skip_serializing_if = "Blend::is_normal")]
+        blend: Blend,
+        #[serde(default, skip_serializing_if = "LayerId::is_base")]
+        layer: LayerId,
+        author: AuthorId,
     },
     #[serde(rename = "stroke:move")]
     StrokeMove { id: StrokeId, point: Point },
@@ -163,17 +598,92 @@ pub enum ServerMessage {
     #[serde(rename = "stroke:end")]
     StrokeEnd { id: StrokeId },
     #[serde(rename = "clear")]
-    Clear,
+    Clear { page: PageId },
     #[serde(rename = "stroke:remove")]
     StrokeRemove { id: StrokeId },
     #[serde(rename = "stroke:restore")]
-    StrokeRestore { stroke: Stroke },
+    StrokeRestore { page: PageId, stroke: Stroke },
+    /// Removes `remove` and inserts `insert` on `page` where the first removed stroke was, in
+    /// one step.
+    #[serde(rename = "stroke:splice")]
+    StrokeSplice {
+        page: PageId,
+        remove: Vec<StrokeId>,
+        insert: Vec<Stroke>,
+    },
     #[serde(rename = "stroke:replace")]
     StrokeReplace { stroke: Stroke },
+    /// The paint order of `page` after a reorder, bottom first; see [`order::arrange`].
+    #[serde(rename = "reorder")]
+    Reorder { page: PageId, order: Vec<StrokeId> },
+    /// Adds a finished shape (`stroke.shape` must be set).
+    #[serde(rename = "shape:create")]
+    ShapeCreate { page: PageId, stroke: Stroke },
+    /// Restyles a shape; its geometry is edited through transforms.
+    #[serde(rename = "shape:update")]
+    ShapeUpdate {
+        id: StrokeId,
+        color: Color,
+        size: f32,
+        shape: Shape,
+    },
+    #[serde(rename = "text:create")]
+    TextCreate { page: PageId, stroke: Stroke },
+    #[serde(rename = "text:update")]
+    TextUpdate { id: StrokeId, text: TextBox },
+    #[serde(rename = "image:create")]
+    ImageCreate { page: PageId, stroke: Stroke },
     #[serde(rename = "transform:update")]
     TransformUpdate {
         ids: Vec<StrokeId>,
         #[serde(flatten)]
         op: TransformOp,
     },
+    /// Sent once, before `sync`. Share tokens are only included for roles allowed to hand
+    /// them out.
+    #[serde(rename = "access")]
+    Access {
+        role: Role,
+        edit_token: Option<String>,
+        view_token: Option<String>,
+    },
+    #[serde(rename = "peers")]
+    Peers { peers: Vec<PeerInfo> },
+    #[serde(rename = "peer:join")]
+    PeerJoin { peer: PeerId },
+    #[serde(rename = "peer:name")]
+    PeerName { peer: PeerId, name: String },
+    #[serde(rename = "peer:leave")]
+    PeerLeave { peer: PeerId },
+    #[serde(rename = "peer:cursor")]
+    PeerCursor {
+        peer: PeerId,
+        point: Option<Point>,
+        color: Color,
+    },
+    #[serde(rename = "peer:laser")]
+    PeerLaser {
+        peer: PeerId,
+        points: Vec<Point>,
+        start: bool,
+    },
+    #[serde(rename = "peer:page")]
+    PeerPage { peer: PeerId, page: PageId },
+    #[serde(rename = "page:add")]
+    PageAdd { page: PageId, index: usize },
+    #[serde(rename = "page:remove")]
+    PageRemove { page: PageId },
+    #[serde(rename = "page:move")]
+    PageMove { page: PageId, index: usize },
+    /// The whole layer list after any layer edit, sent to every peer including the editor.
+    #[serde(rename = "layers")]
+    Layers { layers: Vec<Layer> },
+    /// Sent only to the editor whose undo or redo left these strokes as they were, because
+    /// someone else changed them since or their layer is locked.
+    #[serde(rename = "history:skipped")]
+    HistorySkipped { ids: Vec<StrokeId> },
+    /// Sent only to the editor whose `strokes:add` to `page` dropped these strokes, e.g.
+    /// images whose asset this board does not know, so it can take them off its board.
+    #[serde(rename = "strokes:rejected")]
+    StrokesRejected { page: PageId, ids: Vec<StrokeId> },
 }
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Stroke, StrokeId, ZOrder};
+
+/// Ids of `strokes` bottom first once `ids` are moved as `to` says. A stroke only steps past
+/// strokes on its own layer, since layers paint apart anyway.
+pub fn reordered(strokes: &[Stroke], ids: &[StrokeId], to: ZOrder) -> Vec<StrokeId> {
+    let selected = ids.iter().copied().collect::<HashSet<_>>();
+    let mut order = strokes
+        .iter()
+        .map(|stroke| (stroke.id, stroke.layer))
+        .collect::<Vec<_>>();
+    match to {
+        ZOrder::Front | ZOrder::Back => {
+            let (moved, rest): (Vec<_>, Vec<_>) =
+                order.into_iter().partition(|(id, _)| selected.contains(id));
+            order = if to == ZOrder::Front {
+                rest.into_iter().chain(moved).collect()
+            } else {
+                moved.into_iter().chain(rest).collect()
+            };
+        }
+        ZOrder::Forward => {
+            for index in (0..order.len()).rev() {
+                let (id, layer) = order[index];
+                if !selected.contains(&id) {
+                    continue;
+                }
+                let Some(next) = (index + 1..order.len()).find(|&next| order[next].1 == layer)
+                else {
+                    continue;
+                };
+                if selected.contains(&order[next].0) {
+                    continue;
+                }
+                let item = order.remove(index);
+                order.insert(next, item);
+            }
+        }
+        ZOrder::Backward => {
+            for index in 0..order.len() {
+                let (id, layer) = order[index];
+                if !selected.contains(&id) {
+                    continue;
+                }
+                let Some(previous) = (0..index)
+                    .rev()
+                    .find(|&previous| order[previous].1 == layer)
+                else {
+                    continue;
+                };
+                if selected.contains(&order[previous].0) {
+                    continue;
+                }
+                let item = order.remove(index);
+                order.insert(previous, item);
+            }
+        }
+    }
+    order.into_iter().map(|(id, _)| id).collect()
+}
+
+/// Sorts the strokes listed in `order` into that order within the slots they already take;
+/// strokes not listed keep their places and listed ids that are gone are skipped.
+pub fn arrange(strokes: &mut [Stroke], order: &[StrokeId]) {
+    let rank = order
+        .iter()
+        .enumerate()
+        .map(|(index, id)| (*id, index))
+        .collect::<HashMap<_, _>>();
+    let slots = strokes
+        .iter()
+        .enumerate()
+        .filter(|(_, stroke)| rank.contains_key(&stroke.id))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut listed = slots
+        .iter()
+        .map(|&index| strokes[index].clone())
+        .collect::<Vec<_>>();
+    listed.sort_by_key(|stroke| rank[&stroke.id]);
+    for (slot, stroke) in slots.into_iter().zip(listed) {
+        strokes[slot] = stroke;
+    }
+}
@@ -0,0 +1,648 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::curve::{curved_stroke, RENDER_CURVE_SPACING};
+use crate::highlight::paint_order;
+use crate::image::image_matrix;
+use crate::pressure::{circle_curves, has_pressure, stroke_outline, OutlinePart};
+use crate::shape::{arrow_head, shape_path, PathSegment};
+use crate::svg::{ink_bounds, SvgRect};
+use crate::text::{wrap_lines, TEXT_ASCENT, TEXT_LINE_HEIGHT};
+use crate::{Color, Layer, ShapeKind, Stroke, STROKE_UNIT};
+
+/// PDF points per board unit when drawing at natural size (board units are CSS pixels).
+pub const PDF_POINTS_PER_UNIT: f64 = 0.75;
+
+/// PDF pages per document; `render_pdf` refuses layouts that would need more.
+pub const MAX_PDF_PAGES: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaperSize {
+    A4,
+    A3,
+    Letter,
+}
+
+impl PaperSize {
+    /// Portrait width and height in points.
+    pub fn dimensions(self) -> (f64, f64) {
+        match self {
+            PaperSize::A4 => (595.28, 841.89),
+            PaperSize::A3 => (841.89, 1190.55),
+            PaperSize::Letter => (612.0, 792.0),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "a4" => Some(PaperSize::A4),
+            "a3" => Some(PaperSize::A3),
+            "letter" => Some(PaperSize::Letter),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PdfLayout {
+    /// A single page per board. Without a paper size the page is cut to the drawing at natural
+    /// size; with one, the drawing is scaled to fit the page in whichever orientation suits it.
+    FitToContent { paper: Option<PaperSize> },
+    /// The drawing at natural size, split across as many pages as it needs.
+    Tiled { paper: PaperSize, landscape: bool },
+}
+
+impl PdfLayout {
+    /// Parses the layout names shared by the export menu and the HTTP export: `fit` or `tiled`.
+    /// Tiling without a paper size uses A4.
+    pub fn from_name(name: &str, paper: Option<PaperSize>, landscape: bool) -> Option<Self> {
+        match name {
+            "fit" => Some(PdfLayout::FitToContent { paper }),
+            "tiled" => Some(PdfLayout::Tiled {
+                paper: paper.unwrap_or(PaperSize::A4),
+                landscape,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdfOptions {
+    pub layout: PdfLayout,
+    /// Blank border on every page, in points.
+    pub margin: f64,
+    /// Decoded pixels of each image asset by hash. Images without them are left out.
+    pub images: HashMap<String, RasterImage>,
+    /// Stacking order and visibility, shared by all boards; hidden layers are left out.
+    pub layers: Vec<Layer>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            layout: PdfLayout::FitToContent { paper: None },
+            margin: 18.0,
+            images: HashMap::new(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+/// Straight (not premultiplied) 8-bit RGBA pixels, row by row from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RasterImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+struct Page {
+    width: f64,
+    height: f64,
+    // Board-to-page transform: page = board * scale + offset (y measured from the top).
+    scale: f64,
+    offset_x: f64,
+    offset_y: f64,
+}
+
+/// Writes `boards` (the strokes of each board page, in order) as a vector PDF; every board
+/// starts on a new PDF page. `None` if the layout needs more than `MAX_PDF_PAGES` pages, as
+/// tiling far-flung ink at natural size can.
+pub fn render_pdf(boards: &[&[Stroke]], options: &PdfOptions) -> Option<Vec<u8>> {
+    let margin = if options.margin.is_finite() {
+        options.margin.max(0.0)
+    } else {
+        0.0
+    };
+
+    let mut layouts = Vec::with_capacity(boards.len());
+    let mut total = 0;
+    for &strokes in boards {
+        let painted = paint_order(strokes, &options.layers);
+        let bounds = ink_bounds(painted.iter().copied()).unwrap_or(SvgRect {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        });
+        let pages = layout_pages(&bounds, options.layout, margin)?;
+        total += pages.len();
+        if total > MAX_PDF_PAGES {
+            return None;
+        }
+        layouts.push((strokes, painted, pages));
+    }
+
+    let mut writer = PdfWriter::default();
+    let catalog = writer.reserve();
+    let pages_id = writer.reserve();
+    let font_id = writer.add(
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    );
+    let all_strokes = boards.iter().flat_map(|strokes| strokes.iter());
+    let image_ids = image_objects(&mut writer, all_strokes, &options.images);
+    let x_objects = image_ids
+        .values()
+        .map(|id| format!("/Im{id} {id} 0 R"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut page_ids = Vec::new();
+    for (strokes, painted, pages) in &layouts {
+        let alpha = alpha_states(strokes);
+        for page in pages {
+            let content = page_content(painted, page, margin, &image_ids);
+            let content_id = writer.add(format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            ));
+            let page_id = writer.add(format!(
+                "<< /Type /Page /Parent {pages_id} 0 R /MediaBox [0 0 {} {}] /Resources << /ExtGState {alpha} /Font << /F1 {font_id} 0 R >> /XObject << {x_objects} >> >> /Contents {content_id} 0 R >>",
+                num(page.width),
+                num(page.height),
+            ));
+            page_ids.push(page_id);
+        }
+    }
+    let kids = page_ids
+        .iter()
+        .map(|id| format!("{id} 0 R"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writer.set(
+        pages_id,
+        format!(
+            "<< /Type /Pages /Kids [{kids}] /Count {} >>",
+            page_ids.len()
+        ),
+    );
+    writer.set(
+        catalog,
+        format!("<< /Type /Catalog /Pages {pages_id} 0 R >>"),
+    );
+    Some(writer.finish(catalog))
+}
+
+/// One image XObject per asset drawn in `strokes`, with a soft mask when it has transparency.
+/// Returns the object id of each by hash; pages name them `/Im<id>`.
+fn image_objects<'a>(
+    writer: &mut PdfWriter,
+    strokes: impl IntoIterator<Item = &'a Stroke>,
+    images: &HashMap<String, RasterImage>,
+) -> HashMap<String, usize> {
+    let mut ids = HashMap::new();
+    for stroke in strokes {
+        let Some(hash) = stroke.image.as_ref().map(|image| &image.hash) else {
+            continue;
+        };
+        let Some(image) = images.get(hash) else {
+            continue;
+        };
+        let pixels = image.width as usize * image.height as usize;
+        if ids.contains_key(hash) || pixels == 0 || image.rgba.len() != pixels * 4 {
+            continue;
+        }
+        let mut rgb = Vec::with_capacity(pixels * 3);
+        let mut alpha = Vec::with_capacity(pixels);
+        for pixel in image.rgba.chunks_exact(4) {
+            rgb.extend_from_slice(&pixel[..3]);
+            alpha.push(pixel[3]);
+        }
+        let size = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /BitsPerComponent 8 /Filter /FlateDecode",
+            image.width, image.height
+        );
+        let mask = if alpha.iter().any(|value| *value < 0xff) {
+            let id = writer.add_stream(
+                format!("{size} /ColorSpace /DeviceGray"),
+                &miniz_oxide::deflate::compress_to_vec_zlib(&alpha, 6),
+            );
+            format!(" /SMask {id} 0 R")
+        } else {
+            String::new()
+        };
+        let id = writer.add_stream(
+            format!("{size} /ColorSpace /DeviceRGB{mask}"),
+            &miniz_oxide::deflate::compress_to_vec_zlib(&rgb, 6),
+        );
+        ids.insert(hash.clone(), id);
+    }
+    ids
+}
+
+/// The pages of one board; `None` for a tiling of more than `MAX_PDF_PAGES` pages.
+fn layout_pages(bounds: &SvgRect, layout: PdfLayout, margin: f64) -> Option<Vec<Page>> {
+    let pages = match layout {
+        PdfLayout::FitToContent { paper: None } => {
+            let scale = PDF_POINTS_PER_UNIT;
+            vec![Page {
+                width: bounds.width * scale + margin * 2.0,
+                height: bounds.height * scale + margin * 2.0,
+                scale,
+                offset_x: margin - bounds.x * scale,
+                offset_y: margin - bounds.y * scale,
+            }]
+        }
+        PdfLayout::FitToContent { paper: Some(paper) } => {
+            let (short, long) = paper.dimensions();
+            let (width, height) = if bounds.width > bounds.height {
+                (long, short)
+            } else {
+                (short, long)
+            };
+            let area_width = (width - margin * 2.0).max(1.0);
+            let area_height = (height - margin * 2.0).max(1.0);
+            let scale = (area_width / bounds.width).min(area_height / bounds.height);
+            vec![Page {
+                width,
+                height,
+                scale,
+                offset_x: margin + (area_width - bounds.width * scale) / 2.0 - bounds.x * scale,
+                offset_y: margin + (area_height - bounds.height * scale) / 2.0 - bounds.y * scale,
+            }]
+        }
+        PdfLayout::Tiled { paper, landscape } => {
+            let (short, long) = paper.dimensions();
+            let (width, height) = if landscape {
+                (long, short)
+            } else {
+                (short, long)
+            };
+            let scale = PDF_POINTS_PER_UNIT;
+            let area_width = (width - margin * 2.0).max(1.0);
+            let area_height = (height - margin * 2.0).max(1.0);
+            // Float-to-int casts saturate, so huge extents fail the checked product below.
+            let columns = ((bounds.width * scale) / area_width).ceil().max(1.0) as usize;
+            let rows = ((bounds.height * scale) / area_height).ceil().max(1.0) as usize;
+            let count = columns
+                .checked_mul(rows)
+                .filter(|count| *count <= MAX_PDF_PAGES)?;
+            let mut pages = Vec::with_capacity(count);
+            for row in 0..rows {
+                for column in 0..columns {
+                    pages.push(Page {
+                        width,
+                        height,
+                        scale,
+                        offset_x: margin - bounds.x * scale - column as f64 * area_width,
+                        offset_y: margin - bounds.y * scale - row as f64 * area_height,
+                    });
+                }
+            }
+            pages
+        }
+    };
+    Some(pages)
+}
+
+/// Draws `strokes`, already in paint order.
+fn page_content(
+    strokes: &[&Stroke],
+    page: &Page,
+    margin: f64,
+    image_ids: &HashMap<String, usize>,
+) -> String {
+    let mut out = String::new();
+    // Clip to the margins, then flip to a top-left origin in board units.
+    let _ = writeln!(
+        out,
+        "{} {} {} {} re W n",
+        num(margin),
+        num(margin),
+        num(page.width - margin * 2.0),
+        num(page.height - margin * 2.0)
+    );
+    let _ = writeln!(
+        out,
+        "{} 0 0 {} {} {} cm",
+        num(page.scale),
+        num(-page.scale),
+        num(page.offset_x),
+        num(page.height - page.offset_y)
+    );
+    out.push_str("1 J 1 j\n");
+    for &stroke in strokes {
+        let Some(first) = stroke.points.first() else {
+            continue;
+        };
+        let width = stroke.size as f64 * STROKE_UNIT;
+        if let Some(image) = &stroke.image {
+            if let (Some(id), Some([a, b, c, d, e, f])) =
+                (image_ids.get(&image.hash), image_matrix(stroke))
+            {
+                // Image space puts the first row at the top of the unit square, i.e. at v = 1.
+                let _ = writeln!(
+                    out,
+                    "q {} {} {} {} {} {} cm /Im{id} Do Q",
+                    num(a),
+                    num(b),
+                    num(-c),
+                    num(-d),
+                    num(e + c),
+                    num(f + d)
+                );
+            }
+            continue;
+        }
+        if let Some(text) = &stroke.text {
+            out.push_str("q\n");
+            set_fill(&mut out, stroke.color);
+            // Undo the page flip so glyphs stand upright; board y becomes -y.
+            out.push_str("1 0 0 -1 0 0 cm\n");
+            let em = text.font_size as f64;
+            for (index, line) in wrap_lines(text).iter().enumerate() {
+                let baseline =
+                    first.y as f64 + (index as f64 * TEXT_LINE_HEIGHT + TEXT_ASCENT) * em;
+                let _ = writeln!(
+                    out,
+                    "BT /F1 {} Tf {} {} Td ({}) Tj ET",
+                    num(em),
+                    num(first.x as f64),
+                    num(-baseline),
+                    pdf_string(line)
+                );
+            }
+            out.push_str("Q\n");
+            continue;
+        }
+        if let Some(shape) = stroke.shape {
+            // The fill has its own alpha, so it is painted separately from the outline.
+            if let Some(fill) = shape.fill.filter(|_| shape.kind.is_closed()) {
+                out.push_str("q\n");
+                set_fill(&mut out, fill);
+                segments_path(&mut out, &shape_path(stroke));
+                out.push_str("f\nQ\n");
+            }
+            out.push_str("q\n");
+            set_fill(&mut out, stroke.color);
+            let Color { r, g, b, .. } = stroke.color;
+            let _ = writeln!(
+                out,
+                "{} {} {} RG {} w",
+                num(r as f64 / 255.0),
+                num(g as f64 / 255.0),
+                num(b as f64 / 255.0),
+                num(width)
+            );
+            segments_path(&mut out, &shape_path(stroke));
+            out.push_str("S\n");
+            if let Some(head) =
+                arrow_head(&stroke.points, stroke.size).filter(|_| shape.kind == ShapeKind::Arrow)
+            {
+                for (index, (x, y)) in head.iter().enumerate() {
+                    let operator = if index == 0 { "m" } else { "l" };
+                    let _ = writeln!(out, "{} {} {operator}", num(*x), num(*y));
+                }
+                out.push_str("h f\n");
+            }
+            out.push_str("Q\n");
+            continue;
+        }
+        let stroke = &*curved_stroke(stroke, RENDER_CURVE_SPACING);
+        let Color { r, g, b, a } = stroke.color;
+        let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+        out.push_str("q\n");
+        if a < 0xff {
+            let _ = writeln!(out, "/GS{a} gs");
+        }
+        if has_pressure(stroke) {
+            let _ = writeln!(out, "{} {} {} rg", num(r), num(g), num(b));
+            outline_path(&mut out, &stroke_outline(stroke));
+            out.push_str("f\n");
+        } else if let [point] = stroke.points.as_slice() {
+            let _ = writeln!(out, "{} {} {} rg", num(r), num(g), num(b));
+            circle_path(&mut out, point.x as f64, point.y as f64, width / 2.0);
+            out.push_str("f\n");
+        } else if let Some((first, rest)) = stroke.points.split_first() {
+            let _ = writeln!(out, "{} {} {} RG {} w", num(r), num(g), num(b), num(width));
+            let _ = writeln!(out, "{} {} m", num(first.x as f64), num(first.y as f64));
+            for point in rest {
+                let _ = writeln!(out, "{} {} l", num(point.x as f64), num(point.y as f64));
+            }
+            out.push_str("S\n");
+        }
+        out.push_str("Q\n");
+    }
+    out
+}
+
+/// Selects `color` as the fill color, and its alpha for both fill and stroke.
+fn set_fill(out: &mut String, color: Color) {
+    let Color { r, g, b, a } = color;
+    if a < 0xff {
+        let _ = writeln!(out, "/GS{a} gs");
+    }
+    let _ = writeln!(
+        out,
+        "{} {} {} rg",
+        num(r as f64 / 255.0),
+        num(g as f64 / 255.0),
+        num(b as f64 / 255.0)
+    );
+}
+
+fn segments_path(out: &mut String, segments: &[PathSegment]) {
+    for segment in segments {
+        let _ = match *segment {
+            PathSegment::MoveTo(x, y) => writeln!(out, "{} {} m", num(x), num(y)),
+            PathSegment::LineTo(x, y) => writeln!(out, "{} {} l", num(x), num(y)),
+            PathSegment::CubicTo(c1, c2, end) => writeln!(
+                out,
+                "{} {} {} {} {} {} c",
+                num(c1.0),
+                num(c1.1),
+                num(c2.0),
+                num(c2.1),
+                num(end.0),
+                num(end.1)
+            ),
+            PathSegment::Close => writeln!(out, "h"),
+        };
+    }
+}
+
+fn circle_path(out: &mut String, cx: f64, cy: f64, r: f64) {
+    // Four cubic Béziers approximating a circle.
+    let k = r * 0.552_284_75;
+    let _ = writeln!(out, "{} {} m", num(cx + r), num(cy));
+    let _ = writeln!(
+        out,
+        "{} {} {} {} {} {} c",
+        num(cx + r),
+        num(cy + k),
+        num(cx + k),
+        num(cy + r),
+        num(cx),
+        num(cy + r)
+    );
+    let _ = writeln!(
+        out,
+        "{} {} {} {} {} {} c",
+        num(cx - k),
+        num(cy + r),
+        num(cx - r),
+        num(cy + k),
+        num(cx - r),
+        num(cy)
+    );
+    let _ = writeln!(
+        out,
+        "{} {} {} {} {} {} c",
+        num(cx - r),
+        num(cy - k),
+        num(cx - k),
+        num(cy - r),
+        num(cx),
+        num(cy - r)
+    );
+    let _ = writeln!(
+        out,
+        "{} {} {} {} {} {} c",
+        num(cx + k),
+        num(cy - r),
+        num(cx + r),
+        num(cy - k),
+        num(cx + r),
+        num(cy)
+    );
+}
+
+fn outline_path(out: &mut String, parts: &[OutlinePart]) {
+    for part in parts {
+        match part {
+            OutlinePart::Circle { x, y, radius } => {
+                let (start, curves) = circle_curves(*x, *y, *radius);
+                let _ = writeln!(out, "{} {} m", num(start.0), num(start.1));
+                for (c1, c2, end) in curves {
+                    let _ = writeln!(
+                        out,
+                        "{} {} {} {} {} {} c",
+                        num(c1.0),
+                        num(c1.1),
+                        num(c2.0),
+                        num(c2.1),
+                        num(end.0),
+                        num(end.1)
+                    );
+                }
+                out.push_str("h\n");
+            }
+            OutlinePart::Quad(corners) => {
+                for (index, (x, y)) in corners.iter().enumerate() {
+                    let operator = if index == 0 { "m" } else { "l" };
+                    let _ = writeln!(out, "{} {} {operator}", num(*x), num(*y));
+                }
+                out.push_str("h\n");
+            }
+        }
+    }
+}
+
+/// One graphics state per distinct stroke or shape fill alpha, named `/GS<alpha>`.
+fn alpha_states(strokes: &[Stroke]) -> String {
+    let mut alphas = strokes
+        .iter()
+        .flat_map(|stroke| {
+            let fill = stroke.shape.and_then(|shape| shape.fill);
+            std::iter::once(stroke.color.a).chain(fill.map(|fill| fill.a))
+        })
+        .filter(|alpha| *alpha < 0xff)
+        .collect::<Vec<_>>();
+    alphas.sort_unstable();
+    alphas.dedup();
+    let mut out = String::from("<<");
+    for alpha in alphas {
+        let value = num(alpha as f64 / 255.0);
+        let _ = write!(out, " /GS{alpha} << /CA {value} /ca {value} >>");
+    }
+    out.push_str(" >>");
+    out
+}
+
+/// The body of a literal string in the WinAnsi encoding of the standard Helvetica font. Characters
+/// outside Latin-1 have no glyph there and become `?`.
+fn pdf_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(out, "\\{:03o}", c as u32);
+            }
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+fn num(value: f64) -> String {
+    let value = if value.is_finite() { value } else { 0.0 };
+    let text = format!("{value:.4}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[derive(Default)]
+struct PdfWriter {
+    objects: Vec<Option<Vec<u8>>>,
+}
+
+impl PdfWriter {
+    fn reserve(&mut self) -> usize {
+        self.objects.push(None);
+        self.objects.len()
+    }
+
+    fn add(&mut self, body: String) -> usize {
+        self.objects.push(Some(body.into_bytes()));
+        self.objects.len()
+    }
+
+    /// A stream object with binary `data`; `dictionary` is the inside of its dictionary minus
+    /// the length.
+    fn add_stream(&mut self, dictionary: String, data: &[u8]) -> usize {
+        let mut body = format!("<< {dictionary} /Length {} >>\nstream\n", data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.objects.push(Some(body));
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, body: String) {
+        self.objects[id - 1] = Some(body.into_bytes());
+    }
+
+    fn finish(self, root: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (index, body) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+            out.extend_from_slice(body.as_deref().unwrap_or(b"null"));
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(trailer, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            trailer,
+            "trailer\n<< /Size {} /Root {root} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.objects.len() + 1
+        );
+        out.extend_from_slice(trailer.as_bytes());
+        out
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{Point, Stroke, STROKE_UNIT};
+
+/// Width multiplier at zero pressure; full pressure gives `2 - MIN_PRESSURE_FACTOR`, so the
+/// default pen pressure of 0.5 keeps the nominal `Stroke::size`.
+pub const MIN_PRESSURE_FACTOR: f64 = 0.3;
+
+/// `(control1, control2, end)` of a cubic Bézier.
+pub type CubicSegment = ((f64, f64), (f64, f64), (f64, f64));
+
+/// A filled piece of a variable-width stroke outline. Every part winds the same way (negative
+/// angle direction), so the union can be filled with the nonzero rule without holes and
+/// translucent ink is painted once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutlinePart {
+    Circle { x: f64, y: f64, radius: f64 },
+    Quad([(f64, f64); 4]),
+}
+
+/// Drops non-finite pressure and clamps the rest to `0..=1`.
+pub fn normalize_pressure(pressure: Option<f32>) -> Option<f32> {
+    pressure
+        .filter(|pressure| pressure.is_finite())
+        .map(|pressure| pressure.clamp(0.0, 1.0))
+}
+
+pub fn pressure_factor(pressure: Option<f32>) -> f64 {
+    match pressure {
+        Some(pressure) => MIN_PRESSURE_FACTOR + (2.0 - MIN_PRESSURE_FACTOR * 2.0) * pressure as f64,
+        None => 1.0,
+    }
+}
+
+/// Ink width at `point` in board units.
+pub fn point_width(size: f32, point: Point) -> f64 {
+    size as f64 * STROKE_UNIT * pressure_factor(point.pressure)
+}
+
+pub fn max_stroke_width(stroke: &Stroke) -> f64 {
+    stroke
+        .points
+        .iter()
+        .map(|point| point_width(stroke.size, *point))
+        .fold(stroke.size as f64 * STROKE_UNIT, f64::max)
+}
+
+/// Strokes recorded without pressure keep the plain constant-width path everywhere.
+pub fn has_pressure(stroke: &Stroke) -> bool {
+    stroke.points.iter().any(|point| point.pressure.is_some())
+}
+
+/// The segment from `from` to `to` as round ends joined by a quad.
+pub fn segment_outline(from: Point, to: Point, size: f32) -> Vec<OutlinePart> {
+    let from_radius = point_width(size, from) / 2.0;
+    let to_radius = point_width(size, to) / 2.0;
+    let mut parts = vec![OutlinePart::Circle {
+        x: from.x as f64,
+        y: from.y as f64,
+        radius: from_radius,
+    }];
+    if from.x != to.x || from.y != to.y {
+        parts.push(segment_quad(from, from_radius, to, to_radius));
+        parts.push(OutlinePart::Circle {
+            x: to.x as f64,
+            y: to.y as f64,
+            radius: to_radius,
+        });
+    }
+    parts
+}
+
+/// The whole stroke as circles at every point plus a quad per segment.
+pub fn stroke_outline(stroke: &Stroke) -> Vec<OutlinePart> {
+    let radii = stroke
+        .points
+        .iter()
+        .map(|point| point_width(stroke.size, *point) / 2.0)
+        .collect::<Vec<_>>();
+    let mut parts = Vec::with_capacity(stroke.points.len() * 2);
+    for (index, point) in stroke.points.iter().enumerate() {
+        parts.push(OutlinePart::Circle {
+            x: point.x as f64,
+            y: point.y as f64,
+            radius: radii[index],
+        });
+        if index > 0 {
+            let previous = stroke.points[index - 1];
+            if previous.x != point.x || previous.y != point.y {
+                parts.push(segment_quad(
+                    previous,
+                    radii[index - 1],
+                    *point,
+                    radii[index],
+                ));
+            }
+        }
+    }
+    parts
+}
+
+/// Four cubic Béziers tracing a circle from angle 0 in the negative direction, as the start point
+/// and `(control1, control2, end)` triples.
+pub fn circle_curves(x: f64, y: f64, radius: f64) -> ((f64, f64), [CubicSegment; 4]) {
+    let k = radius * 0.552_284_75;
+    let r = radius;
+    (
+        (x + r, y),
+        [
+            ((x + r, y - k), (x + k, y - r), (x, y - r)),
+            ((x - k, y - r), (x - r, y - k), (x - r, y)),
+            ((x - r, y + k), (x - k, y + r), (x, y + r)),
+            ((x + k, y + r), (x + r, y + k), (x + r, y)),
+        ],
+    )
+}
+
+fn segment_quad(from: Point, from_radius: f64, to: Point, to_radius: f64) -> OutlinePart {
+    let dx = to.x as f64 - from.x as f64;
+    let dy = to.y as f64 - from.y as f64;
+    let length = dx.hypot(dy);
+    let (nx, ny) = (-dy / length, dx / length);
+    let (fx, fy) = (from.x as f64, from.y as f64);
+    let (tx, ty) = (to.x as f64, to.y as f64);
+    OutlinePart::Quad([
+        (fx + nx * from_radius, fy + ny * from_radius),
+        (tx + nx * to_radius, ty + ny * to_radius),
+        (tx - nx * to_radius, ty - ny * to_radius),
+        (fx - nx * from_radius, fy - ny * from_radius),
+    ])
+}
@@ -1,14 +1,336 @@
 use bincode::{Decode, Encode};
 
-use crate::Stroke;
+use crate::{
+    Blend, Color, ImageRef, Layer, LayerId, Page, PageId, Point, Shape, Stroke, StrokeId, TextBox,
+};
 
 pub const SESSION_FILE_MAGIC: [u8; 4] = *b"YBSS";
-pub const SESSION_FILE_VERSION: u32 = 1;
+pub const SESSION_FILE_VERSION: u32 = 10;
 const SESSION_HEADER_LEN: usize = SESSION_FILE_MAGIC.len() + std::mem::size_of::<u32>();
 
 #[derive(Clone, Debug, Default, Encode, Decode, serde::Serialize, serde::Deserialize)]
 pub struct SessionFileData {
-    pub strokes: Vec<Stroke>,
+    pub pages: Vec<Page>,
+    /// Bottom first. Empty in files saved before layers, which hold only the base layer.
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+    /// Share tokens of a server-side session; `None` in files saved from the client and in
+    /// sessions created before access control.
+    #[serde(skip)]
+    pub access: Option<SessionAccess>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct SessionAccess {
+    pub owner_secret: String,
+    pub edit_token: String,
+    pub view_token: String,
+}
+
+#[derive(Decode)]
+struct SessionFileDataV1 {
+    strokes: Vec<StrokeV2>,
+}
+
+#[derive(Decode)]
+struct SessionFileDataV2 {
+    strokes: Vec<StrokeV2>,
+    access: Option<SessionAccess>,
+}
+
+#[derive(Decode)]
+struct SessionFileDataV3 {
+    strokes: Vec<StrokeV3>,
+    access: Option<SessionAccess>,
+}
+
+#[derive(Decode)]
+struct SessionFileDataV4 {
+    strokes: Vec<StrokeV4>,
+    access: Option<SessionAccess>,
+}
+
+#[derive(Decode)]
+struct SessionFileDataV5 {
+    strokes: Vec<StrokeV5>,
+    access: Option<SessionAccess>,
+}
+
+#[derive(Decode)]
+struct SessionFileDataV6 {
+    strokes: Vec<StrokeV6>,
+    access: Option<SessionAccess>,
+}
+
+/// Version 7, before pages.
+#[derive(Decode)]
+struct SessionFileDataV7 {
+    strokes: Vec<StrokeV8>,
+    access: Option<SessionAccess>,
+}
+
+/// Version 8, before layers.
+#[derive(Decode)]
+struct SessionFileDataV8 {
+    pages: Vec<PageV8>,
+    access: Option<SessionAccess>,
+}
+
+#[derive(Decode)]
+struct PageV8 {
+    id: PageId,
+    strokes: Vec<StrokeV8>,
+}
+
+impl From<PageV8> for Page {
+    fn from(page: PageV8) -> Self {
+        Page {
+            id: page.id,
+            strokes: page.strokes.into_iter().map(Stroke::from).collect(),
+        }
+    }
+}
+
+/// Version 9, before stroke authors.
+#[derive(Decode)]
+struct SessionFileDataV9 {
+    pages: Vec<PageV9>,
+    layers: Vec<Layer>,
+    access: Option<SessionAccess>,
+}
+
+#[derive(Decode)]
+struct PageV9 {
+    id: PageId,
+    strokes: Vec<StrokeV9>,
+}
+
+impl From<PageV9> for Page {
+    fn from(page: PageV9) -> Self {
+        Page {
+            id: page.id,
+            strokes: page.strokes.into_iter().map(Stroke::from).collect(),
+        }
+    }
+}
+
+/// Files saved before pages hold one board, which becomes the initial page.
+fn single_page(strokes: Vec<Stroke>) -> Vec<Page> {
+    vec![Page {
+        id: PageId::INITIAL,
+        strokes,
+    }]
+}
+
+/// Strokes of version 9, before authors.
+#[derive(Decode)]
+struct StrokeV9 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<Point>,
+    shape: Option<Shape>,
+    text: Option<TextBox>,
+    image: Option<ImageRef>,
+    blend: Blend,
+    layer: LayerId,
+}
+
+impl From<StrokeV9> for Stroke {
+    fn from(stroke: StrokeV9) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke.points,
+            shape: stroke.shape,
+            text: stroke.text,
+            image: stroke.image,
+            blend: stroke.blend,
+            layer: stroke.layer,
+            author: None,
+        }
+    }
+}
+
+/// Strokes of versions 7 and 8, before layers.
+#[derive(Decode)]
+struct StrokeV8 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<Point>,
+    shape: Option<Shape>,
+    text: Option<TextBox>,
+    image: Option<ImageRef>,
+    blend: Blend,
+}
+
+impl From<StrokeV8> for Stroke {
+    fn from(stroke: StrokeV8) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke.points,
+            shape: stroke.shape,
+            text: stroke.text,
+            image: stroke.image,
+            blend: stroke.blend,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
+}
+
+/// Strokes of version 6, before blend styles.
+#[derive(Decode)]
+struct StrokeV6 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<Point>,
+    shape: Option<Shape>,
+    text: Option<TextBox>,
+    image: Option<ImageRef>,
+}
+
+impl From<StrokeV6> for Stroke {
+    fn from(stroke: StrokeV6) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke.points,
+            shape: stroke.shape,
+            text: stroke.text,
+            image: stroke.image,
+            blend: Blend::Normal,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
+}
+
+/// Strokes of version 5, before images.
+#[derive(Decode)]
+struct StrokeV5 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<Point>,
+    shape: Option<Shape>,
+    text: Option<TextBox>,
+}
+
+impl From<StrokeV5> for Stroke {
+    fn from(stroke: StrokeV5) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke.points,
+            shape: stroke.shape,
+            text: stroke.text,
+            image: None,
+            blend: Blend::Normal,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
+}
+
+/// Strokes of version 4, before text boxes.
+#[derive(Decode)]
+struct StrokeV4 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<Point>,
+    shape: Option<Shape>,
+}
+
+impl From<StrokeV4> for Stroke {
+    fn from(stroke: StrokeV4) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke.points,
+            shape: stroke.shape,
+            text: None,
+            image: None,
+            blend: Blend::Normal,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
+}
+
+/// Strokes of version 3, before shapes.
+#[derive(Decode)]
+struct StrokeV3 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<Point>,
+}
+
+impl From<StrokeV3> for Stroke {
+    fn from(stroke: StrokeV3) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke.points,
+            shape: None,
+            text: None,
+            image: None,
+            blend: Blend::Normal,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
+}
+
+/// Strokes of versions 1 and 2, whose points had no pressure.
+#[derive(Decode)]
+struct StrokeV2 {
+    id: StrokeId,
+    color: Color,
+    size: f32,
+    points: Vec<PointV2>,
+}
+
+#[derive(Decode)]
+struct PointV2 {
+    x: f32,
+    y: f32,
+}
+
+impl From<StrokeV2> for Stroke {
+    fn from(stroke: StrokeV2) -> Self {
+        Stroke {
+            id: stroke.id,
+            color: stroke.color,
+            size: stroke.size,
+            points: stroke
+                .points
+                .into_iter()
+                .map(|point| Point {
+                    x: point.x,
+                    y: point.y,
+                    pressure: None,
+                })
+                .collect(),
+            shape: None,
+            text: None,
+            image: None,
+            blend: Blend::Normal,
+            layer: LayerId::BASE,
+            author: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -38,6 +360,69 @@ pub fn decode_session_file(payload: &[u8]) -> Result<SessionFileData, SessionFil
     let body = &payload[SESSION_HEADER_LEN..];
     return match version {
         1 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV1, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: None,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        2 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV2, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        3 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV3, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        4 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV4, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        5 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV5, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        6 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV6, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        7 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV7, _)| SessionFileData {
+                pages: single_page(data.strokes.into_iter().map(Stroke::from).collect()),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        8 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV8, _)| SessionFileData {
+                pages: data.pages.into_iter().map(Page::from).collect(),
+                layers: Vec::new(),
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        9 => bincode::decode_from_slice(body, bincode::config::standard())
+            .map(|(data, _): (SessionFileDataV9, _)| SessionFileData {
+                pages: data.pages.into_iter().map(Page::from).collect(),
+                layers: data.layers,
+                access: data.access,
+            })
+            .map_err(|_| SessionFileDecodeError::InvalidData),
+        10 => bincode::decode_from_slice(body, bincode::config::standard())
             .map(|(data, _)| data)
             .map_err(|_| SessionFileDecodeError::InvalidData),
         _ => Err(SessionFileDecodeError::UnsupportedVersion(version)),
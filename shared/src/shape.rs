@@ -0,0 +1,172 @@
+use crate::image::image_outline;
+use crate::text::text_outline;
+use crate::{Point, Shape, ShapeKind, Stroke, STROKE_UNIT};
+
+/// Samples used when an ellipse has to be approximated by a polyline.
+const ELLIPSE_SEGMENTS: usize = 48;
+// Control point distance of a quarter circle drawn with one cubic Bézier.
+const KAPPA: f64 = 0.552_284_75;
+
+/// A renderer-neutral path command in board coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathSegment {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CubicTo((f64, f64), (f64, f64), (f64, f64)),
+    Close,
+}
+
+/// Control points for a new shape dragged from `start` to `end`: the axis-aligned box for closed
+/// shapes, the two ends for lines and arrows.
+pub fn shape_points(kind: ShapeKind, start: Point, end: Point) -> Vec<Point> {
+    let point = |x: f32, y: f32| Point {
+        x,
+        y,
+        pressure: None,
+    };
+    if kind.is_closed() {
+        vec![
+            point(start.x, start.y),
+            point(end.x, start.y),
+            point(end.x, end.y),
+            point(start.x, end.y),
+        ]
+    } else {
+        vec![point(start.x, start.y), point(end.x, end.y)]
+    }
+}
+
+/// Whether `points` has the right number of control points for `shape`.
+pub fn shape_is_valid(shape: &Shape, points: &[Point]) -> bool {
+    points.len() == shape.kind.point_count()
+}
+
+/// The outline of a shape stroke (empty for freehand strokes). Rectangles and ellipses are closed;
+/// ellipses are the affine image of a circle inscribed in the (possibly rotated or sheared) corner
+/// box, so every `TransformOp` keeps them exact.
+pub fn shape_path(stroke: &Stroke) -> Vec<PathSegment> {
+    let Some(shape) = stroke.shape else {
+        return Vec::new();
+    };
+    let points = stroke.points.as_slice();
+    if !shape_is_valid(&shape, points) {
+        return Vec::new();
+    }
+    let p = |index: usize| (points[index].x as f64, points[index].y as f64);
+    match shape.kind {
+        ShapeKind::Rect => {
+            let mut path = vec![PathSegment::MoveTo(p(0).0, p(0).1)];
+            for index in 1..4 {
+                path.push(PathSegment::LineTo(p(index).0, p(index).1));
+            }
+            path.push(PathSegment::Close);
+            path
+        }
+        ShapeKind::Ellipse => {
+            let (center, u, v) = ellipse_axes(points);
+            let at = |a: f64, b: f64| (center.0 + u.0 * a + v.0 * b, center.1 + u.1 * a + v.1 * b);
+            let start = at(1.0, 0.0);
+            vec![
+                PathSegment::MoveTo(start.0, start.1),
+                PathSegment::CubicTo(at(1.0, KAPPA), at(KAPPA, 1.0), at(0.0, 1.0)),
+                PathSegment::CubicTo(at(-KAPPA, 1.0), at(-1.0, KAPPA), at(-1.0, 0.0)),
+                PathSegment::CubicTo(at(-1.0, -KAPPA), at(-KAPPA, -1.0), at(0.0, -1.0)),
+                PathSegment::CubicTo(at(KAPPA, -1.0), at(1.0, -KAPPA), at(1.0, 0.0)),
+                PathSegment::Close,
+            ]
+        }
+        ShapeKind::Line => vec![
+            PathSegment::MoveTo(p(0).0, p(0).1),
+            PathSegment::LineTo(p(1).0, p(1).1),
+        ],
+        ShapeKind::Arrow => {
+            // Stop the shaft at the head so its round cap does not poke through the tip.
+            let end = arrow_head(points, stroke.size)
+                .map(|[_, left, right]| ((left.0 + right.0) / 2.0, (left.1 + right.1) / 2.0))
+                .unwrap_or(p(1));
+            vec![
+                PathSegment::MoveTo(p(0).0, p(0).1),
+                PathSegment::LineTo(end.0, end.1),
+            ]
+        }
+    }
+}
+
+/// Tip, left and right corner of the filled head of an arrow drawn with `size`.
+pub fn arrow_head(points: &[Point], size: f32) -> Option<[(f64, f64); 3]> {
+    let [start, end] = points else {
+        return None;
+    };
+    let dx = end.x as f64 - start.x as f64;
+    let dy = end.y as f64 - start.y as f64;
+    let length = dx.hypot(dy);
+    if length <= f64::EPSILON {
+        return None;
+    }
+    let (ux, uy) = (dx / length, dy / length);
+    let head = (size as f64 * STROKE_UNIT * 3.0).max(8.0).min(length);
+    let (tip_x, tip_y) = (end.x as f64, end.y as f64);
+    let (base_x, base_y) = (tip_x - ux * head, tip_y - uy * head);
+    let half = head * 0.5;
+    Some([
+        (tip_x, tip_y),
+        (base_x - uy * half, base_y + ux * half),
+        (base_x + uy * half, base_y - ux * half),
+    ])
+}
+
+/// A polyline along a shape's, text box's or image's outline (closed outlines repeat their first
+/// point), for hit-testing and lasso selection. `None` for freehand strokes.
+pub fn outline_points(stroke: &Stroke) -> Option<Vec<Point>> {
+    if stroke.text.is_some() {
+        return text_outline(stroke);
+    }
+    if stroke.image.is_some() {
+        return image_outline(stroke);
+    }
+    let shape = stroke.shape?;
+    if !shape_is_valid(&shape, &stroke.points) {
+        return None;
+    }
+    let point = |(x, y): (f64, f64)| Point {
+        x: x as f32,
+        y: y as f32,
+        pressure: None,
+    };
+    let mut out = match shape.kind {
+        ShapeKind::Rect => {
+            let mut corners = stroke.points.clone();
+            corners.push(stroke.points[0]);
+            corners
+        }
+        ShapeKind::Ellipse => {
+            let (center, u, v) = ellipse_axes(&stroke.points);
+            (0..=ELLIPSE_SEGMENTS)
+                .map(|step| {
+                    let angle = step as f64 / ELLIPSE_SEGMENTS as f64 * std::f64::consts::TAU;
+                    let (cos, sin) = (angle.cos(), angle.sin());
+                    point((
+                        center.0 + u.0 * cos + v.0 * sin,
+                        center.1 + u.1 * cos + v.1 * sin,
+                    ))
+                })
+                .collect()
+        }
+        ShapeKind::Line | ShapeKind::Arrow => stroke.points.clone(),
+    };
+    if shape.kind == ShapeKind::Arrow {
+        if let Some([_, left, right]) = arrow_head(&stroke.points, stroke.size) {
+            out.extend([point(left), stroke.points[1], point(right)]);
+        }
+    }
+    Some(out)
+}
+
+// Center and the two half-axis vectors of the ellipse inscribed in corners 0..4.
+fn ellipse_axes(points: &[Point]) -> ((f64, f64), (f64, f64), (f64, f64)) {
+    let p = |index: usize| (points[index].x as f64, points[index].y as f64);
+    let center = ((p(0).0 + p(2).0) / 2.0, (p(0).1 + p(2).1) / 2.0);
+    let u = ((p(1).0 - p(0).0) / 2.0, (p(1).1 - p(0).1) / 2.0);
+    let v = ((p(3).0 - p(0).0) / 2.0, (p(3).1 - p(0).1) / 2.0);
+    (center, u, v)
+}
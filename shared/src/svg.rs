@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::curve::{curved_stroke, RENDER_CURVE_SPACING};
+use crate::highlight::paint_order;
+use crate::image::image_matrix;
+use crate::pressure::{circle_curves, has_pressure, max_stroke_width, stroke_outline, OutlinePart};
+use crate::shape::{arrow_head, outline_points, shape_path, PathSegment};
+use crate::text::{wrap_lines, TEXT_ASCENT, TEXT_FONT_FAMILY, TEXT_LINE_HEIGHT};
+use crate::{Color, Layer, ShapeKind, Stroke, STROKE_UNIT};
+
+/// An axis-aligned rectangle in board coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SvgRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl SvgRect {
+    pub fn intersects(&self, other: &SvgRect) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+
+    fn padded(self, padding: f64) -> SvgRect {
+        SvgRect {
+            x: self.x - padding,
+            y: self.y - padding,
+            width: (self.width + padding * 2.0).max(1.0),
+            height: (self.height + padding * 2.0).max(1.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SvgOptions {
+    /// CSS color filled behind the strokes; transparent when `None`.
+    pub background: Option<String>,
+    /// Extra margin around the region, in board units.
+    pub padding: f64,
+    /// Area to export; defaults to the ink bounds of the strokes.
+    pub region: Option<SvgRect>,
+    /// Stacking order and visibility; strokes of hidden layers are left out.
+    pub layers: Vec<Layer>,
+    /// `href` of each image asset by hash, usually a `data:` URL so the document stands alone.
+    /// Images without one are left out.
+    pub image_hrefs: HashMap<String, String>,
+}
+
+/// Bounds of the painted area of `strokes`, including half the widest stroke so round caps are
+/// not clipped.
+pub fn ink_bounds<'a>(strokes: impl IntoIterator<Item = &'a Stroke>) -> Option<SvgRect> {
+    let mut min_x = f64::MAX;
+    let mut min_y = f64::MAX;
+    let mut max_x = f64::MIN;
+    let mut max_y = f64::MIN;
+    let mut max_size: f64 = 0.0;
+    for stroke in strokes {
+        max_size = max_size.max(max_stroke_width(stroke));
+        let outline = outline_points(stroke);
+        for point in outline.as_deref().unwrap_or(&stroke.points) {
+            min_x = min_x.min(point.x as f64);
+            min_y = min_y.min(point.y as f64);
+            max_x = max_x.max(point.x as f64);
+            max_y = max_y.max(point.y as f64);
+        }
+    }
+    if min_x == f64::MAX {
+        return None;
+    }
+    let pad = (max_size / 2.0).max(1.0);
+    Some(SvgRect {
+        x: min_x - pad,
+        y: min_y - pad,
+        width: (max_x - min_x + pad * 2.0).max(1.0),
+        height: (max_y - min_y + pad * 2.0).max(1.0),
+    })
+}
+
+/// The `d` attribute of a stroke's polyline, with coordinates written at full `f32` precision.
+pub fn stroke_path_data(stroke: &Stroke) -> String {
+    let mut data = String::new();
+    for (index, point) in stroke.points.iter().enumerate() {
+        let command = if index == 0 { "M" } else { " L" };
+        let _ = write!(data, "{command} {} {}", point.x, point.y);
+    }
+    data
+}
+
+/// `<path>` (plus a dot for single-point strokes) elements for `strokes`, in paint order.
+pub fn stroke_elements(
+    strokes: &[Stroke],
+    layers: &[Layer],
+    image_hrefs: &HashMap<String, String>,
+) -> String {
+    let mut out = String::new();
+    for stroke in paint_order(strokes, layers) {
+        if stroke.points.is_empty() {
+            continue;
+        }
+        if let Some(image) = &stroke.image {
+            if let (Some(href), Some([a, b, c, d, e, f])) =
+                (image_hrefs.get(&image.hash), image_matrix(stroke))
+            {
+                let _ = write!(
+                    out,
+                    "<image href=\"{}\" width=\"1\" height=\"1\" preserveAspectRatio=\"none\" transform=\"matrix({} {} {} {} {} {})\" />",
+                    escape_attribute(href),
+                    a as f32,
+                    b as f32,
+                    c as f32,
+                    d as f32,
+                    e as f32,
+                    f as f32,
+                );
+            }
+            continue;
+        }
+        let (color, opacity) = svg_color(stroke.color);
+        if let Some(text) = &stroke.text {
+            let origin = stroke.points[0];
+            let em = text.font_size as f64;
+            let _ = write!(
+                out,
+                "<text font-family=\"{TEXT_FONT_FAMILY}\" font-size=\"{}\" fill=\"{color}\"{} xml:space=\"preserve\">",
+                text.font_size,
+                opacity_attribute("fill-opacity", opacity),
+            );
+            for (index, line) in wrap_lines(text).iter().enumerate() {
+                let baseline =
+                    origin.y as f64 + (index as f64 * TEXT_LINE_HEIGHT + TEXT_ASCENT) * em;
+                let _ = write!(
+                    out,
+                    "<tspan x=\"{}\" y=\"{}\">{}</tspan>",
+                    origin.x,
+                    baseline as f32,
+                    escape_text(line),
+                );
+            }
+            out.push_str("</text>");
+            continue;
+        }
+        if let Some(shape) = stroke.shape {
+            let width = stroke.size as f64 * STROKE_UNIT;
+            let fill = match shape.fill.filter(|_| shape.kind.is_closed()) {
+                Some(fill) => {
+                    let (fill, fill_opacity) = svg_color(fill);
+                    format!(
+                        "fill=\"{fill}\"{}",
+                        opacity_attribute("fill-opacity", fill_opacity)
+                    )
+                }
+                None => "fill=\"none\"".to_string(),
+            };
+            let _ = write!(
+                out,
+                "<path d=\"{}\" stroke=\"{color}\"{} stroke-width=\"{width}\" {fill} stroke-linecap=\"round\" stroke-linejoin=\"round\" />",
+                shape_path_data(&shape_path(stroke)),
+                opacity_attribute("stroke-opacity", opacity),
+            );
+            if let Some([tip, left, right]) =
+                arrow_head(&stroke.points, stroke.size).filter(|_| shape.kind == ShapeKind::Arrow)
+            {
+                let _ = write!(
+                    out,
+                    "<path d=\"M{} {}L{} {}L{} {}Z\" fill=\"{color}\"{} />",
+                    tip.0 as f32,
+                    tip.1 as f32,
+                    left.0 as f32,
+                    left.1 as f32,
+                    right.0 as f32,
+                    right.1 as f32,
+                    opacity_attribute("fill-opacity", opacity),
+                );
+            }
+            continue;
+        }
+        let stroke = &*curved_stroke(stroke, RENDER_CURVE_SPACING);
+        if has_pressure(stroke) {
+            let _ = write!(
+                out,
+                "<path d=\"{}\" fill=\"{color}\"{} />",
+                outline_path_data(&stroke_outline(stroke)),
+                opacity_attribute("fill-opacity", opacity),
+            );
+            continue;
+        }
+        let width = stroke.size as f64 * STROKE_UNIT;
+        let _ = write!(
+            out,
+            "<path d=\"{}\" stroke=\"{color}\"{} stroke-width=\"{width}\" fill=\"none\" stroke-linecap=\"round\" stroke-linejoin=\"round\" />",
+            stroke_path_data(stroke),
+            opacity_attribute("stroke-opacity", opacity),
+        );
+        if let [point] = stroke.points.as_slice() {
+            let _ = write!(
+                out,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{color}\"{} />",
+                point.x,
+                point.y,
+                width / 2.0,
+                opacity_attribute("fill-opacity", opacity),
+            );
+        }
+    }
+    out
+}
+
+/// The `d` attribute of a pressure outline, filled with the default nonzero rule.
+pub fn outline_path_data(parts: &[OutlinePart]) -> String {
+    let mut data = String::new();
+    for part in parts {
+        match part {
+            OutlinePart::Circle { x, y, radius } => {
+                let (start, curves) = circle_curves(*x, *y, *radius);
+                let _ = write!(data, "M{} {}", start.0 as f32, start.1 as f32);
+                for (c1, c2, end) in curves {
+                    let _ = write!(
+                        data,
+                        "C{} {} {} {} {} {}",
+                        c1.0 as f32,
+                        c1.1 as f32,
+                        c2.0 as f32,
+                        c2.1 as f32,
+                        end.0 as f32,
+                        end.1 as f32
+                    );
+                }
+                data.push('Z');
+            }
+            OutlinePart::Quad(corners) => {
+                for (index, (x, y)) in corners.iter().enumerate() {
+                    let command = if index == 0 { "M" } else { "L" };
+                    let _ = write!(data, "{command}{} {}", *x as f32, *y as f32);
+                }
+                data.push('Z');
+            }
+        }
+    }
+    data
+}
+
+/// The `d` attribute of a shape outline.
+pub fn shape_path_data(segments: &[PathSegment]) -> String {
+    let mut data = String::new();
+    for segment in segments {
+        let _ = match *segment {
+            PathSegment::MoveTo(x, y) => write!(data, "M{} {}", x as f32, y as f32),
+            PathSegment::LineTo(x, y) => write!(data, "L{} {}", x as f32, y as f32),
+            PathSegment::CubicTo(c1, c2, end) => write!(
+                data,
+                "C{} {} {} {} {} {}",
+                c1.0 as f32, c1.1 as f32, c2.0 as f32, c2.1 as f32, end.0 as f32, end.1 as f32
+            ),
+            PathSegment::Close => write!(data, "Z"),
+        };
+    }
+    data
+}
+
+/// A standalone SVG document whose user units are board units.
+pub fn render_svg_document(strokes: &[Stroke], options: &SvgOptions) -> String {
+    let region = options
+        .region
+        .or_else(|| ink_bounds(paint_order(strokes, &options.layers)))
+        .unwrap_or(SvgRect {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        })
+        .padded(options.padding.max(0.0));
+    let visible = strokes
+        .iter()
+        .filter(|stroke| {
+            ink_bounds(std::iter::once(*stroke)).is_some_and(|bounds| bounds.intersects(&region))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = write!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"{x} {y} {w} {h}\">",
+        x = region.x,
+        y = region.y,
+        w = region.width,
+        h = region.height,
+    );
+    if let Some(background) = &options.background {
+        let _ = write!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" />",
+            region.x,
+            region.y,
+            region.width,
+            region.height,
+            escape_attribute(background),
+        );
+    }
+    out.push_str(&stroke_elements(
+        &visible,
+        &options.layers,
+        &options.image_hrefs,
+    ));
+    out.push_str("</svg>\n");
+    out
+}
+
+fn svg_color(color: Color) -> (String, Option<f64>) {
+    let hex = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+    let opacity = (color.a < 0xff).then(|| color.a as f64 / 255.0);
+    (hex, opacity)
+}
+
+fn opacity_attribute(name: &str, opacity: Option<f64>) -> String {
+    match opacity {
+        Some(opacity) => format!(" {name}=\"{opacity:.3}\""),
+        None => String::new(),
+    }
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
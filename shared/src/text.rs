@@ -0,0 +1,135 @@
+use crate::{Point, Stroke, TextBox, MAX_TEXT_CHARS};
+
+/// Distance between baselines, in ems.
+pub const TEXT_LINE_HEIGHT: f64 = 1.25;
+/// Distance from the top of a line to its baseline, in ems.
+pub const TEXT_ASCENT: f64 = 0.92;
+pub const TEXT_FONT_FAMILY: &str = "Helvetica, Arial, sans-serif";
+pub const MIN_FONT_SIZE: f32 = 4.0;
+pub const MAX_FONT_SIZE: f32 = 400.0;
+pub const MAX_WRAP_WIDTH: f32 = 10_000.0;
+/// Wrap width of a new text box, in ems.
+pub const DEFAULT_WRAP_EMS: f32 = 12.0;
+
+/// Approximate advance of `c` in ems. Every renderer wraps with these widths rather than real
+/// font metrics, so line breaks agree between the canvas and the exports.
+pub fn char_advance(c: char) -> f64 {
+    match c {
+        ' ' | 'i' | 'j' | 'l' | 'I' | '.' | ',' | ':' | ';' | '\'' | '!' | '|' => 0.28,
+        'm' | 'w' | 'M' | 'W' => 0.85,
+        'A'..='Z' => 0.68,
+        c if is_wide(c) => 1.0,
+        _ => 0.55,
+    }
+}
+
+/// Approximate width of `line` in board units.
+pub fn line_width(line: &str, font_size: f32) -> f64 {
+    line.chars().map(char_advance).sum::<f64>() * font_size as f64
+}
+
+/// The display lines of `text`: hard breaks at `\n`, soft breaks after spaces and wide (CJK)
+/// characters, and anywhere inside a word longer than the wrap width.
+pub fn wrap_lines(text: &TextBox) -> Vec<String> {
+    let max_width = text.wrap_width as f64;
+    let em = text.font_size as f64;
+    let mut lines = Vec::new();
+    for paragraph in text.content.split('\n') {
+        let mut line = String::new();
+        let mut width = 0.0;
+        // Byte offset in `line` just after the last break opportunity.
+        let mut last_break = None;
+        for c in paragraph.chars() {
+            let advance = char_advance(c) * em;
+            if c != ' ' && !line.is_empty() && width + advance > max_width {
+                let rest = match last_break {
+                    Some(at) if at < line.len() => line.split_off(at),
+                    Some(_) | None => String::new(),
+                };
+                lines.push(line.trim_end().to_string());
+                line = rest;
+                width = line_width(&line, text.font_size);
+                last_break = None;
+            }
+            line.push(c);
+            width += advance;
+            if c == ' ' || is_wide(c) {
+                last_break = Some(line.len());
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Width and height of the laid-out box in board units.
+pub fn text_size(text: &TextBox) -> (f64, f64) {
+    let lines = wrap_lines(text).len().max(1);
+    (
+        text.wrap_width as f64,
+        lines as f64 * text.font_size as f64 * TEXT_LINE_HEIGHT,
+    )
+}
+
+/// The closed outline of a text box, for hit-testing, bounds and lasso selection.
+pub fn text_outline(stroke: &Stroke) -> Option<Vec<Point>> {
+    let text = stroke.text.as_ref()?;
+    let origin = *stroke.points.first()?;
+    let (width, height) = text_size(text);
+    let corner = |dx: f64, dy: f64| Point {
+        x: (origin.x as f64 + dx) as f32,
+        y: (origin.y as f64 + dy) as f32,
+        pressure: None,
+    };
+    Some(vec![
+        corner(0.0, 0.0),
+        corner(width, 0.0),
+        corner(width, height),
+        corner(0.0, height),
+        corner(0.0, 0.0),
+    ])
+}
+
+/// Clamps sizes into range, drops control characters other than newlines and caps the length.
+/// `None` if a size is not finite.
+pub fn sanitize_text(text: TextBox) -> Option<TextBox> {
+    if !text.font_size.is_finite() || !text.wrap_width.is_finite() {
+        return None;
+    }
+    let font_size = text.font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+    Some(TextBox {
+        content: text
+            .content
+            .chars()
+            .map(|c| if c == '\t' { ' ' } else { c })
+            .filter(|c| *c == '\n' || !c.is_control())
+            .take(MAX_TEXT_CHARS)
+            .collect(),
+        font_size,
+        wrap_width: text.wrap_width.clamp(font_size, MAX_WRAP_WIDTH),
+    })
+}
+
+/// Applies the size part of a `TransformOp::Scale` to a text box: the horizontal factor
+/// resizes the wrap width and the vertical one the font. Rotation only moves the box.
+pub fn scale_text(text: &mut TextBox, sx: f64, sy: f64) {
+    let font_size = (text.font_size as f64 * sy.abs()) as f32;
+    text.font_size = font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+    let wrap_width = (text.wrap_width as f64 * sx.abs()) as f32;
+    text.wrap_width = wrap_width.clamp(text.font_size, MAX_WRAP_WIDTH);
+}
+
+fn is_wide(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1100..=0x115F
+            | 0x2E80..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1FAFF
+            | 0x20000..=0x3FFFD
+    )
+}